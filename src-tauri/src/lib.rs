@@ -6,6 +6,7 @@ mod modules;
 // 重新导出所有命令
 use modules::*;
 use modules::database::DatabaseState;
+use modules::scheduler::SyncScheduler;
 use tauri::Manager;
 
 // 简单的问候命令，保留作为示例
@@ -21,24 +22,26 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .manage(DatabaseState::default())
+        .manage(SyncScheduler::default())
         .setup(|app| {
-            // 在启动时就设置窗口层级
-            let window = app.get_webview_window("main").unwrap();
-
-            // 设置窗口层级（平台特定）
-            #[cfg(target_os = "windows")]
-            {
-                modules::tray::setup_window_layer(&window);
-            }
-            
-            #[cfg(target_os = "macos")]
+            // 在启动时就设置窗口层级（平台特定）
+            #[cfg(any(target_os = "windows", target_os = "macos"))]
             {
+                let window = app.get_webview_window("main").unwrap();
                 modules::tray::setup_window_layer(&window);
             }
 
             // 设置系统托盘
             modules::tray::setup_tray(app)?;
 
+            // 按设置启动后台自动同步，窗口隐藏在托盘时也会继续同步
+            if let Ok(settings) = tauri::async_runtime::block_on(load_app_settings()) {
+                app.state::<SyncScheduler>().apply(app.handle(), settings.auto_sync_interval());
+            }
+
+            // 启动后台自动归档任务
+            modules::archive::spawn_archive_job(app.handle());
+
             // 窗口初始为隐藏状态，等待前端配置完成后显示
             // 前端会通过 show_main_window 命令来显示窗口
 
@@ -53,29 +56,68 @@ pub fn run() {
             hide_main_window,
             // 应用设置命令
             save_app_settings,
+            update_app_settings,
             load_app_settings,
+            list_profiles,
+            create_profile,
+            switch_profile,
+            delete_profile,
             // 数据持久化命令
             save_todos,
             load_todos,
+            upsert_todo,
+            add_todo,
+            update_todo,
+            toggle_todo,
+            move_todo,
+            delete_todo,
             save_archived_todos,
             load_archived_todos,
             clear_archived_todos,
+            // 撤销历史命令
+            undo,
+            redo,
+            get_history_status,
+            // 归档命令
+            list_archives,
+            unarchive_todo,
+            search_archive,
+            // 备份命令
+            list_backups,
+            restore_backup,
+            // 导出与导入命令
+            export_bundle,
+            inspect_bundle,
+            import_bundle,
             // 数据库同步命令
             save_database_config,
             load_database_config,
             test_database_connection,
             connect_database,
             check_and_initialize_tables,
+            get_remote_schema_version,
             start_database_sync,
+            force_push_todos,
+            preview_database_sync,
             // 逻辑删除命令
             delete_todo_logically,
             restore_todo,
             restore_todo_safe,
             get_deleted_todos,
+            purge_deleted_todos,
+            // 离线队列命令
+            get_pending_operations,
+            discard_failed_operation,
+            retry_failed_operation,
+            // 操作日志命令
+            get_todo_history,
             // 数据清理命令
             cleanup_duplicate_data,
             // 数据比较命令
-            get_remote_data_for_comparison
+            get_remote_data_for_comparison,
+            // 同步冲突命令
+            list_sync_conflicts,
+            resolve_sync_conflict
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,72 +1,847 @@
-use serde_json::Value;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use chrono;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::{Mutex, MutexGuard};
 
-/// 保存应用设置
+use crate::modules::bundle::ImportMode;
+use crate::modules::scheduler::{parse_auto_sync, SyncScheduler};
+use crate::modules::migration::{load_versioned, to_versioned_value, FileKind, FORMAT_VERSION_KEY};
+use crate::modules::storage::{backup_file, write_atomic};
+use crate::modules::store;
+use crate::modules::tray::refresh_tray_menu;
+
+/// 修改 profiles.json 或切换配置方案时加锁
+static PROFILE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// 读取-修改-写入设置时加锁，避免并发的修改（前端、托盘、同步）互相覆盖；
+/// 与 `PROFILE_LOCK` 同时持有时先取 `PROFILE_LOCK`
+static SETTINGS_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// 没有创建过配置方案时，当前设置所属的方案
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// 配置方案名称的最大长度（字符）
+const MAX_PROFILE_NAME: usize = 40;
+
+/// 天数类设置（archiveDays、trashRetentionDays）的上限
+const MAX_DAYS: u64 = 36500;
+
+/// 窗口圆角与边框宽度的上限（像素）
+const MAX_PIXELS: u64 = 100;
+
+/// 自动同步周期的上限
+const MAX_AUTO_SYNC: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// 只属于本机、从不同步的设置字段，嵌套字段的路径用 `.` 分隔
+///
+/// 窗口位置与尺寸随屏幕而定，一台设备的布局不应覆盖到另一台设备上。
+pub const DEVICE_LOCAL_SETTINGS: &[&str] = &[
+    FORMAT_VERSION_KEY,
+    "windowConfig.x",
+    "windowConfig.y",
+    "windowConfig.width",
+    "windowConfig.height",
+];
+
+/// 待办颜色设置
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Colors {
+    pub normal: String,
+    pub warning: String,
+    pub urgent: String,
+    pub completed: String,
+    pub background: String,
+    pub border: String,
+    /// 较新版本添加的其他颜色，原样保留
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors {
+            normal: "#4f2937".to_string(),
+            warning: "#f59e0b".to_string(),
+            urgent: "#ef4444".to_string(),
+            completed: "#f5dbd6".to_string(),
+            background: "#60a5fa".to_string(),
+            border: "#29cdcd".to_string(),
+            extra: Map::new(),
+        }
+    }
+}
+
+/// 窗口外观设置（窗口位置与尺寸保存在 window.json 中）
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WindowStyle {
+    pub border_radius: u32,
+    pub border_color: String,
+    pub border_width: u32,
+    /// 其他窗口设置，原样保留
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Default for WindowStyle {
+    fn default() -> Self {
+        WindowStyle {
+            border_radius: 8,
+            border_color: "#3b82f6".to_string(),
+            border_width: 1,
+            extra: Map::new(),
+        }
+    }
+}
+
+/// 应用设置，缺少的字段使用默认值
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AppSettings {
+    pub locale: String,
+    pub is_transparent: bool,
+    pub window_config: WindowStyle,
+    pub colors: Colors,
+    /// 完成多少天后自动归档，0 为不自动归档
+    pub archive_days: u64,
+    /// 自动同步周期（"15m"、"1h"），"0" 为不自动同步
+    pub auto_sync: String,
+    /// 回收站保留天数，0 为不自动清除
+    pub trash_retention_days: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_update: Option<String>,
+    /// 前端或较新版本添加的其他字段，原样保留
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            locale: "zh-cn".to_string(),
+            is_transparent: false,
+            window_config: WindowStyle::default(),
+            colors: Colors::default(),
+            archive_days: 30,
+            auto_sync: "0".to_string(),
+            trash_retention_days: 0,
+            last_update: None,
+            extra: Map::new(),
+        }
+    }
+}
+
+/// `settings-changed` 事件的内容
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsChanged {
+    /// 值有变化的顶层字段
+    pub keys: Vec<String>,
+    pub settings: AppSettings,
+}
+
+/// 校验失败的设置字段，`field` 为字段路径（如 `colors.normal`）
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// 保存设置失败的原因
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum SettingsError {
+    /// 设置内容无效，列出每个无效的字段
+    Invalid { errors: Vec<FieldError> },
+    /// 读写本地数据失败
+    Storage { message: String },
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Invalid { errors } => {
+                let fields: Vec<String> = errors
+                    .iter()
+                    .map(|error| format!("{} {}", error.field, error.message))
+                    .collect();
+                write!(f, "Invalid settings: {}", fields.join("; "))
+            }
+            SettingsError::Storage { message } => f.write_str(message),
+        }
+    }
+}
+
+impl From<String> for SettingsError {
+    fn from(message: String) -> Self {
+        SettingsError::Storage { message }
+    }
+}
+
+type FieldCheck = fn(&Value) -> Result<(), String>;
+
+/// 各设置字段的校验规则，嵌套字段的路径用 `.` 分隔
+const FIELD_CHECKS: &[(&str, FieldCheck)] = &[
+    ("locale", check_locale),
+    ("isTransparent", check_bool),
+    ("archiveDays", check_days),
+    ("autoSync", check_auto_sync),
+    ("trashRetentionDays", check_days),
+    ("lastUpdate", check_timestamp),
+    ("windowConfig", check_object),
+    ("windowConfig.borderRadius", check_pixels),
+    ("windowConfig.borderColor", check_color),
+    ("windowConfig.borderWidth", check_pixels),
+    ("colors", check_object),
+    ("colors.normal", check_color),
+    ("colors.warning", check_color),
+    ("colors.urgent", check_color),
+    ("colors.completed", check_color),
+    ("colors.background", check_color),
+    ("colors.border", check_color),
+];
+
+fn check_locale(value: &Value) -> Result<(), String> {
+    let valid = value.as_str().is_some_and(|locale| {
+        let mut parts = locale.split('-');
+        parts.next().is_some_and(|language| (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic()))
+            && parts.all(|part| !part.is_empty() && part.len() <= 8 && part.chars().all(|c| c.is_ascii_alphanumeric()))
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err("must be a language tag such as \"zh-cn\" or \"en\"".to_string())
+    }
+}
+
+fn check_bool(value: &Value) -> Result<(), String> {
+    value.as_bool().map(|_| ()).ok_or_else(|| "must be true or false".to_string())
+}
+
+fn check_object(value: &Value) -> Result<(), String> {
+    value.as_object().map(|_| ()).ok_or_else(|| "must be an object".to_string())
+}
+
+fn check_days(value: &Value) -> Result<(), String> {
+    match value.as_u64() {
+        Some(days) if days <= MAX_DAYS => Ok(()),
+        _ => Err(format!("must be a whole number of days between 0 and {}", MAX_DAYS)),
+    }
+}
+
+fn check_pixels(value: &Value) -> Result<(), String> {
+    match value.as_u64() {
+        Some(pixels) if pixels <= MAX_PIXELS => Ok(()),
+        _ => Err(format!("must be a whole number of pixels between 0 and {}", MAX_PIXELS)),
+    }
+}
+
+fn check_auto_sync(value: &Value) -> Result<(), String> {
+    let valid = value.as_str().is_some_and(|period| {
+        period.trim() == "0" || parse_auto_sync(period).is_some_and(|interval| interval <= MAX_AUTO_SYNC)
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err("must be \"0\" or a period such as \"15m\" or \"1h\" of at most 24 hours".to_string())
+    }
+}
+
+fn check_timestamp(value: &Value) -> Result<(), String> {
+    value.as_str()
+        .and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok())
+        .map(|_| ())
+        .ok_or_else(|| "must be an RFC 3339 timestamp".to_string())
+}
+
+/// 颜色须为十六进制（#rgb、#rgba、#rrggbb、#rrggbbaa）或颜色选择器输出的
+/// rgb()、rgba()、hsl()、hsla()、hsv()、hsva() 形式
+fn check_color(value: &Value) -> Result<(), String> {
+    let valid = value.as_str().map(str::trim).is_some_and(|color| {
+        if let Some(hex) = color.strip_prefix('#') {
+            return [3, 4, 6, 8].contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit());
+        }
+        let Some((name, args)) = color.strip_suffix(')').and_then(|color| color.split_once('(')) else {
+            return false;
+        };
+        let args: Vec<&str> = args.split(',').map(str::trim).collect();
+        let count = match name.trim().to_ascii_lowercase().as_str() {
+            "rgb" | "hsl" | "hsv" => 3,
+            "rgba" | "hsla" | "hsva" => 4,
+            _ => return false,
+        };
+        args.len() == count
+            && args.iter().all(|arg| arg.strip_suffix('%').unwrap_or(arg).parse::<f64>().is_ok_and(f64::is_finite))
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err("must be a colour such as \"#3b82f6\" or \"rgba(59, 130, 246, 0.5)\"".to_string())
+    }
+}
+
+/// 按路径读取字段，中间的字段不是对象时返回 None（由上一级的规则报告）
+fn field_at<'a>(settings: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    match path.split_once('.') {
+        Some((parent, child)) => settings.get(parent)?.as_object()?.get(child),
+        None => settings.get(path),
+    }
+}
+
+/// 按 `FIELD_CHECKS` 校验设置中出现的字段
+fn check_fields(settings: &Map<String, Value>) -> Vec<FieldError> {
+    FIELD_CHECKS
+        .iter()
+        .filter_map(|(path, check)| {
+            let value = field_at(settings, path)?;
+            check(value).err().map(|message| FieldError { field: path.to_string(), message })
+        })
+        .collect()
+}
+
+impl AppSettings {
+    /// 解析前端提交的设置，任一字段无效时返回所有无效字段
+    pub fn parse(settings: Value) -> Result<Self, Vec<FieldError>> {
+        let Value::Object(settings) = settings else {
+            return Err(vec![FieldError { field: String::new(), message: "settings must be a JSON object".to_string() }]);
+        };
+
+        let errors = check_fields(&settings);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        serde_json::from_value(Value::Object(settings))
+            .map_err(|e| vec![FieldError { field: String::new(), message: e.to_string() }])
+    }
+
+    /// 解析已保存或从远程下载的设置，无效的字段改用默认值
+    pub fn parse_lenient(mut settings: Map<String, Value>) -> Self {
+        for error in check_fields(&settings) {
+            match error.field.split_once('.') {
+                Some((parent, child)) => {
+                    if let Some(parent) = settings.get_mut(parent).and_then(Value::as_object_mut) {
+                        parent.remove(child);
+                    }
+                }
+                None => {
+                    settings.remove(&error.field);
+                }
+            }
+        }
+        serde_json::from_value(Value::Object(settings)).unwrap_or_default()
+    }
+
+    /// 按保存时的 JSON 形式展开为顶层字段
+    pub fn to_map(&self) -> Map<String, Value> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(settings)) => settings,
+            _ => Map::new(),
+        }
+    }
+
+    /// 参与同步的设置，不含只属于本机的字段
+    pub fn to_sync_map(&self) -> Map<String, Value> {
+        let mut settings = self.to_map();
+        for path in DEVICE_LOCAL_SETTINGS {
+            match path.split_once('.') {
+                Some((parent, child)) => {
+                    if let Some(parent) = settings.get_mut(parent).and_then(Value::as_object_mut) {
+                        parent.remove(child);
+                    }
+                }
+                None => {
+                    settings.remove(*path);
+                }
+            }
+        }
+        settings
+    }
+
+    /// 用同步下载的字段替换当前设置中的对应字段，只属于本机的字段保留本机的值
+    pub fn with_synced_fields(&self, fields: Map<String, Value>) -> Self {
+        let local = self.to_map();
+        let mut settings = local.clone();
+        settings.extend(fields);
+        for path in DEVICE_LOCAL_SETTINGS {
+            let Some((parent, child)) = path.split_once('.') else {
+                continue;
+            };
+            let local_value = local.get(parent).and_then(|value| value.get(child)).cloned();
+            if let Some(parent) = settings.get_mut(parent).and_then(Value::as_object_mut) {
+                match local_value {
+                    Some(value) => parent.insert(child.to_string(), value),
+                    None => parent.remove(child),
+                };
+            }
+        }
+        AppSettings::parse_lenient(settings)
+    }
+
+    /// 自动同步周期，不自动同步时为 None
+    pub fn auto_sync_interval(&self) -> Option<Duration> {
+        parse_auto_sync(&self.auto_sync)
+    }
+}
+
+/// 按 JSON merge patch（RFC 7396）合并：值为 null 时删除字段，对象逐字段合并，其他值整体替换
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// 获取设置锁，从读取当前设置到写入修改后的设置期间持有
+pub async fn lock_settings() -> MutexGuard<'static, ()> {
+    SETTINGS_LOCK.lock().await
+}
+
+/// 写入设置（调用方持有 `lock_settings`），有变化时按 autoSync 调整后台自动同步，
+/// 并向所有窗口广播 `settings-changed`
+pub async fn apply_app_settings(app: &AppHandle, settings: AppSettings) -> Result<AppSettings, String> {
+    let current_time = chrono::Utc::now().to_rfc3339();
+    apply_app_settings_at(app, settings, &current_time, &HashMap::new()).await
+}
+
+/// 按给定的修改时间写入并应用设置（调用方持有 `lock_settings`），时间的含义同 `write_app_settings_at`
+pub async fn apply_app_settings_at(
+    app: &AppHandle,
+    mut settings: AppSettings,
+    last_update: &str,
+    field_times: &HashMap<String, String>,
+) -> Result<AppSettings, String> {
+    let keys = write_app_settings_at(&mut settings, last_update, field_times).await?;
+    if keys.is_empty() {
+        return Ok(settings);
+    }
+
+    app.state::<SyncScheduler>().apply(app, settings.auto_sync_interval());
+    let _ = app.emit("settings-changed", SettingsChanged { keys, settings: settings.clone() });
+    Ok(settings)
+}
+
+/// 保存应用设置，autoSync 周期变化时重启后台自动同步
+///
+/// 设置无效时返回 `SettingsError::Invalid`，列出每个无效字段及原因，不写入任何内容。
 #[tauri::command]
-pub fn save_app_settings(settings: Value) -> Result<(), String> {
+pub async fn save_app_settings(app: AppHandle, settings: Value) -> Result<(), SettingsError> {
+    let settings = AppSettings::parse(settings).map_err(|errors| SettingsError::Invalid { errors })?;
+    let _guard = lock_settings().await;
+    apply_app_settings(&app, settings).await?;
+    Ok(())
+}
+
+/// 按 JSON merge patch 修改部分设置，返回修改后的完整设置
+///
+/// 只有值改变的字段记录修改时间，lastUpdate 由程序维护，不能通过补丁修改。
+#[tauri::command]
+pub async fn update_app_settings(app: AppHandle, patch: Value) -> Result<AppSettings, SettingsError> {
+    let invalid = |field: &str, message: &str| SettingsError::Invalid {
+        errors: vec![FieldError { field: field.to_string(), message: message.to_string() }],
+    };
+    if !patch.is_object() {
+        return Err(invalid("", "patch must be a JSON object"));
+    }
+    if patch.get("lastUpdate").is_some() {
+        return Err(invalid("lastUpdate", "is maintained automatically and cannot be patched"));
+    }
+
+    let _guard = lock_settings().await;
+    let mut settings = Value::Object(load_app_settings().await?.to_map());
+    merge_patch(&mut settings, &patch);
+    let settings = AppSettings::parse(settings).map_err(|errors| SettingsError::Invalid { errors })?;
+
+    Ok(apply_app_settings(&app, settings).await?)
+}
+
+/// 按给定的时间写入设置，返回值有变化的顶层字段
+///
+/// 没有变化时不写入；有变化时 lastUpdate 记为 `last_update`，变化的字段记录 `field_times` 中
+/// 各自的修改时间（同步下载时为远程记录的时间），没有记录的字段记为 `last_update`。
+async fn write_app_settings_at(
+    settings: &mut AppSettings,
+    last_update: &str,
+    field_times: &HashMap<String, String>,
+) -> Result<Vec<String>, String> {
+    let previous = store::read_settings().await?.unwrap_or_default();
+    let next = settings.to_map();
+
+    let mut keys: Vec<String> = next
+        .keys()
+        .chain(previous.keys())
+        .filter(|key| !matches!(key.as_str(), "lastUpdate" | FORMAT_VERSION_KEY))
+        .filter(|key| previous.get(*key) != next.get(*key))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    if keys.is_empty() {
+        return Ok(keys);
+    }
+
+    settings.last_update = Some(last_update.to_string());
+    store::write_settings(&settings.to_map(), last_update, field_times).await?;
+    Ok(keys)
+}
+
+/// 各设置字段最后一次修改的时间，没有记录的字段以 lastUpdate 为准
+pub async fn load_settings_times() -> Result<HashMap<String, String>, String> {
+    store::read_settings_times().await
+}
+
+/// 加载当前配置方案的应用设置，没有保存过时返回默认设置
+#[tauri::command]
+pub async fn load_app_settings() -> Result<AppSettings, String> {
+    Ok(store::read_settings().await?
+        .map(AppSettings::parse_lenient)
+        .unwrap_or_default())
+}
+
+/// profiles.json 的内容：各配置方案的完整设置以及当前使用的方案
+///
+/// 当前方案的设置以本地数据库中的为准，这里的副本在切换离开该方案时更新。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ProfileFile {
+    active: String,
+    #[serde(default)]
+    profiles: BTreeMap<String, AppSettings>,
+}
+
+impl Default for ProfileFile {
+    fn default() -> Self {
+        ProfileFile {
+            active: DEFAULT_PROFILE.to_string(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+impl ProfileFile {
+    fn contains(&self, name: &str) -> bool {
+        self.active == name || self.profiles.contains_key(name)
+    }
+
+    fn to_list(&self) -> ProfileList {
+        let mut profiles: Vec<String> = self.profiles.keys().cloned().collect();
+        if !self.profiles.contains_key(&self.active) {
+            profiles.push(self.active.clone());
+            profiles.sort();
+        }
+        ProfileList { active: self.active.clone(), profiles }
+    }
+}
+
+/// 配置方案列表，随 `profiles-changed` 事件发出
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileList {
+    /// 当前使用的方案
+    pub active: String,
+    /// 所有方案的名称，按名称排序
+    pub profiles: Vec<String>,
+}
+
+fn profiles_path() -> Result<PathBuf, String> {
     let config_dir = dirs::config_dir()
         .ok_or("Failed to get config directory")?
         .join("Ton")
         .join("config");
-
     std::fs::create_dir_all(&config_dir)
         .map_err(|e| format!("Failed to create config directory: {}", e))?;
+    Ok(config_dir.join("profiles.json"))
+}
 
-    let config_file = config_dir.join("settings.json");
-    
-    // 添加 lastUpdate 字段
-    let mut settings_with_timestamp = settings;
-    if let Some(settings_obj) = settings_with_timestamp.as_object_mut() {
-        let current_time = chrono::Utc::now().to_rfc3339();
-        settings_obj.insert("lastUpdate".to_string(), serde_json::Value::String(current_time));
+/// 读取 profiles.json，没有时只有当前设置所属的默认方案
+fn read_profiles() -> Result<ProfileFile, String> {
+    match load_versioned(FileKind::Profiles, &profiles_path()?)? {
+        Some(content) => serde_json::from_value(content)
+            .map_err(|e| format!("Failed to parse profiles.json: {}", e)),
+        None => Ok(ProfileFile::default()),
     }
-    
-    let json_str = serde_json::to_string_pretty(&settings_with_timestamp)
-        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+}
 
-    std::fs::write(config_file, json_str)
-        .map_err(|e| format!("Failed to write settings file: {}", e))?;
+fn write_profiles(file: &ProfileFile) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(&to_versioned_value(FileKind::Profiles, file)?)
+        .map_err(|e| format!("Failed to serialize profiles.json: {}", e))?;
+    let path = profiles_path()?;
+    backup_file(&path, false)?;
+    write_atomic(&path, json.as_bytes())
+}
 
-    Ok(())
+/// 更新托盘菜单并通知前端方案列表的变化
+///
+/// 方案已经保存，托盘菜单更新失败不影响结果，发出 `profile-failed` 事件（内容为错误信息）。
+fn notify_profiles_changed(app: &AppHandle, list: &ProfileList) {
+    if let Err(e) = refresh_tray_menu(app) {
+        let _ = app.emit("profile-failed", format!("Failed to refresh tray menu: {}", e));
+    }
+    let _ = app.emit("profiles-changed", list.clone());
+}
+
+fn check_profile_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_PROFILE_NAME {
+        return Err(format!("Profile name must be between 1 and {} characters", MAX_PROFILE_NAME));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("Profile name must not contain control characters".to_string());
+    }
+    Ok(name.to_string())
 }
 
-/// 加载应用设置
+/// 当前的配置方案列表（供托盘菜单使用）
+pub fn profile_list() -> Result<ProfileList, String> {
+    Ok(read_profiles()?.to_list())
+}
+
+/// 列出所有配置方案以及当前使用的方案
 #[tauri::command]
-pub fn load_app_settings() -> Result<Value, String> {
-    let config_dir = dirs::config_dir()
-        .ok_or("Failed to get config directory")?
-        .join("Ton")
-        .join("config");
+pub async fn list_profiles() -> Result<ProfileList, String> {
+    let _guard = PROFILE_LOCK.lock().await;
+    profile_list()
+}
+
+/// 以当前设置为起点新建配置方案，不切换到新方案
+#[tauri::command]
+pub async fn create_profile(app: AppHandle, name: String) -> Result<ProfileList, String> {
+    let _guard = PROFILE_LOCK.lock().await;
+    let name = check_profile_name(&name)?;
+    let mut file = read_profiles()?;
+    if file.contains(&name) {
+        return Err(format!("Profile {:?} already exists", name));
+    }
+
+    file.profiles.insert(name, load_app_settings().await?);
+    write_profiles(&file)?;
+
+    let list = file.to_list();
+    notify_profiles_changed(&app, &list);
+    Ok(list)
+}
+
+/// 切换到指定的配置方案，返回切换后的设置
+///
+/// 当前方案的设置先保存回 profiles.json；只属于本机的字段（窗口位置与尺寸）不随方案变化。
+#[tauri::command]
+pub async fn switch_profile(app: AppHandle, name: String) -> Result<AppSettings, String> {
+    let _guard = PROFILE_LOCK.lock().await;
+    let _settings_guard = lock_settings().await;
+    let mut file = read_profiles()?;
+    let current = load_app_settings().await?;
+    if file.active == name {
+        return Ok(current);
+    }
+    let target = file.profiles.get(&name)
+        .ok_or_else(|| format!("Profile {:?} does not exist", name))?
+        .to_sync_map();
 
-    let config_file = config_dir.join("settings.json");
-
-    if !config_file.exists() {
-        // 返回默认设置（不包含宽高）
-        return Ok(serde_json::json!({
-            "locale": "zh-cn",
-            "isTransparent": false,
-            "windowConfig": {
-              "borderRadius": 8,
-              "borderColor": "#3b82f6",
-              "borderWidth": 1,
-            },
-            "colors": {
-              "normal": "#4f2937",
-              "warning": "#f59e0b",
-              "urgent": "#ef4444",
-              "completed": "#f5dbd6",
-              "background": "#60a5fa",
-              "border": "#29cdcd",
-            },
-            "archiveDays": 30
-        }));
-    }
-
-    let json_str = std::fs::read_to_string(config_file)
-        .map_err(|e| format!("Failed to read settings file: {}", e))?;
-
-    let settings: Value = serde_json::from_str(&json_str)
-        .map_err(|e| format!("Failed to parse settings file: {}", e))?;
+    let original = file.clone();
+    let settings = current.with_synced_fields(target);
+    let previous = std::mem::replace(&mut file.active, name);
+    file.profiles.insert(previous, current);
+    let settings = write_profiles_and_apply(&app, &original, &file, settings).await?;
 
+    notify_profiles_changed(&app, &file.to_list());
     Ok(settings)
 }
+
+/// 写入 profiles.json 并应用当前方案的设置（调用方持有两把锁）
+///
+/// 先写入 profiles.json，应用设置失败时再改回 `original`，两者不会不一致。
+async fn write_profiles_and_apply(
+    app: &AppHandle,
+    original: &ProfileFile,
+    file: &ProfileFile,
+    settings: AppSettings,
+) -> Result<AppSettings, String> {
+    write_profiles(file)?;
+    match apply_app_settings(app, settings).await {
+        Ok(settings) => Ok(settings),
+        Err(e) => Err(match write_profiles(original) {
+            Ok(()) => e,
+            Err(restore) => format!("{}; failed to restore profiles.json: {}", e, restore),
+        }),
+    }
+}
+
+/// 删除配置方案，不能删除当前使用的方案
+#[tauri::command]
+pub async fn delete_profile(app: AppHandle, name: String) -> Result<ProfileList, String> {
+    let _guard = PROFILE_LOCK.lock().await;
+    let mut file = read_profiles()?;
+    if file.active == name {
+        return Err("Cannot delete the active profile; switch to another profile first".to_string());
+    }
+    if file.profiles.remove(&name).is_none() {
+        return Err(format!("Profile {:?} does not exist", name));
+    }
+    write_profiles(&file)?;
+
+    let list = file.to_list();
+    notify_profiles_changed(&app, &list);
+    Ok(list)
+}
+
+/// profiles.json 的当前内容（带格式版本），供导出使用
+pub async fn export_profiles() -> Result<Value, String> {
+    let _guard = PROFILE_LOCK.lock().await;
+    to_versioned_value(FileKind::Profiles, &read_profiles()?)
+}
+
+/// 导入 profiles.json 的内容（已迁移到当前格式），返回导入的方案数量
+///
+/// 替换时使用导入的方案列表与当前方案，并与 `switch_profile` 一样应用导入的当前方案的设置
+/// （导入的内容中没有该方案的设置时沿用当前设置）；合并时只添加名称不重复的方案，当前方案不变。
+pub async fn import_profiles(app: &AppHandle, content: Value, mode: ImportMode) -> Result<usize, String> {
+    let imported: ProfileFile = serde_json::from_value(content)
+        .map_err(|e| format!("Failed to parse profiles.json: {}", e))?;
+
+    let _guard = PROFILE_LOCK.lock().await;
+    let (file, count) = match mode {
+        ImportMode::Replace => {
+            let count = imported.to_list().profiles.len();
+            // 与导入待办一样，替换前强制备份当前的方案
+            backup_file(&profiles_path()?, true)?;
+            match imported.profiles.get(&imported.active).map(AppSettings::to_sync_map) {
+                Some(target) => {
+                    let _settings_guard = lock_settings().await;
+                    let settings = load_app_settings().await?.with_synced_fields(target);
+                    write_profiles_and_apply(app, &read_profiles()?, &imported, settings).await?;
+                }
+                None => write_profiles(&imported)?,
+            }
+            (imported, count)
+        }
+        ImportMode::Merge => {
+            let mut file = read_profiles()?;
+            let mut count = 0;
+            for (name, settings) in imported.profiles {
+                if !file.contains(&name) {
+                    file.profiles.insert(name, settings);
+                    count += 1;
+                }
+            }
+            write_profiles(&file)?;
+            (file, count)
+        }
+    };
+
+    notify_profiles_changed(app, &file.to_list());
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|error| error.field.as_str()).collect()
+    }
+
+    #[test]
+    fn parse_fills_defaults_and_keeps_unknown_fields() {
+        let settings = AppSettings::parse(json!({ "locale": "en", "customFlag": true })).unwrap();
+        assert_eq!(settings.locale, "en");
+        assert_eq!(settings.archive_days, AppSettings::default().archive_days);
+        assert_eq!(settings.colors, Colors::default());
+        assert_eq!(settings.extra.get("customFlag"), Some(&json!(true)));
+    }
+
+    #[test]
+    fn parse_reports_every_invalid_field() {
+        let errors = AppSettings::parse(json!({
+            "locale": "not a locale",
+            "archiveDays": -1,
+            "autoSync": "48h",
+            "colors": { "normal": "red", "urgent": "#ef4444" },
+            "windowConfig": { "borderWidth": 500 },
+        }))
+        .unwrap_err();
+        assert_eq!(fields(&errors), ["locale", "archiveDays", "autoSync", "windowConfig.borderWidth", "colors.normal"]);
+
+        let errors = AppSettings::parse(json!(["not", "an", "object"])).unwrap_err();
+        assert_eq!(fields(&errors), [""]);
+    }
+
+    #[test]
+    fn parse_lenient_replaces_invalid_fields_with_defaults() {
+        let settings = AppSettings::parse_lenient(json!({
+            "locale": "en",
+            "trashRetentionDays": "forever",
+            "colors": { "normal": "red", "urgent": "#000" },
+        }).as_object().unwrap().clone());
+        assert_eq!(settings.locale, "en");
+        assert_eq!(settings.trash_retention_days, AppSettings::default().trash_retention_days);
+        assert_eq!(settings.colors.normal, Colors::default().normal);
+        assert_eq!(settings.colors.urgent, "#000");
+    }
+
+    #[test]
+    fn field_checks_accept_documented_formats() {
+        for color in ["#fff", "#3b82f6cc", "rgba(59, 130, 246, 0.5)", "hsl(210, 50%, 40%)", "hsva(0, 0%, 100%, 1)"] {
+            assert!(check_color(&json!(color)).is_ok(), "{}", color);
+        }
+        for color in ["#ggg", "blue", "rgb(1, 2)", "rgb(1, 2, NaN)", "cmyk(0, 0, 0, 0)"] {
+            assert!(check_color(&json!(color)).is_err(), "{}", color);
+        }
+        for period in ["0", "15m", "24h"] {
+            assert!(check_auto_sync(&json!(period)).is_ok(), "{}", period);
+        }
+        for period in ["25h", "15s", ""] {
+            assert!(check_auto_sync(&json!(period)).is_err(), "{}", period);
+        }
+        assert!(check_locale(&json!("zh-cn")).is_ok());
+        assert!(check_days(&json!(MAX_DAYS + 1)).is_err());
+        assert!(check_timestamp(&json!("2024-01-01T00:00:00.000Z")).is_ok());
+        assert!(check_timestamp(&json!("yesterday")).is_err());
+    }
+
+    #[test]
+    fn synced_fields_keep_device_local_values() {
+        let mut local = AppSettings::default();
+        local.window_config.extra.insert("x".to_string(), json!(10));
+        let remote = json!({ "locale": "en", "windowConfig": { "borderWidth": 2, "x": 500 } });
+
+        let settings = local.with_synced_fields(remote.as_object().unwrap().clone());
+        assert_eq!(settings.locale, "en");
+        assert_eq!(settings.window_config.border_width, 2);
+        assert_eq!(settings.window_config.extra.get("x"), Some(&json!(10)));
+        assert!(settings.to_sync_map()["windowConfig"].get("x").is_none());
+    }
+
+    #[test]
+    fn merge_patch_follows_rfc_7396() {
+        let mut target = json!({ "locale": "zh-cn", "colors": { "normal": "#000", "urgent": "#f00" }, "autoSync": "15m" });
+        merge_patch(&mut target, &json!({ "colors": { "normal": "#fff", "urgent": null }, "autoSync": null, "archiveDays": 7 }));
+        assert_eq!(target, json!({ "locale": "zh-cn", "colors": { "normal": "#fff" }, "archiveDays": 7 }));
+
+        merge_patch(&mut target, &json!({ "colors": "#123" }));
+        assert_eq!(target["colors"], json!("#123"));
+
+        merge_patch(&mut target, &json!({ "colors": { "border": "#abc" } }));
+        assert_eq!(target["colors"], json!({ "border": "#abc" }));
+
+        merge_patch(&mut target, &json!(["replaced"]));
+        assert_eq!(target, json!(["replaced"]));
+    }
+}
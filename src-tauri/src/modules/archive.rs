@@ -0,0 +1,155 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::modules::app::{load_app_settings, AppSettings};
+use crate::modules::data::{archive_todos, collect_subtree, load_todos, read_archive, unarchive_todos};
+use crate::modules::database::DatabaseState;
+use crate::modules::types::{parse_utc, ArchiveBatch, Todo};
+
+/// 自动归档的检查间隔
+const ARCHIVE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 归档搜索结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveMatch {
+    pub batch_id: String,
+    pub archived_at: String,
+    pub todo: Todo,
+}
+
+/// 设置中的归档天数，为 0 时不自动归档
+fn archive_days(settings: &AppSettings) -> Option<u64> {
+    let days = settings.archive_days;
+    (days > 0).then_some(days)
+}
+
+/// 选出可以归档的待办：在 `before` 之前完成、且所有后代都已完成的待办连同其后代
+pub fn select_archivable(todos: &[Todo], before: DateTime<Utc>) -> HashSet<String> {
+    let mut selected = HashSet::new();
+
+    for todo in todos {
+        if selected.contains(&todo.id) || !todo.completed {
+            continue;
+        }
+        let expired = todo.completed_at.as_deref()
+            .and_then(parse_utc)
+            .is_some_and(|completed_at| completed_at <= before);
+        if !expired {
+            continue;
+        }
+
+        let subtree = collect_subtree(todos, vec![todo.id.clone()]);
+        if todos.iter().filter(|item| subtree.contains(&item.id)).all(|item| item.completed) {
+            selected.extend(subtree);
+        }
+    }
+
+    selected
+}
+
+/// 把 `before` 之前完成的待办作为一个新批次移入归档，返回归档的数量
+pub async fn archive_completed(before: DateTime<Utc>) -> Result<usize, String> {
+    archive_todos(|todos| select_archivable(todos, before)).await
+}
+
+/// 按 archiveDays 执行一次自动归档
+async fn run_archive(app: &AppHandle) -> Result<usize, String> {
+    let Some(days) = archive_days(&load_app_settings().await?) else {
+        return Ok(0);
+    };
+    let before = Utc::now() - chrono::Duration::days(i64::try_from(days).map_err(|e| e.to_string())?);
+
+    // 与同步互斥，避免两边同时改写待办列表
+    let state = app.state::<DatabaseState>();
+    let _sync_guard = state.sync_lock.lock().await;
+    archive_completed(before).await
+}
+
+/// 启动后台归档任务：启动时执行一次，之后按 `ARCHIVE_INTERVAL` 定期执行
+///
+/// 归档了待办时发出 `todos-archived` 事件（内容为归档数量），失败时发出 `archive-failed`。
+pub fn spawn_archive_job(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match run_archive(&app).await {
+                Ok(0) => {}
+                Ok(count) => {
+                    let _ = app.emit("todos-archived", count);
+                }
+                Err(e) => {
+                    let _ = app.emit("archive-failed", e);
+                }
+            }
+            tokio::time::sleep(ARCHIVE_INTERVAL).await;
+        }
+    });
+}
+
+/// 列出所有归档批次，最近归档的在前
+#[tauri::command]
+pub async fn list_archives() -> Result<Vec<ArchiveBatch>, String> {
+    let mut batches = read_archive().await?.batches;
+    batches.reverse();
+    Ok(batches)
+}
+
+/// 把归档中的待办连同其归档的后代移回待办列表，父项不在列表中时移到顶层
+///
+/// 待办不在归档中时返回 false。
+#[tauri::command]
+pub async fn unarchive_todo(
+    todo_id: String,
+    state: State<'_, DatabaseState>,
+) -> Result<bool, String> {
+    let _sync_guard = state.sync_lock.lock().await;
+
+    let archived: Vec<Todo> = read_archive().await?.todos().cloned().collect();
+    if !archived.iter().any(|todo| todo.id == todo_id) {
+        return Ok(false);
+    }
+    let ids = collect_subtree(&archived, vec![todo_id]);
+
+    let mut known_ids: HashSet<String> = load_todos().await?.data.into_iter().map(|todo| todo.id).collect();
+    known_ids.extend(ids.iter().cloned());
+    let restored: Vec<Todo> = archived
+        .into_iter()
+        .filter(|todo| ids.contains(&todo.id))
+        .map(|mut todo| {
+            if todo.parent_id.as_ref().is_some_and(|parent| !known_ids.contains(parent)) {
+                todo.parent_id = None;
+            }
+            todo
+        })
+        .collect();
+
+    // 写回列表与移出归档在同一事务中完成
+    unarchive_todos(restored).await?;
+
+    Ok(true)
+}
+
+/// 按内容搜索归档（不区分大小写），查询为空时返回全部，最近归档的在前
+#[tauri::command]
+pub async fn search_archive(query: String) -> Result<Vec<ArchiveMatch>, String> {
+    let query = query.trim().to_lowercase();
+
+    Ok(read_archive().await?
+        .batches
+        .into_iter()
+        .rev()
+        .flat_map(|batch| {
+            let ArchiveBatch { id, archived_at, todos } = batch;
+            todos.into_iter().map(move |todo| ArchiveMatch {
+                batch_id: id.clone(),
+                archived_at: archived_at.clone(),
+                todo,
+            })
+        })
+        .filter(|found| query.is_empty() || found.todo.text.to_lowercase().contains(&query))
+        .collect())
+}
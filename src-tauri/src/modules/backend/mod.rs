@@ -0,0 +1,203 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::modules::crdt::TextCrdt;
+use crate::modules::database::{DatabaseConfig, DatabaseKind};
+use crate::modules::oplog::{Hlc, TodoOp};
+use crate::modules::schema::RemoteSchemaStatus;
+use crate::modules::types::{parse_utc, Todo};
+
+mod mysql;
+mod postgres;
+mod sqlite;
+
+pub use mysql::MySqlBackend;
+pub use postgres::PostgresBackend;
+pub use sqlite::SqliteBackend;
+pub(crate) use sqlite::MIGRATIONS_TABLE_SQL as SQLITE_MIGRATIONS_TABLE_SQL;
+
+// 恢复已删除待办的范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RestoreScope {
+    // 当前项、被删除的父项以及它们的子项
+    WithAncestors,
+    // 只恢复当前项和其子项
+    Subtree,
+}
+
+// 远程同步后端：所有与具体数据库方言相关的操作
+#[async_trait]
+pub trait SyncBackend: Send + Sync {
+    // 执行一次简单查询，确认连接可用
+    async fn ping(&self) -> Result<(), String>;
+
+    // 远程结构版本状态（不做任何修改）
+    async fn schema_status(&self) -> Result<RemoteSchemaStatus, String>;
+    // 执行未执行的结构迁移，远程结构比当前程序新时返回错误
+    async fn ensure_schema(&self) -> Result<Vec<String>, String>;
+    // 当前程序已知的最高结构版本
+    fn latest_schema_version(&self) -> u32;
+
+    // 插入或更新待办，lastUpdate 缺失时使用 `fallback_last_update`
+    async fn upsert_todos(&self, todos: &[Todo], fallback_last_update: &str) -> Result<usize, String>;
+    // 下载待办（include_deleted 为 true 时包含已逻辑删除的项）
+    async fn download_todos(&self, include_deleted: bool) -> Result<Vec<Todo>, String>;
+    // 已逻辑删除的待办，最近删除的在前
+    async fn deleted_todos(&self) -> Result<Vec<Todo>, String>;
+    // 远程待办的最新更新时间，没有数据时返回 None
+    async fn todos_last_update(&self) -> Result<Option<DateTime<Utc>>, String>;
+    // 级联逻辑删除待办及其子项
+    async fn delete_todo(&self, id: &str, time: DateTime<Utc>) -> Result<bool, String>;
+    // 恢复已删除的待办
+    async fn restore_todo(&self, id: &str, scope: RestoreScope, time: DateTime<Utc>) -> Result<bool, String>;
+    // 彻底删除在 `before` 之前逻辑删除的待办及其已删除的子项，同时删除它们的操作日志并留下墓碑，
+    // 返回被清除的待办 id
+    async fn purge_deleted_todos(&self, before: DateTime<Utc>) -> Result<Vec<String>, String>;
+    // 已彻底删除的待办 id（墓碑），其他设备据此从本地移除
+    async fn purged_todo_ids(&self) -> Result<Vec<String>, String>;
+
+    // 上传操作日志（已存在的操作忽略），返回新写入的数量
+    async fn upload_ops(&self, ops: &[TodoOp]) -> Result<usize, String>;
+    // 下载远程序号大于 `after` 的操作，按序号升序返回 (序号, 操作)
+    async fn download_ops(&self, after: i64) -> Result<Vec<(i64, TodoOp)>, String>;
+
+    // 下载设置（字段名 -> 值）
+    async fn download_settings(&self) -> Result<Map<String, Value>, String>;
+    // 远程设置中记录的 lastUpdate
+    async fn settings_last_update(&self) -> Result<Option<String>, String>;
+    // 各设置字段在远程记录的修改时间（字段名 -> last_update 列）
+    async fn download_settings_times(&self) -> Result<HashMap<String, String>, String>;
+    // 逐字段上传设置，每个字段记录 `field_times` 中自己的修改时间，没有记录的使用 `last_update`
+    async fn upload_settings(
+        &self,
+        settings: &Map<String, Value>,
+        field_times: &HashMap<String, String>,
+        last_update: &str,
+    ) -> Result<usize, String>;
+
+    // 清理重复的设置数据，只保留每个字段的最新记录
+    async fn cleanup_duplicate_settings(&self) -> Result<(), String>;
+    // 检查数据完整性，返回说明文字
+    async fn check_data_integrity(&self) -> Result<String, String>;
+}
+
+// 按配置中的种类连接对应的后端
+pub async fn connect(config: &DatabaseConfig) -> Result<Arc<dyn SyncBackend>, String> {
+    Ok(match config.kind {
+        DatabaseKind::Mysql => Arc::new(MySqlBackend::connect(config).await?),
+        DatabaseKind::Postgres => Arc::new(PostgresBackend::connect(config).await?),
+        DatabaseKind::Sqlite => Arc::new(SqliteBackend::connect(config).await?),
+    })
+}
+
+// 将 RFC 3339 时间转换为 UTC，用于写入远程时间列
+fn to_db_time(value: &str, field: &str) -> Result<DateTime<Utc>, String> {
+    parse_utc(value).ok_or_else(|| format!("{} 不是有效的时间: {}", field, value))
+}
+
+fn to_optional_db_time(value: Option<&str>, field: &str) -> Result<Option<DateTime<Utc>>, String> {
+    value.map(|value| to_db_time(value, field)).transpose()
+}
+
+// 序列化待办的文本副本，写入 text_crdt 列
+fn text_crdt_column(todo: &Todo) -> Result<Option<String>, String> {
+    todo.text_crdt
+        .as_ref()
+        .map(|crdt| serde_json::to_string(crdt).map_err(|e| format!("序列化待办 {} 的文本副本失败: {}", todo.id, e)))
+        .transpose()
+}
+
+// 读取 text_crdt 列；只更新了 text 列的旧版本写入会被补到副本中，无法解析时忽略
+fn text_crdt_from_column(value: Option<String>, text: &str) -> Option<TextCrdt> {
+    let mut crdt: TextCrdt = serde_json::from_str(&value?).ok()?;
+    crdt.reconcile(text);
+    Some(crdt)
+}
+
+// 序列化操作中修改的字段
+fn serialize_patch(op: &TodoOp) -> Result<String, String> {
+    serde_json::to_string(&op.patch).map_err(|e| format!("序列化操作 {} 失败: {}", op.id, e))
+}
+
+// 由远程 todo_ops 行的各列还原操作
+fn op_from_columns(
+    id: String,
+    device: String,
+    wall: i64,
+    counter: i64,
+    todo_id: String,
+    patch: &str,
+) -> Result<TodoOp, String> {
+    let patch = serde_json::from_str(patch)
+        .map_err(|e| format!("解析远程操作 {} 失败: {}", id, e))?;
+    Ok(TodoOp {
+        id,
+        hlc: Hlc { wall, counter: counter as u32, device },
+        todo_id,
+        patch,
+    })
+}
+
+// 设置值在远程表中记录的数据类型
+fn setting_data_type(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "string",
+        Value::Number(_) => "number",
+        Value::Bool(_) => "boolean",
+        Value::Object(_) => "object",
+        Value::Array(_) => "array",
+        Value::Null => "null",
+    }
+}
+
+// 解析远程设置字段值，不是 JSON 时按字符串处理
+fn parse_setting_value(field_value: String) -> Value {
+    serde_json::from_str(&field_value).unwrap_or(Value::String(field_value))
+}
+
+// 远程 lastUpdate 设置以 JSON 字符串保存，去掉引号
+fn trim_setting_time(field_value: &str) -> String {
+    field_value.trim_matches('"').to_string()
+}
+
+// 所有后端通用的维护语句
+const CLEANUP_DUPLICATE_SETTINGS_SQL: &str = r#"
+    DELETE FROM todo_settings_sync
+    WHERE id NOT IN (
+        SELECT id FROM (
+            SELECT id,
+                   ROW_NUMBER() OVER (PARTITION BY field_name ORDER BY id DESC) as rn
+            FROM todo_settings_sync
+        ) ranked
+        WHERE rn = 1
+    )
+"#;
+
+const DUPLICATE_SETTINGS_SQL: &str = r#"
+    SELECT field_name, COUNT(*) as count
+    FROM todo_settings_sync
+    GROUP BY field_name
+    HAVING COUNT(*) > 1
+"#;
+
+const ACTIVE_TODO_COUNT_SQL: &str = "SELECT COUNT(*) as count FROM todo_items_sync WHERE is_deleted = FALSE";
+
+// 根据重复字段数与待办数量生成完整性说明
+fn integrity_message(duplicate_fields: usize, todo_count: i64) -> String {
+    let mut messages: Vec<String> = Vec::new();
+
+    if duplicate_fields > 0 {
+        messages.push(format!("发现 {} 个重复的设置字段", duplicate_fields));
+    } else {
+        messages.push("设置表数据完整性正常".to_string());
+    }
+
+    messages.push(format!("待办事项总数: {}", todo_count));
+
+    messages.join("；")
+}
@@ -0,0 +1,863 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use sqlx::mysql::MySqlRow;
+use sqlx::{MySqlConnection, MySqlPool, Row};
+use std::collections::HashMap;
+
+use super::{
+    integrity_message, op_from_columns, parse_setting_value, serialize_patch, setting_data_type,
+    text_crdt_column, text_crdt_from_column, to_db_time, to_optional_db_time, trim_setting_time,
+    RestoreScope, SyncBackend, ACTIVE_TODO_COUNT_SQL, CLEANUP_DUPLICATE_SETTINGS_SQL,
+    DUPLICATE_SETTINGS_SQL,
+};
+use crate::modules::database::DatabaseConfig;
+use crate::modules::oplog::TodoOp;
+use crate::modules::schema::{
+    apply_pending, latest_version, schema_status, MigrationConnection, MigrationStep,
+    RemoteSchemaStatus, SchemaMigration, StepFuture,
+};
+use crate::modules::types::{format_utc, parse_utc, Todo};
+
+// 远程迁移记录表
+const MIGRATIONS_TABLE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS ton_schema_migrations (
+        version INT PRIMARY KEY COMMENT '迁移版本号',
+        name VARCHAR(100) NOT NULL COMMENT '迁移名称',
+        checksum CHAR(64) NOT NULL COMMENT '迁移语句的 SHA-256 校验和',
+        applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP COMMENT '执行时间'
+    ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci
+"#;
+
+// 迁移期间持有的命名锁，防止多台设备同时迁移
+const MIGRATION_LOCK: &str = "ton_schema_migrations";
+
+// 按版本顺序排列的迁移，已发布的迁移不能再修改（校验和会变化）
+const MIGRATIONS: &[SchemaMigration<MySqlConnection>] = &[
+    SchemaMigration {
+        version: 1,
+        name: "create_todo_settings_sync",
+        steps: &[MigrationStep::Sql(r#"
+            CREATE TABLE IF NOT EXISTS todo_settings_sync (
+                id INT AUTO_INCREMENT PRIMARY KEY,
+                update_time VARCHAR(50) NOT NULL COMMENT '更新时间',
+                field_name VARCHAR(100) NOT NULL UNIQUE COMMENT '字段名',
+                data_type VARCHAR(50) NOT NULL COMMENT '数据类型',
+                field_value TEXT NOT NULL COMMENT '字段值',
+                last_update VARCHAR(50) NOT NULL COMMENT '最后更新时间',
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+                INDEX idx_field_name (field_name),
+                INDEX idx_last_update (last_update)
+            ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci
+        "#)],
+    },
+    SchemaMigration {
+        version: 2,
+        name: "create_todo_items_sync",
+        steps: &[MigrationStep::Sql(r#"
+            CREATE TABLE IF NOT EXISTS todo_items_sync (
+                id VARCHAR(36) PRIMARY KEY COMMENT '待办事项ID (UUID)',
+                parent_id VARCHAR(36) NULL COMMENT '父项ID，支持树形结构 (UUID)',
+                text TEXT NOT NULL COMMENT '待办事项内容',
+                completed BOOLEAN NOT NULL DEFAULT FALSE COMMENT '是否完成',
+                created_at VARCHAR(50) NOT NULL COMMENT '创建时间',
+                completed_at VARCHAR(50) NULL COMMENT '完成时间',
+                deadline VARCHAR(50) NULL COMMENT '截止时间',
+                is_deleted BOOLEAN NOT NULL DEFAULT FALSE COMMENT '是否已删除（逻辑删除）',
+                last_update VARCHAR(50) NOT NULL COMMENT '最后更新时间',
+                created_timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                updated_timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+                INDEX idx_id (id),
+                INDEX idx_parent_id (parent_id),
+                INDEX idx_completed (completed),
+                INDEX idx_is_deleted (is_deleted),
+                INDEX idx_last_update (last_update),
+                INDEX idx_deadline (deadline),
+                INDEX idx_created_timestamp (created_timestamp),
+                INDEX idx_updated_timestamp (updated_timestamp)
+            ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci
+        "#)],
+    },
+    SchemaMigration {
+        version: 3,
+        name: "todo_items_sync_datetime_utc",
+        // MySQL 的 DDL 会隐式提交，每一步执行前先检查当前结构，中途失败后重试可以从断点继续
+        steps: &[
+            MigrationStep::Rust("add_utc_time_columns", add_utc_time_columns),
+            MigrationStep::Rust("convert_item_timestamps_to_utc", convert_item_timestamps),
+            MigrationStep::Rust("drop_legacy_time_columns", drop_legacy_time_columns),
+            MigrationStep::Rust("rename_utc_time_columns", rename_utc_time_columns),
+        ],
+    },
+    SchemaMigration {
+        version: 4,
+        name: "create_todo_ops",
+        steps: &[MigrationStep::Sql(r#"
+            CREATE TABLE IF NOT EXISTS todo_ops (
+                seq BIGINT AUTO_INCREMENT PRIMARY KEY COMMENT '远程写入顺序',
+                op_id VARCHAR(120) NOT NULL UNIQUE COMMENT '操作ID（设备ID:时钟）',
+                device_id VARCHAR(36) NOT NULL COMMENT '产生操作的设备ID',
+                hlc_wall BIGINT NOT NULL COMMENT '混合逻辑时钟：物理毫秒',
+                hlc_counter INT NOT NULL COMMENT '混合逻辑时钟：逻辑计数器',
+                todo_id VARCHAR(36) NOT NULL COMMENT '待办事项ID (UUID)',
+                patch TEXT NOT NULL COMMENT '修改的字段 (JSON)',
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                INDEX idx_todo_id (todo_id),
+                INDEX idx_device_id (device_id)
+            ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci
+        "#)],
+    },
+    SchemaMigration {
+        version: 5,
+        name: "todo_items_sync_text_crdt",
+        steps: &[MigrationStep::Sql(r#"
+            ALTER TABLE todo_items_sync
+                ADD COLUMN text_crdt MEDIUMTEXT NULL COMMENT '待办内容的字符级合并副本 (JSON)' AFTER text
+        "#)],
+    },
+    SchemaMigration {
+        version: 6,
+        name: "create_todo_tombstones",
+        steps: &[MigrationStep::Sql(r#"
+            CREATE TABLE IF NOT EXISTS todo_tombstones (
+                id VARCHAR(36) PRIMARY KEY COMMENT '已彻底删除的待办事项ID (UUID)',
+                purged_at DATETIME(3) NOT NULL COMMENT '彻底删除时间 (UTC)',
+                INDEX idx_purged_at (purged_at)
+            ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci
+        "#)],
+    },
+];
+
+// 解析旧版 VARCHAR 列中的时间：RFC 3339（任意时区）或不带时区的 UTC 时间
+fn parse_legacy_time(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    parse_utc(value).or_else(|| {
+        chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+            .ok()
+            .map(|time| time.and_utc())
+    })
+}
+
+// 迁移 3 转换的时间列：(旧 VARCHAR 列, 新 DATETIME(3) 列, 改名后的列定义)
+const UTC_TIME_COLUMNS: [(&str, &str, &str); 4] = [
+    ("created_at", "created_at_utc", "DATETIME(3) NOT NULL COMMENT '创建时间 (UTC)'"),
+    ("completed_at", "completed_at_utc", "DATETIME(3) NULL COMMENT '完成时间 (UTC)'"),
+    ("deadline", "deadline_utc", "DATETIME(3) NULL COMMENT '截止时间 (UTC)'"),
+    ("last_update", "last_update_utc", "DATETIME(3) NOT NULL COMMENT '最后更新时间 (UTC)'"),
+];
+
+// 迁移 3 重建的索引：(索引名, 列)
+const UTC_TIME_INDEXES: [(&str, &str); 2] = [("idx_last_update", "last_update"), ("idx_deadline", "deadline")];
+
+// todo_items_sync 中列的类型（小写），列不存在时返回 None
+async fn item_column_type(conn: &mut MySqlConnection, column: &str) -> Result<Option<String>, String> {
+    let row = sqlx::query(r#"
+        SELECT DATA_TYPE AS data_type FROM information_schema.COLUMNS
+        WHERE table_schema = DATABASE() AND table_name = 'todo_items_sync' AND column_name = ?
+    "#)
+        .bind(column)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| format!("读取列 {} 的信息失败: {}", column, e))?;
+    Ok(row.map(|row| row.get::<String, _>("data_type").to_lowercase()))
+}
+
+// todo_items_sync 上的索引是否存在
+async fn item_index_exists(conn: &mut MySqlConnection, index: &str) -> Result<bool, String> {
+    let count: i64 = sqlx::query(r#"
+        SELECT COUNT(*) AS count FROM information_schema.STATISTICS
+        WHERE table_schema = DATABASE() AND table_name = 'todo_items_sync' AND index_name = ?
+    "#)
+        .bind(index)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| format!("读取索引 {} 的信息失败: {}", index, e))?
+        .get("count");
+    Ok(count > 0)
+}
+
+// 旧的 VARCHAR 时间列是否还在
+async fn has_legacy_time_column(conn: &mut MySqlConnection, column: &str) -> Result<bool, String> {
+    Ok(item_column_type(conn, column).await?.as_deref() == Some("varchar"))
+}
+
+// 执行一条由若干子句组成的 ALTER TABLE，没有子句时不执行
+async fn alter_items(conn: &mut MySqlConnection, clauses: Vec<String>) -> Result<(), String> {
+    if clauses.is_empty() {
+        return Ok(());
+    }
+    sqlx::query(&format!("ALTER TABLE todo_items_sync {}", clauses.join(", ")))
+        .execute(&mut *conn)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// 迁移 3 第一步：在旧列旁新增 DATETIME(3) 列（已存在的跳过）
+fn add_utc_time_columns(conn: &mut MySqlConnection) -> StepFuture<'_> {
+    Box::pin(async move {
+        let mut clauses = Vec::new();
+        for (legacy, utc, _) in UTC_TIME_COLUMNS {
+            if has_legacy_time_column(conn, legacy).await? && item_column_type(conn, utc).await?.is_none() {
+                clauses.push(format!("ADD COLUMN {} DATETIME(3) NULL AFTER {}", utc, legacy));
+            }
+        }
+        alter_items(conn, clauses).await
+    })
+}
+
+// 迁移 3 第二步：把字符串时间统一转换为 UTC 写入新的 DATETIME(3) 列（旧列已删除时跳过）
+//
+// 回退用的 TIMESTAMP 列以 UNIX 时间读取，不依赖也不修改会话时区。
+fn convert_item_timestamps(conn: &mut MySqlConnection) -> StepFuture<'_> {
+    Box::pin(async move {
+        if !has_legacy_time_column(conn, "last_update").await? {
+            return Ok(());
+        }
+
+        let rows = sqlx::query(r#"
+            SELECT id, created_at, completed_at, deadline, last_update,
+                CAST(UNIX_TIMESTAMP(created_timestamp) AS SIGNED) AS created_unix,
+                CAST(UNIX_TIMESTAMP(updated_timestamp) AS SIGNED) AS updated_unix
+            FROM todo_items_sync
+        "#)
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| format!("读取待办时间失败: {}", e))?;
+
+        for row in rows {
+            let id: String = row.get("id");
+            let time_of = |column: &str| {
+                row.get::<Option<String>, _>(column)
+                    .as_deref()
+                    .and_then(parse_legacy_time)
+            };
+            let unix_of = |column: &str| {
+                row.get::<Option<i64>, _>(column)
+                    .and_then(|seconds| DateTime::<Utc>::from_timestamp(seconds, 0))
+            };
+
+            // 无法解析的创建/更新时间回退到行本身的时间戳
+            let created_at = time_of("created_at").or_else(|| unix_of("created_unix")).unwrap_or_else(Utc::now);
+            let last_update = time_of("last_update").or_else(|| unix_of("updated_unix")).unwrap_or_else(Utc::now);
+
+            sqlx::query(r#"
+                UPDATE todo_items_sync SET
+                    created_at_utc = ?,
+                    completed_at_utc = ?,
+                    deadline_utc = ?,
+                    last_update_utc = ?
+                WHERE id = ?
+            "#)
+                .bind(created_at)
+                .bind(time_of("completed_at"))
+                .bind(time_of("deadline"))
+                .bind(last_update)
+                .bind(&id)
+                .execute(&mut *conn)
+                .await
+                .map_err(|e| format!("转换待办 {} 的时间失败: {}", id, e))?;
+        }
+
+        Ok(())
+    })
+}
+
+// 迁移 3 第三步：删除旧的 VARCHAR 时间列及其索引（已删除的跳过）
+fn drop_legacy_time_columns(conn: &mut MySqlConnection) -> StepFuture<'_> {
+    Box::pin(async move {
+        let mut clauses = Vec::new();
+        for (index, column) in UTC_TIME_INDEXES {
+            if has_legacy_time_column(conn, column).await? && item_index_exists(conn, index).await? {
+                clauses.push(format!("DROP INDEX {}", index));
+            }
+        }
+        for (legacy, utc, _) in UTC_TIME_COLUMNS {
+            if has_legacy_time_column(conn, legacy).await? && item_column_type(conn, utc).await?.is_some() {
+                clauses.push(format!("DROP COLUMN {}", legacy));
+            }
+        }
+        alter_items(conn, clauses).await
+    })
+}
+
+// 迁移 3 第四步：新列改用原来的列名并重建索引（已完成的跳过）
+fn rename_utc_time_columns(conn: &mut MySqlConnection) -> StepFuture<'_> {
+    Box::pin(async move {
+        let mut clauses = Vec::new();
+        for (legacy, utc, definition) in UTC_TIME_COLUMNS {
+            if item_column_type(conn, utc).await?.is_some() && item_column_type(conn, legacy).await?.is_none() {
+                clauses.push(format!("CHANGE COLUMN {} {} {}", utc, legacy, definition));
+            }
+        }
+        for (index, column) in UTC_TIME_INDEXES {
+            if !item_index_exists(conn, index).await? {
+                clauses.push(format!("ADD INDEX {} ({})", index, column));
+            }
+        }
+        alter_items(conn, clauses).await
+    })
+}
+
+#[async_trait]
+impl MigrationConnection for MySqlConnection {
+    async fn execute_sql(&mut self, sql: &str) -> Result<(), String> {
+        sqlx::query(sql)
+            .execute(&mut *self)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn applied_migrations(&mut self) -> Result<Vec<(u32, String)>, String> {
+        let count: i64 = sqlx::query(
+            "SELECT COUNT(*) as count FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = 'ton_schema_migrations'"
+        )
+            .fetch_one(&mut *self)
+            .await
+            .map_err(|e| format!("检查迁移记录表失败: {}", e))?
+            .get("count");
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query("SELECT version, checksum FROM ton_schema_migrations ORDER BY version")
+            .fetch_all(&mut *self)
+            .await
+            .map_err(|e| format!("读取远程迁移记录失败: {}", e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get::<i32, _>("version") as u32, row.get::<String, _>("checksum")))
+            .collect())
+    }
+
+    async fn record_migration(&mut self, version: u32, name: &str, checksum: &str) -> Result<(), String> {
+        sqlx::query("INSERT INTO ton_schema_migrations (version, name, checksum) VALUES (?, ?, ?)")
+            .bind(version as i32)
+            .bind(name)
+            .bind(checksum)
+            .execute(&mut *self)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+// 将远程待办行转换为待办事项（时间统一输出为 UTC）
+fn todo_from_row(row: &MySqlRow) -> Todo {
+    let text: String = row.get("text");
+    Todo {
+        id: row.get("id"),
+        text_crdt: text_crdt_from_column(row.get("text_crdt"), &text),
+        text,
+        completed: row.get("completed"),
+        created_at: format_utc(row.get("created_at")),
+        completed_at: row.get::<Option<DateTime<Utc>>, _>("completed_at").map(format_utc),
+        deadline: row.get::<Option<DateTime<Utc>>, _>("deadline").map(format_utc),
+        parent_id: row.get("parent_id"),
+        is_deleted: row.get("is_deleted"),
+        last_update: Some(format_utc(row.get("last_update"))),
+        extra: Map::new(),
+    }
+}
+
+// MySQL 同步后端
+pub struct MySqlBackend {
+    pool: MySqlPool,
+}
+
+impl MySqlBackend {
+    // 创建数据库连接池
+    pub async fn connect(config: &DatabaseConfig) -> Result<Self, String> {
+        let connection_string = format!(
+            "mysql://{}:{}@{}:{}/{}",
+            config.username, config.password, config.host, config.port, config.database
+        );
+
+        let pool = MySqlPool::connect(&connection_string).await
+            .map_err(|e| format!("创建数据库连接池失败: {}", e))?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl SyncBackend for MySqlBackend {
+    async fn ping(&self) -> Result<(), String> {
+        sqlx::query("SELECT 1")
+            .fetch_one(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("数据库查询测试失败: {}", e))
+    }
+
+    async fn schema_status(&self) -> Result<RemoteSchemaStatus, String> {
+        let mut conn = self.pool.acquire().await
+            .map_err(|e| format!("获取数据库连接失败: {}", e))?;
+        let applied = conn.applied_migrations().await?;
+        Ok(schema_status(&applied, MIGRATIONS))
+    }
+
+    async fn ensure_schema(&self) -> Result<Vec<String>, String> {
+        let mut conn = self.pool.acquire().await
+            .map_err(|e| format!("获取数据库连接失败: {}", e))?;
+
+        sqlx::query(MIGRATIONS_TABLE_SQL)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("创建迁移记录表失败: {}", e))?;
+
+        // 命名锁与连接绑定，之后的语句都在同一连接上执行
+        let locked: Option<i64> = sqlx::query("SELECT GET_LOCK(?, 30) AS locked")
+            .bind(MIGRATION_LOCK)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|e| format!("获取迁移锁失败: {}", e))?
+            .get("locked");
+        if locked != Some(1) {
+            return Err("其他设备正在迁移远程数据库结构，请稍后重试".to_string());
+        }
+
+        // MySQL 的 DDL 不支持事务，每个迁移成功后立即记录
+        let result = apply_pending(&mut *conn, MIGRATIONS).await;
+
+        let _ = sqlx::query("SELECT RELEASE_LOCK(?)")
+            .bind(MIGRATION_LOCK)
+            .execute(&mut *conn)
+            .await;
+
+        result
+    }
+
+    fn latest_schema_version(&self) -> u32 {
+        latest_version(MIGRATIONS)
+    }
+
+    async fn upsert_todos(&self, todos: &[Todo], fallback_last_update: &str) -> Result<usize, String> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| format!("开始事务失败: {}", e))?;
+
+        let query = r#"
+            INSERT INTO todo_items_sync (id, parent_id, text, text_crdt, completed, created_at, completed_at, deadline, is_deleted, last_update)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON DUPLICATE KEY UPDATE
+                parent_id = VALUES(parent_id),
+                text = VALUES(text),
+                text_crdt = VALUES(text_crdt),
+                completed = VALUES(completed),
+                created_at = VALUES(created_at),
+                completed_at = VALUES(completed_at),
+                deadline = VALUES(deadline),
+                is_deleted = VALUES(is_deleted),
+                last_update = VALUES(last_update)
+        "#;
+
+        for todo in todos {
+            // 每项使用自身的更新时间，缺失时使用列表的更新时间
+            let last_update = to_db_time(todo.last_update.as_deref().unwrap_or(fallback_last_update), "lastUpdate")?;
+
+            sqlx::query(query)
+                .bind(&todo.id)
+                .bind(&todo.parent_id)
+                .bind(&todo.text)
+                .bind(text_crdt_column(todo)?)
+                .bind(todo.completed)
+                .bind(to_db_time(&todo.created_at, "createdAt")?)
+                .bind(to_optional_db_time(todo.completed_at.as_deref(), "completedAt")?)
+                .bind(to_optional_db_time(todo.deadline.as_deref(), "deadline")?)
+                .bind(todo.is_deleted)
+                .bind(last_update)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("同步待办数据失败: {}", e))?;
+        }
+
+        tx.commit().await
+            .map_err(|e| format!("提交事务失败: {}", e))?;
+
+        Ok(todos.len())
+    }
+
+    async fn download_todos(&self, include_deleted: bool) -> Result<Vec<Todo>, String> {
+        let query = if include_deleted {
+            r#"
+            SELECT id, parent_id, text, text_crdt, completed, created_at, completed_at, deadline, is_deleted, last_update
+            FROM todo_items_sync
+            ORDER BY created_timestamp
+        "#
+        } else {
+            r#"
+            SELECT id, parent_id, text, text_crdt, completed, created_at, completed_at, deadline, is_deleted, last_update
+            FROM todo_items_sync
+            WHERE is_deleted = FALSE
+            ORDER BY created_timestamp
+        "#
+        };
+
+        let rows = sqlx::query(query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("下载待办数据失败: {}", e))?;
+
+        Ok(rows.iter().map(todo_from_row).collect())
+    }
+
+    async fn deleted_todos(&self) -> Result<Vec<Todo>, String> {
+        let query = r#"
+            SELECT id, parent_id, text, text_crdt, completed, created_at, completed_at, deadline, is_deleted, last_update
+            FROM todo_items_sync
+            WHERE is_deleted = TRUE
+            ORDER BY updated_timestamp DESC
+        "#;
+
+        let rows = sqlx::query(query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("获取已删除待办事项失败: {}", e))?;
+
+        Ok(rows.iter().map(todo_from_row).collect())
+    }
+
+    async fn todos_last_update(&self) -> Result<Option<DateTime<Utc>>, String> {
+        let row = sqlx::query("SELECT MAX(last_update) as last_update FROM todo_items_sync")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| format!("查询远程待办事项lastUpdate失败: {}", e))?;
+
+        Ok(row.get("last_update"))
+    }
+
+    async fn delete_todo(&self, id: &str, time: DateTime<Utc>) -> Result<bool, String> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| format!("开始事务失败: {}", e))?;
+
+        // 使用递归CTE查找所有子项
+        let cascade_delete_query = r#"
+            WITH RECURSIVE todo_hierarchy AS (
+                -- 基础查询：找到要删除的根项
+                SELECT id, parent_id, 0 as level
+                FROM todo_items_sync 
+                WHERE id = ? AND is_deleted = FALSE
+                
+                UNION ALL
+                
+                -- 递归查询：找到所有子项
+                SELECT t.id, t.parent_id, th.level + 1
+                FROM todo_items_sync t
+                INNER JOIN todo_hierarchy th ON t.parent_id = th.id
+                WHERE t.is_deleted = FALSE
+            )
+            UPDATE todo_items_sync 
+            SET is_deleted = TRUE, last_update = ?
+            WHERE id IN (SELECT id FROM todo_hierarchy)
+        "#;
+
+        let result = sqlx::query(cascade_delete_query)
+            .bind(id)
+            .bind(time)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("级联逻辑删除待办事项失败: {}", e))?;
+
+        tx.commit().await
+            .map_err(|e| format!("提交事务失败: {}", e))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn restore_todo(&self, id: &str, scope: RestoreScope, time: DateTime<Utc>) -> Result<bool, String> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| format!("开始事务失败: {}", e))?;
+
+        let query = match scope {
+            // 使用递归CTE查找所有需要恢复的项（包括父项和子项）
+            RestoreScope::WithAncestors => r#"
+                WITH RECURSIVE todo_hierarchy AS (
+                    -- 基础查询：找到要恢复的根项
+                    SELECT id, parent_id, 0 as level
+                    FROM todo_items_sync 
+                    WHERE id = ? AND is_deleted = TRUE
+                    
+                    UNION ALL
+                    
+                    -- 向上递归：找到所有父项（如果父项也被删除了）
+                    SELECT t.id, t.parent_id, th.level + 1
+                    FROM todo_items_sync t
+                    INNER JOIN todo_hierarchy th ON t.id = th.parent_id
+                    WHERE t.is_deleted = TRUE
+                    
+                    UNION ALL
+                    
+                    -- 向下递归：找到所有子项
+                    SELECT t.id, t.parent_id, th.level + 1
+                    FROM todo_items_sync t
+                    INNER JOIN todo_hierarchy th ON t.parent_id = th.id
+                    WHERE t.is_deleted = TRUE
+                )
+                UPDATE todo_items_sync 
+                SET is_deleted = FALSE, last_update = ?
+                WHERE id IN (SELECT id FROM todo_hierarchy)
+            "#,
+            // 使用递归CTE只查找当前项和其子项
+            RestoreScope::Subtree => r#"
+                WITH RECURSIVE todo_hierarchy AS (
+                    -- 基础查询：找到要恢复的根项
+                    SELECT id, parent_id, 0 as level
+                    FROM todo_items_sync 
+                    WHERE id = ? AND is_deleted = TRUE
+                    
+                    UNION ALL
+                    
+                    -- 向下递归：只找到子项
+                    SELECT t.id, t.parent_id, th.level + 1
+                    FROM todo_items_sync t
+                    INNER JOIN todo_hierarchy th ON t.parent_id = th.id
+                    WHERE t.is_deleted = TRUE
+                )
+                UPDATE todo_items_sync 
+                SET is_deleted = FALSE, last_update = ?
+                WHERE id IN (SELECT id FROM todo_hierarchy)
+            "#,
+        };
+
+        let result = sqlx::query(query)
+            .bind(id)
+            .bind(time)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("恢复待办事项失败: {}", e))?;
+
+        tx.commit().await
+            .map_err(|e| format!("提交事务失败: {}", e))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn purge_deleted_todos(&self, before: DateTime<Utc>) -> Result<Vec<String>, String> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| format!("开始事务失败: {}", e))?;
+
+        // 与级联删除相同，沿 parent_id 向下查找已删除的子项
+        let purge_query = r#"
+            WITH RECURSIVE todo_hierarchy AS (
+                SELECT id FROM todo_items_sync WHERE is_deleted = TRUE AND last_update < ?
+                UNION
+                SELECT t.id
+                FROM todo_items_sync t
+                INNER JOIN todo_hierarchy th ON t.parent_id = th.id
+                WHERE t.is_deleted = TRUE
+            )
+            SELECT id FROM todo_hierarchy
+        "#;
+
+        let ids: Vec<String> = sqlx::query(purge_query)
+            .bind(before)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| format!("查找待清除的待办事项失败: {}", e))?
+            .iter()
+            .map(|row| row.get("id"))
+            .collect();
+
+        let now = Utc::now();
+        for id in &ids {
+            sqlx::query("INSERT IGNORE INTO todo_tombstones (id, purged_at) VALUES (?, ?)")
+                .bind(id)
+                .bind(now)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("写入待办墓碑失败: {}", e))?;
+
+            sqlx::query("DELETE FROM todo_ops WHERE todo_id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("清除待办操作日志失败: {}", e))?;
+
+            sqlx::query("DELETE FROM todo_items_sync WHERE id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("清除已删除待办事项失败: {}", e))?;
+        }
+
+        tx.commit().await
+            .map_err(|e| format!("提交事务失败: {}", e))?;
+
+        Ok(ids)
+    }
+
+    async fn purged_todo_ids(&self) -> Result<Vec<String>, String> {
+        let rows = sqlx::query("SELECT id FROM todo_tombstones")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("获取待办墓碑失败: {}", e))?;
+
+        Ok(rows.iter().map(|row| row.get("id")).collect())
+    }
+
+    async fn upload_ops(&self, ops: &[TodoOp]) -> Result<usize, String> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| format!("开始事务失败: {}", e))?;
+
+        // 按操作 id 去重，重复上传不会产生新行
+        let query = r#"
+            INSERT IGNORE INTO todo_ops (op_id, device_id, hlc_wall, hlc_counter, todo_id, patch)
+            VALUES (?, ?, ?, ?, ?, ?)
+        "#;
+
+        let mut inserted = 0;
+        for op in ops {
+            let result = sqlx::query(query)
+                .bind(&op.id)
+                .bind(&op.hlc.device)
+                .bind(op.hlc.wall)
+                .bind(op.hlc.counter as i32)
+                .bind(&op.todo_id)
+                .bind(serialize_patch(op)?)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("上传操作日志失败: {}", e))?;
+            inserted += result.rows_affected() as usize;
+        }
+
+        tx.commit().await
+            .map_err(|e| format!("提交事务失败: {}", e))?;
+
+        Ok(inserted)
+    }
+
+    async fn download_ops(&self, after: i64) -> Result<Vec<(i64, TodoOp)>, String> {
+        let query = r#"
+            SELECT seq, op_id, device_id, hlc_wall, hlc_counter, todo_id, patch
+            FROM todo_ops
+            WHERE seq > ?
+            ORDER BY seq
+        "#;
+
+        let rows = sqlx::query(query)
+            .bind(after)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("下载操作日志失败: {}", e))?;
+
+        rows.iter()
+            .map(|row| {
+                let op = op_from_columns(
+                    row.get("op_id"),
+                    row.get("device_id"),
+                    row.get("hlc_wall"),
+                    row.get::<i32, _>("hlc_counter") as i64,
+                    row.get("todo_id"),
+                    row.get::<&str, _>("patch"),
+                )?;
+                Ok((row.get("seq"), op))
+            })
+            .collect()
+    }
+
+    async fn download_settings(&self) -> Result<Map<String, Value>, String> {
+        let query = "SELECT field_name, field_value FROM todo_settings_sync WHERE field_name != 'last_update'";
+
+        let rows = sqlx::query(query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("下载设置数据失败: {}", e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("field_name"), parse_setting_value(row.get("field_value"))))
+            .collect())
+    }
+
+    async fn settings_last_update(&self) -> Result<Option<String>, String> {
+        let query = "SELECT field_value FROM todo_settings_sync WHERE field_name = 'lastUpdate' LIMIT 1";
+
+        let row = sqlx::query(query)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| format!("查询远程设置lastUpdate失败: {}", e))?;
+
+        Ok(row.map(|row| trim_setting_time(&row.get::<String, _>("field_value"))))
+    }
+
+    async fn download_settings_times(&self) -> Result<HashMap<String, String>, String> {
+        let query = "SELECT field_name, last_update FROM todo_settings_sync WHERE field_name != 'last_update'";
+
+        let rows = sqlx::query(query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("下载设置修改时间失败: {}", e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("field_name"), row.get("last_update")))
+            .collect())
+    }
+
+    async fn upload_settings(
+        &self,
+        settings: &Map<String, Value>,
+        field_times: &HashMap<String, String>,
+        last_update: &str,
+    ) -> Result<usize, String> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| format!("开始事务失败: {}", e))?;
+
+        // 使用 INSERT ... ON DUPLICATE KEY UPDATE 实现逐字段更新
+        let upsert_query = r#"
+            INSERT INTO todo_settings_sync (update_time, field_name, data_type, field_value, last_update)
+            VALUES (?, ?, ?, ?, ?)
+            ON DUPLICATE KEY UPDATE
+                update_time = VALUES(update_time),
+                data_type = VALUES(data_type),
+                field_value = VALUES(field_value),
+                last_update = VALUES(last_update)
+        "#;
+
+        for (key, value) in settings {
+            let field_value = serde_json::to_string(value)
+                .map_err(|e| format!("序列化设置值失败: {}", e))?;
+            let field_time = field_times.get(key).map_or(last_update, String::as_str);
+
+            sqlx::query(upsert_query)
+                .bind(last_update)
+                .bind(key)
+                .bind(setting_data_type(value))
+                .bind(&field_value)
+                .bind(field_time)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("同步设置数据失败: {}", e))?;
+        }
+
+        tx.commit().await
+            .map_err(|e| format!("提交事务失败: {}", e))?;
+
+        Ok(settings.len())
+    }
+
+    async fn cleanup_duplicate_settings(&self) -> Result<(), String> {
+        sqlx::query(CLEANUP_DUPLICATE_SETTINGS_SQL)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("清理重复设置数据失败: {}", e))
+    }
+
+    async fn check_data_integrity(&self) -> Result<String, String> {
+        let duplicate_rows = sqlx::query(DUPLICATE_SETTINGS_SQL)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("检查设置表重复数据失败: {}", e))?;
+
+        let todo_count: i64 = sqlx::query(ACTIVE_TODO_COUNT_SQL)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| format!("检查待办数据失败: {}", e))?
+            .get("count");
+
+        Ok(integrity_message(duplicate_rows.len(), todo_count))
+    }
+}
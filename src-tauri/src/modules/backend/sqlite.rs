@@ -0,0 +1,656 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteRow};
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::{
+    integrity_message, op_from_columns, parse_setting_value, serialize_patch, setting_data_type,
+    text_crdt_column, text_crdt_from_column, to_db_time, to_optional_db_time, trim_setting_time,
+    RestoreScope, SyncBackend, ACTIVE_TODO_COUNT_SQL, CLEANUP_DUPLICATE_SETTINGS_SQL,
+    DUPLICATE_SETTINGS_SQL,
+};
+use crate::modules::database::DatabaseConfig;
+use crate::modules::oplog::TodoOp;
+use crate::modules::schema::{
+    apply_pending, latest_version, schema_status, MigrationConnection, MigrationStep,
+    RemoteSchemaStatus, SchemaMigration,
+};
+use crate::modules::types::{format_utc, parse_utc, Todo};
+
+// 其他设备占用数据库文件时的最长等待时间
+const BUSY_TIMEOUT: Duration = Duration::from_secs(15);
+
+// 迁移记录表（本地数据库使用同样的记录表）
+pub(crate) const MIGRATIONS_TABLE_SQL: &str = r#"
+    CREATE TABLE IF NOT EXISTS ton_schema_migrations (
+        version INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        checksum TEXT NOT NULL,
+        applied_at TEXT DEFAULT CURRENT_TIMESTAMP
+    )
+"#;
+
+// 按版本顺序排列的迁移，已发布的迁移不能再修改（校验和会变化）
+//
+// SQLite 没有原生时间类型，时间列保存毫秒精度的 UTC RFC 3339 文本（如 2024-01-01T00:00:00.000Z），
+// 固定格式下按字符串排序即按时刻排序。
+const MIGRATIONS: &[SchemaMigration<SqliteConnection>] = &[
+    SchemaMigration {
+        version: 1,
+        name: "create_todo_settings_sync",
+        steps: &[
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS todo_settings_sync (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    update_time TEXT NOT NULL,
+                    field_name TEXT NOT NULL UNIQUE,
+                    data_type TEXT NOT NULL,
+                    field_value TEXT NOT NULL,
+                    last_update TEXT NOT NULL,
+                    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+                    updated_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+                )
+            "#),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_settings_last_update ON todo_settings_sync (last_update)"),
+        ],
+    },
+    SchemaMigration {
+        version: 2,
+        name: "create_todo_items_sync",
+        steps: &[
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS todo_items_sync (
+                    id TEXT PRIMARY KEY,
+                    parent_id TEXT NULL,
+                    text TEXT NOT NULL,
+                    completed BOOLEAN NOT NULL DEFAULT FALSE,
+                    created_at TEXT NOT NULL,
+                    completed_at TEXT NULL,
+                    deadline TEXT NULL,
+                    is_deleted BOOLEAN NOT NULL DEFAULT FALSE,
+                    last_update TEXT NOT NULL,
+                    created_timestamp TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+                    updated_timestamp TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+                )
+            "#),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_items_parent_id ON todo_items_sync (parent_id)"),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_items_is_deleted ON todo_items_sync (is_deleted)"),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_items_last_update ON todo_items_sync (last_update)"),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_items_deadline ON todo_items_sync (deadline)"),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_items_created_timestamp ON todo_items_sync (created_timestamp)"),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_items_updated_timestamp ON todo_items_sync (updated_timestamp)"),
+        ],
+    },
+    SchemaMigration {
+        version: 3,
+        name: "create_todo_ops",
+        steps: &[
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS todo_ops (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    op_id TEXT NOT NULL UNIQUE,
+                    device_id TEXT NOT NULL,
+                    hlc_wall INTEGER NOT NULL,
+                    hlc_counter INTEGER NOT NULL,
+                    todo_id TEXT NOT NULL,
+                    patch TEXT NOT NULL,
+                    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+                )
+            "#),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_ops_todo_id ON todo_ops (todo_id)"),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_ops_device_id ON todo_ops (device_id)"),
+        ],
+    },
+    SchemaMigration {
+        version: 4,
+        name: "todo_items_sync_text_crdt",
+        steps: &[MigrationStep::Sql("ALTER TABLE todo_items_sync ADD COLUMN text_crdt TEXT NULL")],
+    },
+    SchemaMigration {
+        version: 5,
+        name: "create_todo_tombstones",
+        steps: &[
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS todo_tombstones (
+                    id TEXT PRIMARY KEY,
+                    purged_at TEXT NOT NULL
+                )
+            "#),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_tombstones_purged_at ON todo_tombstones (purged_at)"),
+        ],
+    },
+];
+
+// 与列默认值一致的当前时间表达式
+const NOW_SQL: &str = "strftime('%Y-%m-%dT%H:%M:%fZ', 'now')";
+
+#[async_trait]
+impl MigrationConnection for SqliteConnection {
+    async fn execute_sql(&mut self, sql: &str) -> Result<(), String> {
+        sqlx::query(sql)
+            .execute(&mut *self)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn applied_migrations(&mut self) -> Result<Vec<(u32, String)>, String> {
+        let count: i64 = sqlx::query(
+            "SELECT COUNT(*) as count FROM sqlite_master WHERE type = 'table' AND name = 'ton_schema_migrations'"
+        )
+            .fetch_one(&mut *self)
+            .await
+            .map_err(|e| format!("检查迁移记录表失败: {}", e))?
+            .get("count");
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query("SELECT version, checksum FROM ton_schema_migrations ORDER BY version")
+            .fetch_all(&mut *self)
+            .await
+            .map_err(|e| format!("读取远程迁移记录失败: {}", e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get::<i64, _>("version") as u32, row.get::<String, _>("checksum")))
+            .collect())
+    }
+
+    async fn record_migration(&mut self, version: u32, name: &str, checksum: &str) -> Result<(), String> {
+        sqlx::query("INSERT INTO ton_schema_migrations (version, name, checksum) VALUES (?, ?, ?)")
+            .bind(version as i64)
+            .bind(name)
+            .bind(checksum)
+            .execute(&mut *self)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+// 读取文本时间列，无法解析时原样返回
+fn normalize_time(value: String) -> String {
+    parse_utc(&value).map(format_utc).unwrap_or(value)
+}
+
+// 将远程待办行转换为待办事项（时间统一输出为 UTC）
+fn todo_from_row(row: &SqliteRow) -> Todo {
+    let text: String = row.get("text");
+    Todo {
+        id: row.get("id"),
+        text_crdt: text_crdt_from_column(row.get("text_crdt"), &text),
+        text,
+        completed: row.get("completed"),
+        created_at: normalize_time(row.get("created_at")),
+        completed_at: row.get::<Option<String>, _>("completed_at").map(normalize_time),
+        deadline: row.get::<Option<String>, _>("deadline").map(normalize_time),
+        parent_id: row.get("parent_id"),
+        is_deleted: row.get("is_deleted"),
+        last_update: Some(normalize_time(row.get("last_update"))),
+        extra: Map::new(),
+    }
+}
+
+const TODO_COLUMNS: &str = "id, parent_id, text, text_crdt, completed, created_at, completed_at, deadline, is_deleted, last_update";
+
+// SQLite 同步后端（`database` 为数据库文件路径，可位于网络共享目录）
+pub struct SqliteBackend {
+    pool: SqlitePool,
+}
+
+impl SqliteBackend {
+    // 打开（必要时创建）数据库文件
+    pub async fn connect(config: &DatabaseConfig) -> Result<Self, String> {
+        if config.database.trim().is_empty() {
+            return Err("未指定 SQLite 数据库文件路径".to_string());
+        }
+
+        // 网络文件系统上 WAL 模式不可靠，使用传统回滚日志
+        let options = SqliteConnectOptions::new()
+            .filename(&config.database)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Delete)
+            .busy_timeout(BUSY_TIMEOUT);
+
+        let pool = SqlitePool::connect_with(options).await
+            .map_err(|e| format!("打开 SQLite 数据库失败: {}", e))?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl SyncBackend for SqliteBackend {
+    async fn ping(&self) -> Result<(), String> {
+        sqlx::query("SELECT 1")
+            .fetch_one(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("数据库查询测试失败: {}", e))
+    }
+
+    async fn schema_status(&self) -> Result<RemoteSchemaStatus, String> {
+        let mut conn = self.pool.acquire().await
+            .map_err(|e| format!("获取数据库连接失败: {}", e))?;
+        let applied = conn.applied_migrations().await?;
+        Ok(schema_status(&applied, MIGRATIONS))
+    }
+
+    async fn ensure_schema(&self) -> Result<Vec<String>, String> {
+        let mut conn = self.pool.acquire().await
+            .map_err(|e| format!("获取数据库连接失败: {}", e))?;
+
+        // BEGIN IMMEDIATE 立即获取文件写锁，其他设备的迁移会等待或失败，所有迁移在同一事务中完成
+        sqlx::query("BEGIN IMMEDIATE")
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("获取迁移锁失败: {}", e))?;
+
+        let result = async {
+            sqlx::query(MIGRATIONS_TABLE_SQL)
+                .execute(&mut *conn)
+                .await
+                .map_err(|e| format!("创建迁移记录表失败: {}", e))?;
+
+            apply_pending(&mut *conn, MIGRATIONS).await
+        }
+        .await;
+
+        let end = if result.is_ok() { "COMMIT" } else { "ROLLBACK" };
+        sqlx::query(end)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("提交事务失败: {}", e))?;
+
+        result
+    }
+
+    fn latest_schema_version(&self) -> u32 {
+        latest_version(MIGRATIONS)
+    }
+
+    async fn upsert_todos(&self, todos: &[Todo], fallback_last_update: &str) -> Result<usize, String> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| format!("开始事务失败: {}", e))?;
+
+        let query = format!(r#"
+            INSERT INTO todo_items_sync (id, parent_id, text, text_crdt, completed, created_at, completed_at, deadline, is_deleted, last_update)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (id) DO UPDATE SET
+                parent_id = excluded.parent_id,
+                text = excluded.text,
+                text_crdt = excluded.text_crdt,
+                completed = excluded.completed,
+                created_at = excluded.created_at,
+                completed_at = excluded.completed_at,
+                deadline = excluded.deadline,
+                is_deleted = excluded.is_deleted,
+                last_update = excluded.last_update,
+                updated_timestamp = {}
+        "#, NOW_SQL);
+
+        for todo in todos {
+            // 每项使用自身的更新时间，缺失时使用列表的更新时间
+            let last_update = to_db_time(todo.last_update.as_deref().unwrap_or(fallback_last_update), "lastUpdate")?;
+
+            sqlx::query(&query)
+                .bind(&todo.id)
+                .bind(&todo.parent_id)
+                .bind(&todo.text)
+                .bind(text_crdt_column(todo)?)
+                .bind(todo.completed)
+                .bind(format_utc(to_db_time(&todo.created_at, "createdAt")?))
+                .bind(to_optional_db_time(todo.completed_at.as_deref(), "completedAt")?.map(format_utc))
+                .bind(to_optional_db_time(todo.deadline.as_deref(), "deadline")?.map(format_utc))
+                .bind(todo.is_deleted)
+                .bind(format_utc(last_update))
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("同步待办数据失败: {}", e))?;
+        }
+
+        tx.commit().await
+            .map_err(|e| format!("提交事务失败: {}", e))?;
+
+        Ok(todos.len())
+    }
+
+    async fn download_todos(&self, include_deleted: bool) -> Result<Vec<Todo>, String> {
+        let filter = if include_deleted { "" } else { "WHERE is_deleted = FALSE" };
+        let query = format!("SELECT {} FROM todo_items_sync {} ORDER BY created_timestamp", TODO_COLUMNS, filter);
+
+        let rows = sqlx::query(&query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("下载待办数据失败: {}", e))?;
+
+        Ok(rows.iter().map(todo_from_row).collect())
+    }
+
+    async fn deleted_todos(&self) -> Result<Vec<Todo>, String> {
+        let query = format!(
+            "SELECT {} FROM todo_items_sync WHERE is_deleted = TRUE ORDER BY updated_timestamp DESC",
+            TODO_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("获取已删除待办事项失败: {}", e))?;
+
+        Ok(rows.iter().map(todo_from_row).collect())
+    }
+
+    async fn todos_last_update(&self) -> Result<Option<DateTime<Utc>>, String> {
+        let row = sqlx::query("SELECT MAX(last_update) as last_update FROM todo_items_sync")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| format!("查询远程待办事项lastUpdate失败: {}", e))?;
+
+        Ok(row.get::<Option<String>, _>("last_update").as_deref().and_then(parse_utc))
+    }
+
+    async fn delete_todo(&self, id: &str, time: DateTime<Utc>) -> Result<bool, String> {
+        // 使用递归CTE查找所有子项（UNION 去重，数据中存在环时也能结束）
+        let cascade_delete_query = format!(r#"
+            WITH RECURSIVE todo_hierarchy AS (
+                SELECT id FROM todo_items_sync WHERE id = ? AND is_deleted = FALSE
+                UNION
+                SELECT t.id
+                FROM todo_items_sync t
+                INNER JOIN todo_hierarchy th ON t.parent_id = th.id
+                WHERE t.is_deleted = FALSE
+            )
+            UPDATE todo_items_sync
+            SET is_deleted = TRUE, last_update = ?, updated_timestamp = {}
+            WHERE id IN (SELECT id FROM todo_hierarchy)
+        "#, NOW_SQL);
+
+        let result = sqlx::query(&cascade_delete_query)
+            .bind(id)
+            .bind(format_utc(time))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| format!("级联逻辑删除待办事项失败: {}", e))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn restore_todo(&self, id: &str, scope: RestoreScope, time: DateTime<Utc>) -> Result<bool, String> {
+        // SQLite 的递归 CTE 只能引用自身一次，向上与向下分两个 CTE 查找
+        let hierarchy = match scope {
+            RestoreScope::WithAncestors => r#"
+                WITH RECURSIVE ancestors AS (
+                    SELECT id, parent_id FROM todo_items_sync WHERE id = ? AND is_deleted = TRUE
+                    UNION
+                    SELECT t.id, t.parent_id
+                    FROM todo_items_sync t
+                    INNER JOIN ancestors a ON t.id = a.parent_id
+                    WHERE t.is_deleted = TRUE
+                ),
+                todo_hierarchy AS (
+                    SELECT id FROM ancestors
+                    UNION
+                    SELECT t.id
+                    FROM todo_items_sync t
+                    INNER JOIN todo_hierarchy th ON t.parent_id = th.id
+                    WHERE t.is_deleted = TRUE
+                )
+            "#,
+            RestoreScope::Subtree => r#"
+                WITH RECURSIVE todo_hierarchy AS (
+                    SELECT id FROM todo_items_sync WHERE id = ? AND is_deleted = TRUE
+                    UNION
+                    SELECT t.id
+                    FROM todo_items_sync t
+                    INNER JOIN todo_hierarchy th ON t.parent_id = th.id
+                    WHERE t.is_deleted = TRUE
+                )
+            "#,
+        };
+        let query = format!(
+            "{} UPDATE todo_items_sync SET is_deleted = FALSE, last_update = ?, updated_timestamp = {} WHERE id IN (SELECT id FROM todo_hierarchy)",
+            hierarchy, NOW_SQL
+        );
+
+        let result = sqlx::query(&query)
+            .bind(id)
+            .bind(format_utc(time))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| format!("恢复待办事项失败: {}", e))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn purge_deleted_todos(&self, before: DateTime<Utc>) -> Result<Vec<String>, String> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| format!("开始事务失败: {}", e))?;
+
+        // 与级联删除相同，沿 parent_id 向下查找已删除的子项
+        let purge_query = r#"
+            WITH RECURSIVE todo_hierarchy AS (
+                SELECT id FROM todo_items_sync WHERE is_deleted = TRUE AND last_update < ?
+                UNION
+                SELECT t.id
+                FROM todo_items_sync t
+                INNER JOIN todo_hierarchy th ON t.parent_id = th.id
+                WHERE t.is_deleted = TRUE
+            )
+            SELECT id FROM todo_hierarchy
+        "#;
+
+        let ids: Vec<String> = sqlx::query(purge_query)
+            .bind(format_utc(before))
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| format!("查找待清除的待办事项失败: {}", e))?
+            .iter()
+            .map(|row| row.get("id"))
+            .collect();
+
+        let now = format_utc(Utc::now());
+        for id in &ids {
+            sqlx::query("INSERT OR IGNORE INTO todo_tombstones (id, purged_at) VALUES (?, ?)")
+                .bind(id)
+                .bind(&now)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("写入待办墓碑失败: {}", e))?;
+
+            sqlx::query("DELETE FROM todo_ops WHERE todo_id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("清除待办操作日志失败: {}", e))?;
+
+            sqlx::query("DELETE FROM todo_items_sync WHERE id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("清除已删除待办事项失败: {}", e))?;
+        }
+
+        tx.commit().await
+            .map_err(|e| format!("提交事务失败: {}", e))?;
+
+        Ok(ids)
+    }
+
+    async fn purged_todo_ids(&self) -> Result<Vec<String>, String> {
+        let rows = sqlx::query("SELECT id FROM todo_tombstones")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("获取待办墓碑失败: {}", e))?;
+
+        Ok(rows.iter().map(|row| row.get("id")).collect())
+    }
+
+    async fn upload_ops(&self, ops: &[TodoOp]) -> Result<usize, String> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| format!("开始事务失败: {}", e))?;
+
+        // 按操作 id 去重，重复上传不会产生新行
+        let query = r#"
+            INSERT OR IGNORE INTO todo_ops (op_id, device_id, hlc_wall, hlc_counter, todo_id, patch)
+            VALUES (?, ?, ?, ?, ?, ?)
+        "#;
+
+        let mut inserted = 0;
+        for op in ops {
+            let result = sqlx::query(query)
+                .bind(&op.id)
+                .bind(&op.hlc.device)
+                .bind(op.hlc.wall)
+                .bind(op.hlc.counter as i32)
+                .bind(&op.todo_id)
+                .bind(serialize_patch(op)?)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("上传操作日志失败: {}", e))?;
+            inserted += result.rows_affected() as usize;
+        }
+
+        tx.commit().await
+            .map_err(|e| format!("提交事务失败: {}", e))?;
+
+        Ok(inserted)
+    }
+
+    async fn download_ops(&self, after: i64) -> Result<Vec<(i64, TodoOp)>, String> {
+        let query = r#"
+            SELECT seq, op_id, device_id, hlc_wall, hlc_counter, todo_id, patch
+            FROM todo_ops
+            WHERE seq > ?
+            ORDER BY seq
+        "#;
+
+        let rows = sqlx::query(query)
+            .bind(after)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("下载操作日志失败: {}", e))?;
+
+        rows.iter()
+            .map(|row| {
+                let op = op_from_columns(
+                    row.get("op_id"),
+                    row.get("device_id"),
+                    row.get("hlc_wall"),
+                    row.get("hlc_counter"),
+                    row.get("todo_id"),
+                    row.get::<&str, _>("patch"),
+                )?;
+                Ok((row.get("seq"), op))
+            })
+            .collect()
+    }
+
+    async fn download_settings(&self) -> Result<Map<String, Value>, String> {
+        let query = "SELECT field_name, field_value FROM todo_settings_sync WHERE field_name != 'last_update'";
+
+        let rows = sqlx::query(query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("下载设置数据失败: {}", e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("field_name"), parse_setting_value(row.get("field_value"))))
+            .collect())
+    }
+
+    async fn settings_last_update(&self) -> Result<Option<String>, String> {
+        let query = "SELECT field_value FROM todo_settings_sync WHERE field_name = 'lastUpdate' LIMIT 1";
+
+        let row = sqlx::query(query)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| format!("查询远程设置lastUpdate失败: {}", e))?;
+
+        Ok(row.map(|row| trim_setting_time(&row.get::<String, _>("field_value"))))
+    }
+
+    async fn download_settings_times(&self) -> Result<HashMap<String, String>, String> {
+        let query = "SELECT field_name, last_update FROM todo_settings_sync WHERE field_name != 'last_update'";
+
+        let rows = sqlx::query(query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("下载设置修改时间失败: {}", e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("field_name"), row.get("last_update")))
+            .collect())
+    }
+
+    async fn upload_settings(
+        &self,
+        settings: &Map<String, Value>,
+        field_times: &HashMap<String, String>,
+        last_update: &str,
+    ) -> Result<usize, String> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| format!("开始事务失败: {}", e))?;
+
+        let upsert_query = format!(r#"
+            INSERT INTO todo_settings_sync (update_time, field_name, data_type, field_value, last_update)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (field_name) DO UPDATE SET
+                update_time = excluded.update_time,
+                data_type = excluded.data_type,
+                field_value = excluded.field_value,
+                last_update = excluded.last_update,
+                updated_at = {}
+        "#, NOW_SQL);
+
+        for (key, value) in settings {
+            let field_value = serde_json::to_string(value)
+                .map_err(|e| format!("序列化设置值失败: {}", e))?;
+            let field_time = field_times.get(key).map_or(last_update, String::as_str);
+
+            sqlx::query(&upsert_query)
+                .bind(last_update)
+                .bind(key)
+                .bind(setting_data_type(value))
+                .bind(&field_value)
+                .bind(field_time)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("同步设置数据失败: {}", e))?;
+        }
+
+        tx.commit().await
+            .map_err(|e| format!("提交事务失败: {}", e))?;
+
+        Ok(settings.len())
+    }
+
+    async fn cleanup_duplicate_settings(&self) -> Result<(), String> {
+        sqlx::query(CLEANUP_DUPLICATE_SETTINGS_SQL)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("清理重复设置数据失败: {}", e))
+    }
+
+    async fn check_data_integrity(&self) -> Result<String, String> {
+        let duplicate_rows = sqlx::query(DUPLICATE_SETTINGS_SQL)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("检查设置表重复数据失败: {}", e))?;
+
+        let todo_count: i64 = sqlx::query(ACTIVE_TODO_COUNT_SQL)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| format!("检查待办数据失败: {}", e))?
+            .get("count");
+
+        Ok(integrity_message(duplicate_rows.len(), todo_count))
+    }
+}
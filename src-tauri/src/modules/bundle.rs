@@ -0,0 +1,587 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use tauri::{AppHandle, Manager};
+
+use crate::modules::app::{
+    apply_app_settings, export_profiles, import_profiles, load_app_settings, load_settings_times, lock_settings,
+    AppSettings,
+};
+use crate::modules::crypto::{decrypt, encrypt, EncryptedEnvelope, KeySource};
+use crate::modules::data::{import_todo_data, ImportedData};
+use crate::modules::database::{load_database_config, save_database_config, DatabaseConfig, DatabaseState};
+use crate::modules::migration::{migrate, to_versioned_value, FileKind};
+use crate::modules::storage::write_atomic;
+use crate::modules::store;
+use crate::modules::sync::merge_settings;
+use crate::modules::types::{detach_orphans, format_utc, parse_utc, ArchivedTodos, Todo, TodoFile, TrashFile};
+use crate::modules::window::load_window_config;
+
+/// .tonbak 文件的格式标识
+const BUNDLE_FORMAT: &str = "tonbak";
+
+/// 当前程序写出的 .tonbak 格式版本
+const BUNDLE_VERSION: u32 = 1;
+
+/// 加密凭据在清单中的条目名称
+const CREDENTIALS_ENTRY: &str = "da.da";
+
+/// 加密凭据所用口令的最小长度
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// 导入方式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportMode {
+    /// 用归档内容替换本地数据，替换前先备份待办、归档与回收站
+    Replace,
+    /// 与本地数据合并：待办按 id 取较新的一份，设置逐字段取较新的值，已有的配置方案与数据库配置保持不变
+    Merge,
+}
+
+/// 清单中的一个条目
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleEntry {
+    pub name: String,
+    /// 内容的字节数
+    pub size: usize,
+    /// 内容的 SHA-256（十六进制）
+    pub sha256: String,
+}
+
+/// .tonbak 的清单
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleManifest {
+    pub format: String,
+    pub format_version: u32,
+    pub created_at: String,
+    /// 导出时的 Ton 版本
+    pub app_version: String,
+    pub entries: Vec<BundleEntry>,
+    /// 加密的数据库配置，导出时没有要求包含凭据时为 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<BundleEntry>,
+}
+
+/// .tonbak 文件的内容
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Bundle {
+    manifest: BundleManifest,
+    /// 文件名 -> 原文件格式（带格式版本）的 JSON 文本
+    files: BTreeMap<String, String>,
+    /// 用口令加密的数据库配置（EncryptedEnvelope 的 JSON 文本）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    credentials: Option<String>,
+}
+
+/// 导入结果
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    /// 新增或更新的待办数量
+    pub todos: usize,
+    pub archived: usize,
+    pub trashed: usize,
+    /// 值被导入内容改变的设置字段
+    pub settings: Vec<String>,
+    pub profiles: usize,
+    pub credentials: bool,
+}
+
+/// 已校验并迁移到当前格式的归档内容
+#[derive(Default)]
+struct BundleContents {
+    todos: Option<Value>,
+    archive: Option<Value>,
+    trash: Option<Value>,
+    settings: Option<AppSettings>,
+    profiles: Option<Value>,
+    credentials: Option<DatabaseConfig>,
+}
+
+fn entry(name: &str, contents: &str) -> BundleEntry {
+    BundleEntry {
+        name: name.to_string(),
+        size: contents.len(),
+        sha256: format!("{:x}", Sha256::digest(contents.as_bytes())),
+    }
+}
+
+fn check_entry(expected: &BundleEntry, contents: Option<&String>) -> Result<(), String> {
+    let contents = contents.ok_or_else(|| format!("Bundle is missing {}", expected.name))?;
+    let actual = entry(&expected.name, contents);
+    if actual.size != expected.size || actual.sha256 != expected.sha256 {
+        return Err(format!("Checksum mismatch for {}; the bundle is damaged or was modified", expected.name));
+    }
+    Ok(())
+}
+
+fn require_passphrase(passphrase: Option<&str>) -> Result<&str, String> {
+    match passphrase {
+        Some(passphrase) if passphrase.chars().count() >= MIN_PASSPHRASE_LEN => Ok(passphrase),
+        _ => Err(format!(
+            "A passphrase of at least {} characters is required to protect the credentials",
+            MIN_PASSPHRASE_LEN
+        )),
+    }
+}
+
+/// 读取数据库配置并用口令加密
+async fn export_credentials(passphrase: &str) -> Result<String, String> {
+    // 数据库配置通常用本机密钥文件加密，设置过口令保护的配置用同一口令解密
+    let config = match load_database_config(None).await {
+        Ok(config) => config,
+        Err(_) => load_database_config(Some(passphrase.to_string())).await?,
+    };
+    let config = config.ok_or("There is no database configuration to export")?;
+
+    let json = serde_json::to_string(&to_versioned_value(FileKind::DatabaseConfig, &config)?)
+        .map_err(|e| format!("Failed to serialize database configuration: {}", e))?;
+    let envelope = encrypt(json.as_bytes(), KeySource::Passphrase(passphrase))?;
+    serde_json::to_string(&envelope).map_err(|e| format!("Failed to serialize credentials: {}", e))
+}
+
+/// 把待办、归档、回收站、设置、配置方案与窗口配置导出为一个 .tonbak 文件，返回其清单
+///
+/// 只有 `include_credentials` 为 true 时才附带数据库配置，并用 `passphrase` 加密。
+#[tauri::command]
+pub async fn export_bundle(
+    path: String,
+    include_credentials: bool,
+    passphrase: Option<String>,
+) -> Result<BundleManifest, String> {
+    let credentials = if include_credentials {
+        Some(export_credentials(require_passphrase(passphrase.as_deref())?).await?)
+    } else {
+        None
+    };
+
+    let contents = [
+        ("todos.json", to_versioned_value(FileKind::Todos, &store::read_todos().await?)?),
+        ("stage.json", to_versioned_value(FileKind::Archive, &store::read_archive().await?)?),
+        ("trash.json", to_versioned_value(FileKind::Trash, &store::read_trash().await?)?),
+        ("settings.json", to_versioned_value(FileKind::Settings, &load_app_settings().await?)?),
+        ("window.json", to_versioned_value(FileKind::Window, &load_window_config()?)?),
+        ("profiles.json", export_profiles().await?),
+    ];
+    let mut files = BTreeMap::new();
+    for (name, value) in contents {
+        let json = serde_json::to_string_pretty(&value)
+            .map_err(|e| format!("Failed to serialize {}: {}", name, e))?;
+        files.insert(name.to_string(), json);
+    }
+
+    let manifest = BundleManifest {
+        format: BUNDLE_FORMAT.to_string(),
+        format_version: BUNDLE_VERSION,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        entries: files.iter().map(|(name, contents)| entry(name, contents)).collect(),
+        credentials: credentials.as_deref().map(|contents| entry(CREDENTIALS_ENTRY, contents)),
+    };
+    let bundle = Bundle { manifest: manifest.clone(), files, credentials };
+
+    let json = serde_json::to_string_pretty(&bundle)
+        .map_err(|e| format!("Failed to serialize bundle: {}", e))?;
+    write_atomic(Path::new(&path), json.as_bytes())?;
+
+    Ok(manifest)
+}
+
+/// 读取 .tonbak 文件，检查格式版本以及每个条目的校验和
+fn read_bundle(path: &Path) -> Result<Bundle, String> {
+    let json = std::fs::read_to_string(path).map_err(|e| format!("Failed to read bundle: {}", e))?;
+    let bundle: Bundle = serde_json::from_str(&json).map_err(|e| format!("Not a valid Ton bundle: {}", e))?;
+
+    let manifest = &bundle.manifest;
+    if manifest.format != BUNDLE_FORMAT {
+        return Err(format!("Not a Ton bundle (format {:?})", manifest.format));
+    }
+    if manifest.format_version > BUNDLE_VERSION {
+        return Err(format!(
+            "Bundle has format version {}, but this version of Ton only supports up to {}",
+            manifest.format_version, BUNDLE_VERSION
+        ));
+    }
+
+    for expected in &manifest.entries {
+        check_entry(expected, bundle.files.get(&expected.name))?;
+    }
+    if let Some(name) = bundle.files.keys().find(|name| !manifest.entries.iter().any(|e| &e.name == *name)) {
+        return Err(format!("{} is not listed in the bundle manifest", name));
+    }
+    match (&manifest.credentials, &bundle.credentials) {
+        (Some(expected), contents) => check_entry(expected, contents.as_ref())?,
+        (None, Some(_)) => return Err("Credentials are not listed in the bundle manifest".to_string()),
+        (None, None) => {}
+    }
+
+    Ok(bundle)
+}
+
+/// 读取 .tonbak 文件的清单并校验各条目，不修改任何数据
+#[tauri::command]
+pub fn inspect_bundle(path: String) -> Result<BundleManifest, String> {
+    Ok(read_bundle(Path::new(&path))?.manifest)
+}
+
+/// 取出条目并迁移到当前格式，没有该条目时返回 None
+fn entry_value(bundle: &Bundle, name: &str, kind: FileKind) -> Result<Option<Value>, String> {
+    let Some(json) = bundle.files.get(name) else {
+        return Ok(None);
+    };
+    let value: Value = serde_json::from_str(json).map_err(|e| format!("Bundle {} is not valid JSON: {}", name, e))?;
+    Ok(Some(migrate(kind, value)?.0))
+}
+
+fn parse_entry<T: serde::de::DeserializeOwned>(value: &Value, name: &str) -> Result<T, String> {
+    serde_json::from_value(value.clone()).map_err(|e| format!("Bundle {} is not valid: {}", name, e))
+}
+
+/// 解析并校验归档中的所有内容；提供了口令时解密凭据，没有提供时不导入凭据
+fn parse_bundle(bundle: &Bundle, passphrase: Option<&str>) -> Result<BundleContents, String> {
+    let mut contents = BundleContents {
+        todos: entry_value(bundle, "todos.json", FileKind::Todos)?,
+        archive: entry_value(bundle, "stage.json", FileKind::Archive)?,
+        trash: entry_value(bundle, "trash.json", FileKind::Trash)?,
+        profiles: entry_value(bundle, "profiles.json", FileKind::Profiles)?,
+        ..Default::default()
+    };
+
+    let todos: Option<TodoFile> = contents.todos.as_ref().map(|v| parse_entry(v, "todos.json")).transpose()?;
+    let archive: Option<ArchivedTodos> = contents.archive.as_ref().map(|v| parse_entry(v, "stage.json")).transpose()?;
+    if let Some(trash) = &contents.trash {
+        parse_entry::<TrashFile>(trash, "trash.json")?;
+    }
+    let todo_ids: HashSet<String> = todos.iter().flat_map(|file| file.data.iter().map(|todo| todo.id.clone())).collect();
+    let archive_ids: HashSet<String> = archive.iter().flat_map(|archive| archive.todos().map(|todo| todo.id.clone())).collect();
+    if let Some(todos) = &todos {
+        todos.validate(&archive_ids)?;
+    }
+    if let Some(archive) = &archive {
+        archive.validate(&todo_ids)?;
+    }
+
+    if let Some(settings) = entry_value(bundle, "settings.json", FileKind::Settings)? {
+        contents.settings = Some(AppSettings::parse_lenient(parse_entry::<Map<String, Value>>(&settings, "settings.json")?));
+    }
+
+    if let (Some(credentials), Some(passphrase)) = (&bundle.credentials, passphrase) {
+        let envelope: EncryptedEnvelope = serde_json::from_str(credentials)
+            .map_err(|e| format!("Bundle credentials are not valid: {}", e))?;
+        let plaintext = decrypt(&envelope, KeySource::Passphrase(passphrase))?;
+        let value: Value = serde_json::from_slice(&plaintext)
+            .map_err(|e| format!("Bundle credentials are not valid: {}", e))?;
+        let (value, _) = migrate(FileKind::DatabaseConfig, value)?;
+        contents.credentials = Some(parse_entry(&value, CREDENTIALS_ENTRY)?);
+    }
+
+    Ok(contents)
+}
+
+fn is_newer(todo: &Todo, than: &Todo) -> bool {
+    match (todo.last_update.as_deref().and_then(parse_utc), than.last_update.as_deref().and_then(parse_utc)) {
+        (Some(time), Some(than)) => time > than,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+/// 把导入的待办、归档与回收站合并到本地的 `todos`、`archive` 与 `trash`
+///
+/// 本地已有的待办取 lastUpdate 较新的一份，已在本地归档或删除的不再导入；
+/// 合并后父项既不在列表也不在归档中的待办移到顶层。
+fn merge_todo_data(
+    contents: &BundleContents,
+    mut todos: TodoFile,
+    mut archive: ArchivedTodos,
+    mut trash: TrashFile,
+    summary: &mut ImportSummary,
+) -> Result<ImportedData, String> {
+    let mut known: HashSet<String> = todos.data.iter()
+        .chain(archive.todos())
+        .chain(trash.todos.iter())
+        .map(|todo| todo.id.clone())
+        .collect();
+
+    if let Some(imported) = &contents.archive {
+        let imported: ArchivedTodos = parse_entry(imported, "stage.json")?;
+        let batch_ids: HashSet<String> = archive.batches.iter().map(|batch| batch.id.clone()).collect();
+        for mut batch in imported.batches.into_iter().filter(|batch| !batch_ids.contains(&batch.id)) {
+            batch.todos.retain(|todo| known.insert(todo.id.clone()));
+            if !batch.todos.is_empty() {
+                summary.archived += batch.todos.len();
+                archive.batches.push(batch);
+            }
+        }
+    }
+
+    if let Some(imported) = &contents.todos {
+        let imported: TodoFile = parse_entry(imported, "todos.json")?;
+        let positions: HashMap<String, usize> = todos.data.iter()
+            .enumerate()
+            .map(|(index, todo)| (todo.id.clone(), index))
+            .collect();
+
+        for todo in imported.data {
+            if let Some(&index) = positions.get(&todo.id) {
+                if is_newer(&todo, &todos.data[index]) {
+                    todos.data[index] = todo;
+                    summary.todos += 1;
+                }
+            } else if known.insert(todo.id.clone()) {
+                todos.data.push(todo);
+                summary.todos += 1;
+            }
+        }
+        // 更新后的待办也可能指向本地已删除的父项
+        let archive_ids: HashSet<String> = archive.todos().map(|todo| todo.id.clone()).collect();
+        detach_orphans(&mut todos.data, &archive_ids);
+    }
+
+    if let Some(imported) = &contents.trash {
+        let imported: TrashFile = parse_entry(imported, "trash.json")?;
+        for todo in imported.todos {
+            if known.insert(todo.id.clone()) {
+                trash.todos.push(todo);
+                summary.trashed += 1;
+            }
+        }
+    }
+
+    if summary.todos > 0 {
+        todos.last_update = Some(format_utc(chrono::Utc::now()));
+        todos.source = Some("import".to_string());
+    }
+    Ok(ImportedData {
+        todos: (summary.todos > 0).then_some(todos),
+        archive: (summary.archived > 0).then_some(archive),
+        trash: (summary.trashed > 0).then_some(trash),
+    })
+}
+
+/// 用导入的待办、归档与回收站替换本地数据，没有的部分保持不变
+fn replace_todo_data(contents: &BundleContents, summary: &mut ImportSummary) -> Result<ImportedData, String> {
+    let todos: Option<TodoFile> = contents.todos.as_ref().map(|v| parse_entry(v, "todos.json")).transpose()?;
+    let archive: Option<ArchivedTodos> = contents.archive.as_ref().map(|v| parse_entry(v, "stage.json")).transpose()?;
+    let trash: Option<TrashFile> = contents.trash.as_ref().map(|v| parse_entry(v, "trash.json")).transpose()?;
+
+    summary.todos = todos.as_ref().map_or(0, |todos| todos.data.len());
+    summary.archived = archive.as_ref().map_or(0, |archive| archive.todos().count());
+    summary.trashed = trash.as_ref().map_or(0, |trash| trash.todos.len());
+    Ok(ImportedData { todos, archive, trash })
+}
+
+/// 导入设置：替换时取导入的全部值，合并时只取比本地新的字段；只属于本机的字段保持不变
+async fn import_settings(app: &AppHandle, imported: &AppSettings, mode: ImportMode) -> Result<Vec<String>, String> {
+    let _guard = lock_settings().await;
+    let local = load_app_settings().await?;
+    let imported = imported.to_sync_map();
+    let merge = merge_settings(
+        &local.to_sync_map(),
+        &load_settings_times().await?,
+        local.last_update.as_deref(),
+        &imported,
+        &HashMap::new(),
+        imported.get("lastUpdate").and_then(Value::as_str),
+    );
+
+    let mut fields = merge.download_fields;
+    if mode == ImportMode::Replace {
+        fields.extend(merge.upload_fields.into_iter().filter(|key| imported.contains_key(key)));
+        fields.sort();
+    }
+    if !fields.is_empty() {
+        store::backup_data("settings.json", true).await?;
+        let values = fields.iter()
+            .filter_map(|key| imported.get(key).map(|value| (key.clone(), value.clone())))
+            .collect();
+        apply_app_settings(app, local.with_synced_fields(values)).await?;
+    }
+    Ok(fields)
+}
+
+/// 从 .tonbak 文件导入数据，返回导入结果
+///
+/// 先校验整个文件（格式版本、校验和与各条目内容），有任何问题时不做任何修改。
+/// 文件包含加密凭据时，只有提供了口令才导入数据库配置；合并时不覆盖本地已有的数据库配置。
+/// 窗口位置与尺寸只属于导出的设备，不导入。
+#[tauri::command]
+pub async fn import_bundle(
+    app: AppHandle,
+    path: String,
+    mode: ImportMode,
+    passphrase: Option<String>,
+) -> Result<ImportSummary, String> {
+    let bundle = read_bundle(Path::new(&path))?;
+    let contents = parse_bundle(&bundle, passphrase.as_deref().filter(|p| !p.is_empty()))?;
+    let mut summary = ImportSummary::default();
+
+    // 与同步互斥，避免导入与同步同时改写待办
+    let state = app.state::<DatabaseState>();
+    let _sync_guard = state.sync_lock.lock().await;
+
+    import_todo_data("import", |todos, archive, trash| match mode {
+        ImportMode::Replace => replace_todo_data(&contents, &mut summary),
+        ImportMode::Merge => merge_todo_data(&contents, todos, archive, trash, &mut summary),
+    })
+    .await?;
+
+    // settings.json 是当前方案最新的设置，比 profiles.json 中的副本新，所以后导入
+    if let Some(profiles) = contents.profiles {
+        summary.profiles = import_profiles(&app, profiles, mode).await?;
+    }
+    if let Some(settings) = &contents.settings {
+        summary.settings = import_settings(&app, settings, mode).await?;
+    }
+    if let Some(config) = contents.credentials {
+        let keep_local = mode == ImportMode::Merge && !matches!(load_database_config(None).await, Ok(None));
+        if !keep_local {
+            save_database_config(config, None).await?;
+            summary.credentials = true;
+        }
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::types::ArchiveBatch;
+    use serde_json::json;
+
+    const OLD_TIME: &str = "2024-01-01T00:00:00.000Z";
+    const NEW_TIME: &str = "2024-01-02T00:00:00.000Z";
+
+    fn todo(id: &str, parent: Option<&str>, text: &str, last_update: &str) -> Todo {
+        serde_json::from_value(json!({
+            "id": id,
+            "text": text,
+            "createdAt": OLD_TIME,
+            "parentId": parent,
+            "lastUpdate": last_update,
+        }))
+        .unwrap()
+    }
+
+    fn bundle(files: &[(&str, &str)]) -> Bundle {
+        Bundle {
+            manifest: BundleManifest {
+                format: BUNDLE_FORMAT.to_string(),
+                format_version: BUNDLE_VERSION,
+                created_at: OLD_TIME.to_string(),
+                app_version: "0.0.0".to_string(),
+                entries: files.iter().map(|(name, contents)| entry(name, contents)).collect(),
+                credentials: None,
+            },
+            files: files.iter().map(|(name, contents)| (name.to_string(), contents.to_string())).collect(),
+            credentials: None,
+        }
+    }
+
+    // 把归档写入临时文件后用 read_bundle 读回
+    fn read_back(bundle: &Bundle, name: &str) -> Result<Bundle, String> {
+        let path = std::env::temp_dir().join(format!("ton-bundle-test-{}-{}.tonbak", std::process::id(), name));
+        std::fs::write(&path, serde_json::to_string(bundle).unwrap()).unwrap();
+        let result = read_bundle(&path);
+        let _ = std::fs::remove_file(&path);
+        result
+    }
+
+    #[test]
+    fn read_bundle_accepts_intact_bundle() {
+        let bundle = bundle(&[("todos.json", r#"{"data":[]}"#)]);
+        let read = read_back(&bundle, "intact").unwrap();
+        assert_eq!(read.files, bundle.files);
+    }
+
+    #[test]
+    fn read_bundle_rejects_modified_entries() {
+        let mut modified = bundle(&[("todos.json", r#"{"data":[]}"#)]);
+        modified.files.insert("todos.json".to_string(), r#"{"data":[1]}"#.to_string());
+        assert!(read_back(&modified, "modified").unwrap_err().contains("Checksum mismatch"));
+
+        let mut missing = bundle(&[("todos.json", r#"{"data":[]}"#)]);
+        missing.files.clear();
+        assert!(read_back(&missing, "missing").unwrap_err().contains("missing todos.json"));
+    }
+
+    #[test]
+    fn read_bundle_rejects_unlisted_entries() {
+        let mut unlisted = bundle(&[("todos.json", r#"{"data":[]}"#)]);
+        unlisted.files.insert("extra.json".to_string(), "{}".to_string());
+        assert!(read_back(&unlisted, "unlisted").unwrap_err().contains("extra.json is not listed"));
+
+        let mut credentials = bundle(&[]);
+        credentials.credentials = Some("{}".to_string());
+        assert!(read_back(&credentials, "credentials").unwrap_err().contains("Credentials are not listed"));
+    }
+
+    #[test]
+    fn read_bundle_rejects_newer_format() {
+        let mut newer = bundle(&[]);
+        newer.manifest.format_version = BUNDLE_VERSION + 1;
+        assert!(read_back(&newer, "newer").unwrap_err().contains("only supports up to"));
+    }
+
+    #[test]
+    fn merge_keeps_newer_items_and_skips_local_archive_and_trash() {
+        let local = TodoFile {
+            data: vec![todo("a", None, "local a", NEW_TIME), todo("b", None, "local b", OLD_TIME)],
+            ..Default::default()
+        };
+        let archive = ArchivedTodos {
+            batches: vec![ArchiveBatch { id: "batch".to_string(), archived_at: OLD_TIME.to_string(), todos: vec![todo("archived", None, "", OLD_TIME)] }],
+        };
+        let trash = TrashFile { todos: vec![todo("trashed", None, "", OLD_TIME)] };
+        let imported = json!({ "data": [
+            todo("a", None, "imported a", OLD_TIME),
+            todo("b", None, "imported b", NEW_TIME),
+            todo("archived", None, "imported", NEW_TIME),
+            todo("trashed", None, "imported", NEW_TIME),
+            todo("c", Some("missing"), "imported c", OLD_TIME),
+        ] });
+        let contents = BundleContents { todos: Some(imported), ..Default::default() };
+
+        let mut summary = ImportSummary::default();
+        let merged = merge_todo_data(&contents, local, archive, trash, &mut summary).unwrap();
+
+        assert_eq!(summary.todos, 2);
+        assert!(merged.archive.is_none() && merged.trash.is_none());
+        let todos = merged.todos.unwrap();
+        let texts: Vec<&str> = todos.data.iter().map(|todo| todo.text.as_str()).collect();
+        assert_eq!(texts, ["local a", "imported b", "imported c"]);
+        assert_eq!(todos.data[2].parent_id, None);
+        assert_eq!(todos.source.as_deref(), Some("import"));
+    }
+
+    #[test]
+    fn merge_adds_new_archive_batches_and_trash_items() {
+        let imported_archive = json!({ "batches": [
+            { "id": "batch", "archivedAt": OLD_TIME, "todos": [todo("x", None, "", OLD_TIME)] },
+            { "id": "new", "archivedAt": OLD_TIME, "todos": [todo("y", None, "", OLD_TIME), todo("a", None, "", OLD_TIME)] },
+        ] });
+        let imported_trash = json!({ "todos": [todo("z", None, "", OLD_TIME), todo("y", None, "", OLD_TIME)] });
+        let contents = BundleContents { archive: Some(imported_archive), trash: Some(imported_trash), ..Default::default() };
+        let local = TodoFile { data: vec![todo("a", None, "", OLD_TIME)], ..Default::default() };
+        let archive = ArchivedTodos {
+            batches: vec![ArchiveBatch { id: "batch".to_string(), archived_at: OLD_TIME.to_string(), todos: Vec::new() }],
+        };
+
+        let mut summary = ImportSummary::default();
+        let merged = merge_todo_data(&contents, local, archive, TrashFile::default(), &mut summary).unwrap();
+
+        assert_eq!((summary.todos, summary.archived, summary.trashed), (0, 1, 1));
+        assert!(merged.todos.is_none());
+        let archive = merged.archive.unwrap();
+        assert_eq!(archive.batches.len(), 2);
+        assert_eq!(archive.batches[1].todos.len(), 1);
+        assert_eq!(merged.trash.unwrap().todos[0].id, "z");
+    }
+}
@@ -0,0 +1,386 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+
+// 字符 id：Lamport 计数器 + 站点在站点表中的下标，先比较计数器再比较站点
+//
+// 站点表按名称排序，下标的顺序与站点名的顺序一致，不同副本比较的结果相同。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+struct CharId(u64, u32);
+
+// 序列中的一个字符，删除后保留为墓碑
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CharNode {
+    #[serde(rename = "i")]
+    id: CharId,
+    // 插入时左侧相邻字符的 id，位于开头时为 None
+    #[serde(rename = "o")]
+    origin: Option<CharId>,
+    #[serde(rename = "c")]
+    ch: char,
+    #[serde(rename = "d", default, skip_serializing_if = "std::ops::Not::not")]
+    deleted: bool,
+}
+
+// 基于 RGA（Replicated Growable Array）的文本 CRDT
+//
+// 并发编辑在字符级别合并，任意顺序合并的结果一致。`root` 标识创建时的初始文本，
+// 只有同一初始文本派生出的副本才能合并。
+//
+// 每台设备保存副本时确认自己已看到的时钟；所有已知设备都看到了副本中的全部修改后，
+// 删除的字符不会再被引用，也不会影响新字符的位置，墓碑随之回收。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextCrdt {
+    root: String,
+    // 出现过的站点名（按名称排序），字符 id 只记录下标
+    sites: Vec<String>,
+    nodes: Vec<CharNode>,
+    // 副本已看到的每个站点的最大计数器（与站点表对应），删除也占用计数器
+    clock: Vec<u64>,
+    // 各设备（站点下标）确认已看到的时钟
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    peers: BTreeMap<u32, Vec<u64>>,
+}
+
+// 由文本内容确定的站点名，不同设备对同一文本做同样的转换会得到相同的字符 id
+fn text_site(prefix: &str, text: &str) -> String {
+    let digest = Sha256::digest(text.as_bytes());
+    let hash: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}:{}", prefix, hash)
+}
+
+// 把按旧站点表排列的时钟放到新站点表的对应位置
+fn spread(clock: &[u64], remap: &[u32], len: usize) -> Vec<u64> {
+    let mut spread = vec![0; len];
+    for (index, counter) in clock.iter().enumerate() {
+        spread[remap[index] as usize] = *counter;
+    }
+    spread
+}
+
+// 逐站点取两个时钟中较大的计数器
+fn merge_clock(into: &mut [u64], from: &[u64], remap: &[u32]) {
+    for (index, counter) in from.iter().enumerate() {
+        let own = &mut into[remap[index] as usize];
+        *own = (*own).max(*counter);
+    }
+}
+
+impl TextCrdt {
+    // 由纯文本创建，同一文本在任何设备上得到相同的结果
+    pub fn from_text(text: &str) -> Self {
+        let root = text_site("init", text);
+        let mut crdt = TextCrdt {
+            root: root.clone(),
+            sites: Vec::new(),
+            nodes: Vec::new(),
+            clock: Vec::new(),
+            peers: BTreeMap::new(),
+        };
+        crdt.edit(text, &root);
+        crdt
+    }
+
+    // 当前可见文本
+    pub fn text(&self) -> String {
+        self.nodes.iter().filter(|node| !node.deleted).map(|node| node.ch).collect()
+    }
+
+    // 两个副本是否来自同一初始文本
+    pub fn compatible(&self, other: &TextCrdt) -> bool {
+        self.root == other.root
+    }
+
+    fn site_position(&self, name: &str) -> u32 {
+        self.sites.partition_point(|site| site.as_str() < name) as u32
+    }
+
+    // 把站点加入站点表，已有字符 id、时钟和确认记录中的下标随之调整
+    fn add_sites<'a>(&mut self, names: impl IntoIterator<Item = &'a str>) {
+        let mut sites = self.sites.clone();
+        for name in names {
+            if let Err(index) = sites.binary_search_by(|site| site.as_str().cmp(name)) {
+                sites.insert(index, name.to_string());
+            }
+        }
+        if sites.len() == self.sites.len() {
+            return;
+        }
+
+        let remap: Vec<u32> = self.sites.iter().map(|name| sites.partition_point(|site| site < name) as u32).collect();
+        let relocate = |id: &mut CharId| id.1 = remap[id.1 as usize];
+        for node in &mut self.nodes {
+            relocate(&mut node.id);
+            if let Some(origin) = &mut node.origin {
+                relocate(origin);
+            }
+        }
+        self.clock = spread(&self.clock, &remap, sites.len());
+        self.peers = std::mem::take(&mut self.peers)
+            .into_iter()
+            .map(|(site, clock)| (remap[site as usize], spread(&clock, &remap, sites.len())))
+            .collect();
+        self.sites = sites;
+    }
+
+    // 站点在站点表中的下标，不存在时加入
+    fn intern(&mut self, name: &str) -> u32 {
+        self.add_sites([name]);
+        self.site_position(name)
+    }
+
+    // 副本是否已看到过这个 id 对应的修改
+    fn covers(&self, id: CharId) -> bool {
+        self.clock.get(id.1 as usize).is_some_and(|counter| id.0 <= *counter)
+    }
+
+    fn next_counter(&self) -> u64 {
+        self.clock.iter().max().copied().unwrap_or(0) + 1
+    }
+
+    fn position(&self, id: &CharId) -> Option<usize> {
+        self.nodes.iter().position(|node| &node.id == id)
+    }
+
+    // 在 origin 之后插入节点：跳过 id 更大的节点（它们及其后代排在前面）
+    fn integrate(&mut self, node: CharNode) {
+        let mut index = match &node.origin {
+            Some(origin) => match self.position(origin) {
+                Some(position) => position + 1,
+                None => self.nodes.len(),
+            },
+            None => 0,
+        };
+        while index < self.nodes.len() && self.nodes[index].id > node.id {
+            index += 1;
+        }
+        self.nodes.insert(index, node);
+    }
+
+    // 把可见文本改为 `text`：保留公共前后缀，删除并插入中间部分，新字符使用 `site`
+    pub fn edit(&mut self, text: &str, site: &str) {
+        let site = self.intern(site);
+        let visible: Vec<usize> = (0..self.nodes.len()).filter(|i| !self.nodes[*i].deleted).collect();
+        let old: Vec<char> = visible.iter().map(|i| self.nodes[*i].ch).collect();
+        let new: Vec<char> = text.chars().collect();
+
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut counter = self.next_counter();
+        let removed = &visible[prefix..old.len() - suffix];
+        if !removed.is_empty() {
+            for i in removed {
+                self.nodes[*i].deleted = true;
+            }
+            // 删除也占用一个计数器，其他设备的确认据此表明已看到这次删除
+            self.clock[site as usize] = counter;
+            counter += 1;
+        }
+
+        let mut origin = prefix.checked_sub(1).map(|i| self.nodes[visible[i]].id);
+        for ch in &new[prefix..new.len() - suffix] {
+            let id = CharId(counter, site);
+            self.integrate(CharNode { id, origin: origin.take(), ch: *ch, deleted: false });
+            self.clock[site as usize] = counter;
+            origin = Some(id);
+            counter += 1;
+        }
+    }
+
+    // 文本被不了解 CRDT 的程序直接修改过时，把差异补到副本中（结果只取决于副本与文本）
+    pub fn reconcile(&mut self, text: &str) {
+        if self.text() != text {
+            let site = text_site("plain", text);
+            self.edit(text, &site);
+        }
+    }
+
+    // 记录设备 `device` 已看到副本中的全部修改，所有已知设备都看到后回收墓碑
+    pub fn acknowledge(&mut self, device: &str) {
+        let device = self.intern(device);
+        self.peers.insert(device, self.clock.clone());
+        self.compact();
+    }
+
+    // 合并另一个副本的插入、删除和确认记录
+    pub fn merge(&mut self, other: &TextCrdt) {
+        self.add_sites(other.sites.iter().map(String::as_str));
+        let remap: Vec<u32> = other.sites.iter().map(|name| self.site_position(name)).collect();
+        let local = |id: CharId| CharId(id.0, remap[id.1 as usize]);
+        let theirs: HashSet<CharId> = other.nodes.iter().map(|node| local(node.id)).collect();
+
+        // 对方已看到却不存在的字符已在对方回收，回收的只会是删除的字符
+        let other_clock = spread(&other.clock, &remap, self.sites.len());
+        for node in &mut self.nodes {
+            let collected = other_clock[node.id.1 as usize] >= node.id.0 && !theirs.contains(&node.id);
+            node.deleted |= collected;
+        }
+
+        // 按计数器顺序集成，保证左侧相邻字符先于自身出现；本副本已回收的字符作为墓碑放回，
+        // 对方在其后插入的字符仍能找到位置
+        let known: HashSet<CharId> = self.nodes.iter().map(|node| node.id).collect();
+        let mut missing: Vec<CharNode> = other
+            .nodes
+            .iter()
+            .map(|node| CharNode {
+                id: local(node.id),
+                origin: node.origin.map(local),
+                ch: node.ch,
+                deleted: node.deleted || self.covers(local(node.id)),
+            })
+            .filter(|node| !known.contains(&node.id))
+            .collect();
+        missing.sort_by_key(|node| node.id);
+        for node in missing {
+            self.integrate(node);
+        }
+
+        let deleted: HashSet<CharId> = other.nodes.iter().filter(|node| node.deleted).map(|node| local(node.id)).collect();
+        for node in &mut self.nodes {
+            if deleted.contains(&node.id) {
+                node.deleted = true;
+            }
+        }
+
+        merge_clock(&mut self.clock, &other.clock, &remap);
+        let len = self.sites.len();
+        for (device, clock) in &other.peers {
+            let own = self.peers.entry(remap[*device as usize]).or_insert_with(|| vec![0; len]);
+            merge_clock(own, clock, &remap);
+        }
+        self.compact();
+    }
+
+    // 所有已知设备都确认看到了副本中的全部修改时移除墓碑
+    fn compact(&mut self) {
+        let stable = !self.peers.is_empty()
+            && self.peers.values().all(|seen| {
+                self.clock
+                    .iter()
+                    .enumerate()
+                    .all(|(index, counter)| seen.get(index).is_some_and(|seen| seen >= counter))
+            });
+        if stable {
+            self.nodes.retain(|node| !node.deleted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edited(base: &TextCrdt, text: &str, site: &str) -> TextCrdt {
+        let mut crdt = base.clone();
+        crdt.edit(text, site);
+        crdt
+    }
+
+    fn merged(replicas: &[&TextCrdt]) -> TextCrdt {
+        let mut result = replicas[0].clone();
+        for replica in &replicas[1..] {
+            result.merge(replica);
+        }
+        result
+    }
+
+    fn tombstones(crdt: &TextCrdt) -> usize {
+        crdt.nodes.iter().filter(|node| node.deleted).count()
+    }
+
+    #[test]
+    fn from_text_is_deterministic() {
+        assert_eq!(TextCrdt::from_text("hello"), TextCrdt::from_text("hello"));
+        assert!(!TextCrdt::from_text("hello").compatible(&TextCrdt::from_text("world")));
+    }
+
+    #[test]
+    fn concurrent_edits_converge() {
+        let base = TextCrdt::from_text("hello");
+        let a = edited(&base, "hello world", "device-a");
+        let b = edited(&base, "hi hello", "device-b");
+        let c = edited(&base, "hell", "device-c");
+
+        let orders = [[&a, &b, &c], [&b, &c, &a], [&c, &a, &b], [&c, &b, &a]];
+        let results: Vec<TextCrdt> = orders.iter().map(|order| merged(order)).collect();
+        assert_eq!(results[0].text(), "hi hell world");
+        for result in &results[1..] {
+            assert_eq!(result, &results[0]);
+        }
+    }
+
+    #[test]
+    fn merge_is_commutative() {
+        let base = TextCrdt::from_text("abc");
+        let a = edited(&base, "abXc", "device-a");
+        let b = edited(&base, "abYc", "device-b");
+
+        let ab = merged(&[&a, &b]);
+        let ba = merged(&[&b, &a]);
+        assert_eq!(ab, ba);
+        assert!(ab.text() == "abXYc" || ab.text() == "abYXc");
+    }
+
+    #[test]
+    fn merge_is_idempotent() {
+        let base = TextCrdt::from_text("abc");
+        let a = edited(&base, "ac", "device-a");
+        let b = edited(&base, "abcd", "device-b");
+
+        let once = merged(&[&a, &b]);
+        assert_eq!(merged(&[&once, &once]), once);
+        assert_eq!(merged(&[&once, &a]), once);
+        assert_eq!(merged(&[&once, &b]), once);
+        assert_eq!(once.text(), "acd");
+    }
+
+    #[test]
+    fn sites_are_stored_once() {
+        let mut crdt = TextCrdt::from_text("");
+        crdt.edit("a long line typed on one device", "0b8c1f0e-5d7e-4a38-9d3f-7c1e2b6a9f01");
+        assert_eq!(crdt.sites.len(), 2);
+        assert!(crdt.nodes.iter().all(|node| node.id.1 < 2));
+    }
+
+    #[test]
+    fn tombstones_are_kept_until_all_peers_have_seen_them() {
+        let mut a = TextCrdt::from_text("hello");
+        a.acknowledge("device-a");
+        let mut b = a.clone();
+        b.acknowledge("device-b");
+        a.merge(&b);
+
+        a.edit("help", "device-a");
+        a.acknowledge("device-a");
+        assert_eq!(tombstones(&a), 2);
+
+        b.merge(&a);
+        b.acknowledge("device-b");
+        assert_eq!(tombstones(&b), 0);
+        a.merge(&b);
+        assert_eq!(tombstones(&a), 0);
+        assert_eq!(a, b);
+        assert_eq!(a.text(), "help");
+    }
+
+    #[test]
+    fn collected_tombstones_stay_deleted() {
+        let base = TextCrdt::from_text("hello");
+        let mut a = base.clone();
+        a.edit("help", "device-a");
+        a.acknowledge("device-a");
+        assert_eq!(tombstones(&a), 0);
+
+        // 未确认过的旧副本合并后，回收的字符不会重新出现，对方的插入仍在原位置
+        let stale = edited(&base, "hello!", "device-b");
+        assert_eq!(merged(&[&a, &stale]).text(), "help!");
+        assert_eq!(merged(&[&stale, &a]).text(), "help!");
+        assert_eq!(merged(&[&a, &stale]), merged(&[&stale, &a]));
+    }
+}
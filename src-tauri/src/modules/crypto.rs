@@ -0,0 +1,240 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose, Engine as _};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::modules::storage::write_atomic;
+
+// 加密信封格式版本
+const ENVELOPE_VERSION: u32 = 1;
+
+// 使用的 AEAD 算法
+const CIPHER: &str = "xchacha20poly1305";
+
+// 口令派生密钥的默认参数（Argon2id，OWASP 推荐的最低配置）
+const KDF_MEMORY_KIB: u32 = 19 * 1024;
+const KDF_ITERATIONS: u32 = 2;
+const KDF_PARALLELISM: u32 = 1;
+
+// 口令派生参数，随密文一起保存
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KdfParams {
+    pub algorithm: String,
+    pub salt: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+// 加密后的文件内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptedEnvelope {
+    pub format_version: u32,
+    pub cipher: String,
+    // 为 None 时密钥来自本机密钥文件
+    pub kdf: Option<KdfParams>,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+// 加密密钥的来源
+pub enum KeySource<'a> {
+    // 用户口令，通过 Argon2id 派生密钥
+    Passphrase(&'a str),
+    // 本机随机生成并保存的密钥文件
+    KeyFile(&'a Path),
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, String> {
+    general_purpose::STANDARD
+        .decode(value)
+        .map_err(|e| format!("加密数据的 {} 字段无效: {}", field, e))
+}
+
+// 使用 Argon2id 从口令派生 32 字节密钥
+fn derive_key(passphrase: &str, kdf: &KdfParams) -> Result<[u8; 32], String> {
+    if kdf.algorithm != "argon2id" {
+        return Err(format!("不支持的密钥派生算法: {}", kdf.algorithm));
+    }
+
+    let salt = decode("salt", &kdf.salt)?;
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
+        .map_err(|e| format!("密钥派生参数无效: {}", e))?;
+
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| format!("密钥派生失败: {}", e))?;
+
+    Ok(key)
+}
+
+// 读取本机密钥文件，不存在时生成新密钥
+fn load_or_create_key_file(path: &Path) -> Result<[u8; 32], String> {
+    if path.exists() {
+        let encoded = std::fs::read_to_string(path)
+            .map_err(|e| format!("读取密钥文件失败: {}", e))?;
+        let bytes = decode("key", encoded.trim())?;
+        return bytes.try_into().map_err(|_| "密钥文件长度无效".to_string());
+    }
+
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    write_atomic(path, general_purpose::STANDARD.encode(key).as_bytes())?;
+
+    // 密钥文件只允许当前用户读写
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600));
+    }
+
+    Ok(key)
+}
+
+// 附加认证数据：算法与派生参数被篡改时解密同样会失败
+fn associated_data(cipher: &str, kdf: &Option<KdfParams>) -> Result<Vec<u8>, String> {
+    let kdf_json = serde_json::to_string(kdf)
+        .map_err(|e| format!("序列化密钥派生参数失败: {}", e))?;
+    Ok(format!("ton:{}:{}", cipher, kdf_json).into_bytes())
+}
+
+// 加密数据
+pub fn encrypt(plaintext: &[u8], key_source: KeySource) -> Result<EncryptedEnvelope, String> {
+    let (key, kdf) = match key_source {
+        KeySource::Passphrase(passphrase) => {
+            let mut salt = [0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            let kdf = KdfParams {
+                algorithm: "argon2id".to_string(),
+                salt: general_purpose::STANDARD.encode(salt),
+                memory_kib: KDF_MEMORY_KIB,
+                iterations: KDF_ITERATIONS,
+                parallelism: KDF_PARALLELISM,
+            };
+            (derive_key(passphrase, &kdf)?, Some(kdf))
+        }
+        KeySource::KeyFile(path) => (load_or_create_key_file(path)?, None),
+    };
+
+    let cipher = XChaCha20Poly1305::new(&key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let aad = associated_data(CIPHER, &kdf)?;
+
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad: &aad })
+        .map_err(|e| format!("加密失败: {}", e))?;
+
+    Ok(EncryptedEnvelope {
+        format_version: ENVELOPE_VERSION,
+        cipher: CIPHER.to_string(),
+        kdf,
+        nonce: general_purpose::STANDARD.encode(nonce),
+        ciphertext: general_purpose::STANDARD.encode(ciphertext),
+    })
+}
+
+// 解密数据，口令错误或内容被篡改时返回错误
+pub fn decrypt(envelope: &EncryptedEnvelope, key_source: KeySource) -> Result<Vec<u8>, String> {
+    if envelope.format_version > ENVELOPE_VERSION {
+        return Err(format!("不支持的加密格式版本: {}", envelope.format_version));
+    }
+    if envelope.cipher != CIPHER {
+        return Err(format!("不支持的加密算法: {}", envelope.cipher));
+    }
+
+    let key = match (&envelope.kdf, key_source) {
+        (Some(kdf), KeySource::Passphrase(passphrase)) => derive_key(passphrase, kdf)?,
+        (Some(_), KeySource::KeyFile(_)) => return Err("该文件已使用口令加密，请提供口令".to_string()),
+        (None, KeySource::KeyFile(path)) => {
+            if !path.exists() {
+                return Err("找不到本机密钥文件，无法解密".to_string());
+            }
+            load_or_create_key_file(path)?
+        }
+        (None, KeySource::Passphrase(_)) => return Err("该文件未使用口令加密，无需提供口令".to_string()),
+    };
+
+    let nonce_bytes = decode("nonce", &envelope.nonce)?;
+    if nonce_bytes.len() != 24 {
+        return Err("加密数据的 nonce 长度无效".to_string());
+    }
+    let nonce = XNonce::from_slice(&nonce_bytes);
+    let ciphertext = decode("ciphertext", &envelope.ciphertext)?;
+    let aad = associated_data(&envelope.cipher, &envelope.kdf)?;
+
+    XChaCha20Poly1305::new(&key.into())
+        .decrypt(nonce, Payload { msg: &ciphertext, aad: &aad })
+        .map_err(|_| "解密失败：文件已被篡改或口令错误".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"{\"password\":\"secret\"}";
+
+    // 每个测试使用各自的临时密钥文件
+    fn key_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ton-crypto-test-{}-{}.key", std::process::id(), name))
+    }
+
+    // 翻转密文的第一个字节
+    fn tamper(envelope: &mut EncryptedEnvelope) {
+        let mut ciphertext = decode("ciphertext", &envelope.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        envelope.ciphertext = general_purpose::STANDARD.encode(ciphertext);
+    }
+
+    #[test]
+    fn key_file_round_trip_and_tamper_detection() {
+        let path = key_path("round-trip");
+        let envelope = encrypt(SECRET, KeySource::KeyFile(&path)).unwrap();
+        assert!(envelope.kdf.is_none());
+        assert_eq!(decrypt(&envelope, KeySource::KeyFile(&path)).unwrap(), SECRET);
+
+        let mut tampered = envelope.clone();
+        tamper(&mut tampered);
+        assert!(decrypt(&tampered, KeySource::KeyFile(&path)).is_err());
+
+        let _ = std::fs::remove_file(&path);
+        assert!(decrypt(&envelope, KeySource::KeyFile(&path)).is_err());
+    }
+
+    #[test]
+    fn passphrase_round_trip_and_wrong_passphrase() {
+        let envelope = encrypt(SECRET, KeySource::Passphrase("correct horse")).unwrap();
+        assert_eq!(decrypt(&envelope, KeySource::Passphrase("correct horse")).unwrap(), SECRET);
+        assert!(decrypt(&envelope, KeySource::Passphrase("wrong horse")).is_err());
+        assert!(decrypt(&envelope, KeySource::KeyFile(&key_path("unused"))).is_err());
+
+        // 派生参数属于附加认证数据，被改动时同样无法解密
+        let mut tampered = envelope.clone();
+        if let Some(kdf) = &mut tampered.kdf {
+            kdf.iterations += 1;
+        }
+        assert!(decrypt(&tampered, KeySource::Passphrase("correct horse")).is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_envelopes() {
+        let path = key_path("unsupported");
+        let envelope = encrypt(SECRET, KeySource::KeyFile(&path)).unwrap();
+
+        let mut newer = envelope.clone();
+        newer.format_version = ENVELOPE_VERSION + 1;
+        assert!(decrypt(&newer, KeySource::KeyFile(&path)).is_err());
+
+        let mut other_cipher = envelope.clone();
+        other_cipher.cipher = "aes-256-gcm".to_string();
+        assert!(decrypt(&other_cipher, KeySource::KeyFile(&path)).is_err());
+
+        assert!(decrypt(&envelope, KeySource::Passphrase("correct horse")).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
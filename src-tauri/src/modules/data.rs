@@ -1,110 +1,848 @@
-use serde_json::Value;
+use crate::modules::backend::RestoreScope;
+use crate::modules::crdt::TextCrdt;
+use crate::modules::history::{HistoryStack, HISTORY_LIMIT};
+use crate::modules::oplog::{change_ops, device_id, snapshot_ops, TodoOp, OPLOG_SOURCE};
+use crate::modules::store;
+use crate::modules::types::{
+    detach_orphans, format_utc, new_id, ArchiveBatch, ArchivedTodos, HistoryEntry, Todo, TodoDelta, TodoFile,
+    TrashFile,
+};
+use chrono::Utc;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Mutex;
 
-/// 保存待办事项数据
-#[tauri::command]
-pub fn save_todos(todos: Value) -> Result<(), String> {
-    let data_dir = dirs::data_dir()
-        .ok_or("Failed to get data directory")?
-        .join("Ton")
-        .join("data");
+/// 串行化待办列表的读取-修改-写入，避免并发的保存互相覆盖或在操作日志中记录错误的差异
+static TODO_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// 收集一组待办的 id
+fn collect_ids<'a>(todos: impl IntoIterator<Item = &'a Todo>) -> HashSet<String> {
+    todos.into_iter().map(|todo| todo.id.clone()).collect()
+}
+
+/// 从 `roots` 出发沿父项引用向下收集后代（包括 `roots` 本身），`links` 为 (id, parentId)
+pub fn collect_descendants(links: &[(&str, Option<&str>)], roots: Vec<String>) -> HashSet<String> {
+    let mut found: HashSet<String> = roots.into_iter().collect();
+    loop {
+        let children: Vec<String> = links
+            .iter()
+            .filter(|(id, _)| !found.contains(*id))
+            .filter(|(_, parent)| parent.is_some_and(|parent| found.contains(parent)))
+            .map(|(id, _)| id.to_string())
+            .collect();
+        if children.is_empty() {
+            return found;
+        }
+        found.extend(children);
+    }
+}
+
+/// 从 `roots` 出发沿 parentId 向下收集 `todos` 中的后代（包括 `roots` 本身）
+pub fn collect_subtree(todos: &[Todo], roots: Vec<String>) -> HashSet<String> {
+    let links: Vec<(&str, Option<&str>)> = todos
+        .iter()
+        .map(|todo| (todo.id.as_str(), todo.parent_id.as_deref()))
+        .collect();
+    collect_descendants(&links, roots)
+}
+
+/// 校验把 `id` 放到 `parent_id` 下：父项须在列表或归档中，且不能是该项自身或其后代
+fn check_parent(
+    tree: &HashMap<String, Option<String>>,
+    archived_ids: &HashSet<String>,
+    id: &str,
+    parent_id: &str,
+) -> Result<(), String> {
+    if !tree.contains_key(parent_id) && !archived_ids.contains(parent_id) {
+        return Err(format!("parentId {:?} does not refer to an existing todo", parent_id));
+    }
 
-    std::fs::create_dir_all(&data_dir)
-        .map_err(|e| format!("Failed to create data directory: {}", e))?;
+    let mut current = Some(parent_id);
+    while let Some(ancestor) = current {
+        if ancestor == id {
+            return Err(format!("Cannot move todo {:?} under itself or one of its descendants", id));
+        }
+        current = tree.get(ancestor).and_then(|parent| parent.as_deref());
+    }
+
+    Ok(())
+}
+
+/// 维护每个待办的文本副本
+///
+/// 传入的文本与副本不一致时，差异作为本设备的编辑写入副本（没有副本时使用已保存的副本），
+/// 再合并已保存的副本，保留其间同步进来的修改。最后确认本设备已看到副本中的修改，用于回收墓碑。
+fn update_text_crdts(todos: &mut [Todo], previous: &[Todo]) -> Result<(), String> {
+    let previous: HashMap<&str, &Todo> = previous.iter().map(|todo| (todo.id.as_str(), todo)).collect();
+    let device = device_id()?;
 
-    let todo_file = data_dir.join("todos.json");
-    let json_str = serde_json::to_string_pretty(&todos)
-        .map_err(|e| format!("Failed to serialize todos: {}", e))?;
+    for todo in todos {
+        let old = previous.get(todo.id.as_str());
+        let old_crdt = old.and_then(|old| old.text_crdt.as_ref());
 
-    std::fs::write(todo_file, json_str).map_err(|e| format!("Failed to write todo file: {}", e))?;
+        let mut crdt = todo.text_crdt.take()
+            .or_else(|| old_crdt.cloned())
+            .unwrap_or_else(|| TextCrdt::from_text(old.map_or(&todo.text, |old| &old.text)));
+        if crdt.text() != todo.text {
+            crdt.edit(&todo.text, &device);
+        }
+        if let Some(old_crdt) = old_crdt.filter(|old_crdt| crdt.compatible(old_crdt)) {
+            crdt.merge(old_crdt);
+        }
+        crdt.acknowledge(&device);
+
+        todo.text = crdt.text();
+        todo.text_crdt = Some(crdt);
+    }
+
+    Ok(())
+}
 
+/// 操作日志为空时先为当前列表记录完整快照，之后只需记录单项的修改
+async fn ensure_oplog() -> Result<(), String> {
+    if store::oplog_is_empty().await? {
+        let todos = store::read_todos().await?.data;
+        let ops = snapshot_ops(&todos).await
+            .map_err(|e| format!("Failed to record todo changes: {}", e))?;
+        store::append_ops(&ops).await?;
+    }
     Ok(())
 }
 
+/// 这次修改要写入操作日志的操作
+async fn record_ops(before: &[Todo], after: &[Todo]) -> Result<Vec<TodoOp>, String> {
+    change_ops(before, after).await
+        .map_err(|e| format!("Failed to record todo changes: {}", e))
+}
+
+/// 列表中所有待办的 id（按列表顺序）
+fn todo_order<'a>(todos: impl IntoIterator<Item = &'a Todo>) -> Vec<String> {
+    todos.into_iter().map(|todo| todo.id.clone()).collect()
+}
+
+/// 替换整个列表这一步在撤销历史中的记录：只保存有变化的待办，顺序变化时保存前后顺序
+fn list_history_entry(action: &str, previous: &[Todo], todos: &[Todo]) -> HistoryEntry {
+    let old: HashMap<&str, &Todo> = previous.iter().map(|todo| (todo.id.as_str(), todo)).collect();
+    let new: HashMap<&str, &Todo> = todos.iter().map(|todo| (todo.id.as_str(), todo)).collect();
+    let changed = |todo: &&Todo, other: &HashMap<&str, &Todo>| other.get(todo.id.as_str()) != Some(todo);
+
+    let entry = HistoryEntry::new(
+        action,
+        previous.iter().filter(|todo| changed(todo, &new)).cloned().collect(),
+        todos.iter().filter(|todo| changed(todo, &old)).cloned().collect(),
+    );
+    let kept_before: Vec<&str> = previous.iter().map(|todo| todo.id.as_str()).filter(|id| new.contains_key(id)).collect();
+    let kept_after: Vec<&str> = todos.iter().map(|todo| todo.id.as_str()).filter(|id| old.contains_key(id)).collect();
+    if kept_before == kept_after {
+        entry
+    } else {
+        entry.with_order(todo_order(previous), todo_order(todos))
+    }
+}
+
+/// 替换整个待办列表（调用方持有 `TODO_LOCK`），`action` 不为 None 时记入撤销历史
+///
+/// 由其他设备的操作推导出的列表（来源为 oplog）不再记录新的操作，而是把这些操作 `remote_ops`
+/// 与列表一起写入日志。
+async fn save_todo_list(mut todos: TodoFile, action: Option<&str>, remote_ops: &[TodoOp]) -> Result<(), String> {
+    let archive_ids = store::archived_ids().await?;
+    todos.validate(&archive_ids)?;
+    let previous = store::read_todos().await?;
+    update_text_crdts(&mut todos.data, &previous.data)?;
+
+    ensure_oplog().await?;
+    let ops = if todos.source.as_deref() == Some(OPLOG_SOURCE) {
+        remote_ops.to_vec()
+    } else {
+        record_ops(&previous.data, &todos.data).await?
+    };
+    let history = action.map(|action| list_history_entry(action, &previous.data, &todos.data));
+    // 重新出现在列表中的待办（恢复或同步下载）同时从回收站移除
+    store::write_todos(&todos, &ops, history.as_ref()).await
+}
+
+/// 导入后的待办数据，为 None 的部分保持不变
+pub struct ImportedData {
+    pub todos: Option<TodoFile>,
+    pub archive: Option<ArchivedTodos>,
+    pub trash: Option<TrashFile>,
+}
+
+/// 导入（或从备份恢复）待办、归档与回收站：`build` 由本地的三者得到导入后的数据
+///
+/// 在 `TODO_LOCK` 下读取、校验并在一个事务中写入，任何一步失败时不做任何修改；
+/// 列表的修改与其他保存一样记入操作日志，并作为 `action` 记入撤销历史。
+pub async fn import_todo_data<F>(action: &str, build: F) -> Result<(), String>
+where
+    F: FnOnce(TodoFile, ArchivedTodos, TrashFile) -> Result<ImportedData, String>,
+{
+    let _guard = TODO_LOCK.lock().await;
+
+    let previous = store::read_todos().await?;
+    let ImportedData { mut todos, archive, trash } =
+        build(previous.clone(), store::read_archive().await?, store::read_trash().await?)?;
+
+    let archive_ids = match &archive {
+        Some(archive) => collect_ids(archive.todos()),
+        None => store::archived_ids().await?,
+    };
+    let todo_ids = collect_ids(&todos.as_ref().unwrap_or(&previous).data);
+    if let Some(todos) = &todos {
+        todos.validate(&archive_ids)?;
+    }
+    if let Some(archive) = &archive {
+        archive.validate(&todo_ids)?;
+    }
+
+    let ops = match &mut todos {
+        Some(todos) => {
+            update_text_crdts(&mut todos.data, &previous.data)?;
+            ensure_oplog().await?;
+            record_ops(&previous.data, &todos.data).await?
+        }
+        None => Vec::new(),
+    };
+    let history = todos.as_ref().map(|todos| list_history_entry(action, &previous.data, &todos.data));
+    store::write_imported_data(todos.as_ref(), archive.as_ref(), trash.as_ref(), &ops, history.as_ref()).await
+}
+
+/// 相对已保存列表新增或修改过的待办以 `now` 作为修改时间
+fn stamp_changed(todos: &mut [Todo], previous: &[Todo], now: &str) {
+    let old: HashMap<&str, &Todo> = previous.iter().map(|todo| (todo.id.as_str(), todo)).collect();
+    for todo in todos {
+        let changed = match old.get(todo.id.as_str()) {
+            Some(old) => {
+                let mut unchanged = todo.clone();
+                unchanged.last_update = old.last_update.clone();
+                unchanged.text_crdt = old.text_crdt.clone();
+                unchanged != **old
+            }
+            None => true,
+        };
+        if changed {
+            todo.last_update = Some(now.to_string());
+        }
+    }
+}
+
+/// 保存前端编辑后的待办列表
+///
+/// 前端传入的 `source` 不被采用：与其他本地修改一样记录操作与撤销历史，
+/// 有变化的待办以当前时间作为修改时间。
+#[tauri::command]
+pub async fn save_todos(mut todos: TodoFile) -> Result<(), String> {
+    let _guard = TODO_LOCK.lock().await;
+    let now = format_utc(Utc::now());
+    stamp_changed(&mut todos.data, &store::read_todos().await?.data, &now);
+    todos.last_update = Some(now);
+    todos.source = Some("manual".to_string());
+    save_todo_list(todos, Some("save"), &[]).await
+}
+
+/// 把其他设备的操作应用到列表（在 `TODO_LOCK` 下）：`apply` 由当前列表得到新的列表，
+/// 其中父项已不存在的待办移到顶层；操作与新的列表在同一事务中写入
+pub async fn save_remote_ops<F>(ops: &[TodoOp], apply: F) -> Result<(), String>
+where
+    F: FnOnce(Vec<Todo>) -> Result<Vec<Todo>, String>,
+{
+    let _guard = TODO_LOCK.lock().await;
+
+    let archived_ids = store::archived_ids().await?;
+    let mut data = apply(load_todos().await?.data)?;
+    // 已在本地归档的待办不因其他设备的修改回到列表
+    data.retain(|todo| !archived_ids.contains(&todo.id));
+    // 其他设备删除了父项、本设备在其下新增了子项时，子项移到顶层
+    detach_orphans(&mut data, &archived_ids);
+
+    let todos = TodoFile {
+        data,
+        last_update: Some(format_utc(Utc::now())),
+        source: Some(OPLOG_SOURCE.to_string()),
+    };
+    save_todo_list(todos, None, ops).await
+}
+
+/// 把同步合并的结果写入列表（在 `TODO_LOCK` 下）：`merge` 收到当前列表与归档中的待办 id，
+/// 返回新的列表（None 时列表不变）和交给调用方的结果
+///
+/// 合并基于加锁后重新读取的列表，同步期间的本地修改不会被覆盖；同步写入的列表不记入撤销历史。
+pub async fn save_synced_todos<F, R>(merge: F) -> Result<R, String>
+where
+    F: FnOnce(TodoFile, &HashSet<String>) -> Result<(Option<Vec<Todo>>, R), String>,
+{
+    let _guard = TODO_LOCK.lock().await;
+
+    let (data, result) = merge(load_todos().await?, &store::archived_ids().await?)?;
+    if let Some(data) = data {
+        let todos = TodoFile {
+            data,
+            last_update: Some(format_utc(Utc::now())),
+            source: Some("sync".to_string()),
+        };
+        save_todo_list(todos, None, &[]).await?;
+    }
+    Ok(result)
+}
+
 /// 加载待办事项数据
 #[tauri::command]
-pub fn load_todos() -> Result<Value, String> {
-    let data_dir = dirs::data_dir()
-        .ok_or("Failed to get data directory")?
-        .join("Ton")
-        .join("data");
+pub async fn load_todos() -> Result<TodoFile, String> {
+    let todos = store::read_todos().await?;
+    let archive_ids = store::archived_ids().await?;
+    todos.validate(&archive_ids)?;
+
+    Ok(todos)
+}
+
+/// 在 `TODO_LOCK` 下用 `resolve` 的结果替换列表中的待办 `id`，返回 None 时从列表移除
+///
+/// `resolve` 收到列表中的当前版本（不在列表中时为 None），结果不在列表中时追加到末尾。
+pub async fn replace_todo_with<F>(id: &str, resolve: F) -> Result<(), String>
+where
+    F: FnOnce(Option<&Todo>) -> Result<Option<Todo>, String>,
+{
+    let _guard = TODO_LOCK.lock().await;
+
+    let mut todos = load_todos().await?;
+    let position = todos.data.iter().position(|item| item.id == id);
+    let resolved = resolve(position.map(|index| &todos.data[index]))?;
 
-    let todo_file = data_dir.join("todos.json");
+    let now = format_utc(Utc::now());
+    match (position, resolved) {
+        (Some(index), Some(mut item)) => {
+            item.last_update = Some(now.clone());
+            todos.data[index] = item;
+        }
+        (None, Some(mut item)) => {
+            item.last_update = Some(now.clone());
+            todos.data.push(item);
+        }
+        (Some(index), None) => {
+            todos.data.remove(index);
+        }
+        (None, None) => return Ok(()),
+    }
+
+    todos.last_update = Some(now);
+    todos.source = Some("manual".to_string());
+    save_todo_list(todos, Some("resolve"), &[]).await
+}
+
+/// 通知所有窗口待办列表发生了变化
+pub fn emit_changes(app: &AppHandle, delta: &TodoDelta) {
+    let _ = app.emit("todos-changed", delta);
+}
 
-    if !todo_file.exists() {
-        // 返回默认的空待办事项数组
-        return Ok(serde_json::json!([]));
+/// 按 id 读取待办，不在列表中时报错
+async fn require_todo(id: &str) -> Result<Todo, String> {
+    store::read_todo(id).await?.ok_or_else(|| format!("Todo {:?} not found", id))
+}
+
+/// 校验并写入若干待办（调用方持有 `TODO_LOCK`）：新待办追加到列表末尾，已有的待办原位更新，
+/// 各项的 lastUpdate 更新为当前时间；作为 `action` 记入撤销历史
+async fn put_checked(action: &str, mut todos: Vec<Todo>) -> Result<Vec<Todo>, String> {
+    let archived_ids = store::archived_ids().await?;
+    let mut tree: HashMap<String, Option<String>> = store::read_todo_tree().await?.into_iter().collect();
+    for todo in &todos {
+        tree.insert(todo.id.clone(), todo.parent_id.clone());
     }
 
-    let json_str = std::fs::read_to_string(todo_file)
-        .map_err(|e| format!("Failed to read todo file: {}", e))?;
+    let now = format_utc(Utc::now());
+    let mut previous = Vec::with_capacity(todos.len());
+    for todo in &mut todos {
+        todo.validate().map_err(|e| format!("Todo {:?}: {}", todo.id, e))?;
+        if archived_ids.contains(&todo.id) {
+            return Err(format!("Todo {:?} is archived", todo.id));
+        }
+        if let Some(parent_id) = &todo.parent_id {
+            check_parent(&tree, &archived_ids, &todo.id, parent_id)?;
+        }
+        todo.last_update = Some(now.clone());
+        previous.extend(store::read_todo(&todo.id).await?);
+    }
+    update_text_crdts(&mut todos, &previous)?;
 
-    let todos: Value =
-        serde_json::from_str(&json_str).map_err(|e| format!("Failed to parse todo file: {}", e))?;
+    ensure_oplog().await?;
+    let ops = record_ops(&previous, &todos).await?;
+    let history = HistoryEntry::new(action, previous, todos.clone());
+    store::put_todos(&todos, &ops, Some(&history), &now, "manual").await?;
 
     Ok(todos)
 }
 
-/// 保存已归档的待办事项
+/// 写入单个待办并通知前端，返回写入后的待办
+async fn put_one(app: &AppHandle, action: &str, todo: Todo) -> Result<Todo, String> {
+    let delta = TodoDelta { upserted: put_checked(action, vec![todo]).await?, ..Default::default() };
+    emit_changes(app, &delta);
+    Ok(delta.upserted[0].clone())
+}
+
+/// 新增或整体替换单个待办：新待办追加到列表末尾，已有的待办原位更新
 #[tauri::command]
-pub fn save_archived_todos(archived_todos: Value) -> Result<(), String> {
-    let data_dir = dirs::data_dir()
-        .ok_or("Failed to get data directory")?
-        .join("Ton")
-        .join("data");
+pub async fn upsert_todo(app: AppHandle, todo: Todo) -> Result<Todo, String> {
+    let _guard = TODO_LOCK.lock().await;
 
-    std::fs::create_dir_all(&data_dir)
-        .map_err(|e| format!("Failed to create data directory: {}", e))?;
+    put_one(&app, "update", todo).await
+}
 
-    let archive_file = data_dir.join("stage.json");
-    let json_str = serde_json::to_string_pretty(&archived_todos)
-        .map_err(|e| format!("Failed to serialize archived todos: {}", e))?;
+/// 新增待办并追加到列表末尾，返回新建的待办
+#[tauri::command]
+pub async fn add_todo(
+    app: AppHandle,
+    text: String,
+    parent_id: Option<String>,
+    deadline: Option<String>,
+) -> Result<Todo, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Todo text must not be empty".to_string());
+    }
 
-    std::fs::write(archive_file, json_str)
-        .map_err(|e| format!("Failed to write archive file: {}", e))?;
+    let _guard = TODO_LOCK.lock().await;
 
-    Ok(())
+    let todo = Todo {
+        id: new_id(),
+        text: text.to_string(),
+        completed: false,
+        created_at: format_utc(Utc::now()),
+        completed_at: None,
+        deadline,
+        parent_id,
+        is_deleted: false,
+        last_update: None,
+        text_crdt: None,
+        extra: Map::new(),
+    };
+    put_one(&app, "add", todo).await
 }
 
-/// 加载已归档的待办事项
+/// 按 JSON merge patch 修改待办的字段（值为 null 时删除该字段），返回修改后的待办
+///
+/// id 和 isDeleted 不能通过补丁修改，删除请使用 `delete_todo`。
+#[tauri::command]
+pub async fn update_todo(app: AppHandle, id: String, patch: Map<String, Value>) -> Result<Todo, String> {
+    if let Some(key) = ["id", "isDeleted"].into_iter().find(|key| patch.contains_key(*key)) {
+        return Err(format!("{} cannot be changed with update_todo", key));
+    }
+
+    let _guard = TODO_LOCK.lock().await;
+
+    let mut value = serde_json::to_value(require_todo(&id).await?)
+        .map_err(|e| format!("Failed to serialize todo: {}", e))?;
+    if let Some(fields) = value.as_object_mut() {
+        for (key, patch_value) in patch {
+            if patch_value.is_null() {
+                fields.remove(&key);
+            } else {
+                fields.insert(key, patch_value);
+            }
+        }
+    }
+    let todo: Todo = serde_json::from_value(value)
+        .map_err(|e| format!("Invalid patch for todo {:?}: {}", id, e))?;
+
+    put_one(&app, "update", todo).await
+}
+
+/// 切换待办的完成状态，`with_children` 时所有后代设为同一状态，返回修改过的待办
+#[tauri::command]
+pub async fn toggle_todo(app: AppHandle, id: String, with_children: bool) -> Result<Vec<Todo>, String> {
+    let _guard = TODO_LOCK.lock().await;
+
+    let completed = !require_todo(&id).await?.completed;
+    let tree = store::read_todo_tree().await?;
+    let ids = if with_children {
+        let links: Vec<(&str, Option<&str>)> = tree
+            .iter()
+            .map(|(item, parent)| (item.as_str(), parent.as_deref()))
+            .collect();
+        collect_descendants(&links, vec![id])
+    } else {
+        HashSet::from([id])
+    };
+
+    let now = format_utc(Utc::now());
+    let mut todos = Vec::with_capacity(ids.len());
+    for (item, _) in tree.iter().filter(|(item, _)| ids.contains(item)) {
+        let mut todo = require_todo(item).await?;
+        if todo.completed != completed {
+            todo.completed = completed;
+            todo.completed_at = completed.then(|| now.clone());
+        }
+        todos.push(todo);
+    }
+
+    let delta = TodoDelta { upserted: put_checked("toggle", todos).await?, ..Default::default() };
+    emit_changes(&app, &delta);
+    Ok(delta.upserted)
+}
+
+/// 把待办移到 `parent_id` 下（None 为顶层），`index` 为在新的同级待办中的位置，超出时放到最后
+#[tauri::command]
+pub async fn move_todo(
+    app: AppHandle,
+    id: String,
+    parent_id: Option<String>,
+    index: usize,
+) -> Result<Todo, String> {
+    let _guard = TODO_LOCK.lock().await;
+
+    let tree = store::read_todo_tree().await?;
+    let parents: HashMap<String, Option<String>> = tree.iter().cloned().collect();
+    let before = require_todo(&id).await?;
+    if let Some(parent_id) = &parent_id {
+        check_parent(&parents, &store::archived_ids().await?, &id, parent_id)?;
+    }
+
+    let mut todo = before.clone();
+    todo.parent_id = parent_id.clone();
+    let now = format_utc(Utc::now());
+    todo.last_update = Some(now.clone());
+
+    // 同级待办之间的顺序即列表中的先后顺序
+    let order_before: Vec<String> = tree.iter().map(|(item, _)| item.clone()).collect();
+    let mut order: Vec<(String, Option<String>)> = tree.into_iter().filter(|(item, _)| *item != id).collect();
+    let siblings: Vec<usize> = order
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, parent))| *parent == parent_id)
+        .map(|(position, _)| position)
+        .collect();
+    let position = match (siblings.get(index), siblings.last(), &parent_id) {
+        (Some(position), _, _) => *position,
+        (None, Some(last), _) => last + 1,
+        (None, None, Some(parent_id)) => order
+            .iter()
+            .position(|(item, _)| item == parent_id)
+            .map_or(order.len(), |parent| parent + 1),
+        (None, None, None) => order.len(),
+    };
+    order.insert(position, (id, parent_id));
+    let order: Vec<String> = order.into_iter().map(|(item, _)| item).collect();
+
+    ensure_oplog().await?;
+    let ops = record_ops(std::slice::from_ref(&before), std::slice::from_ref(&todo)).await?;
+    let history = HistoryEntry::new("move", vec![before], vec![todo.clone()]).with_order(order_before, order.clone());
+    store::put_todos_in_order(std::slice::from_ref(&todo), &order, &ops, Some(&history), &now, "manual").await?;
+
+    emit_changes(&app, &TodoDelta { upserted: vec![todo.clone()], ..Default::default() });
+    Ok(todo)
+}
+
+/// 在本地逻辑删除待办及其所有子项（移入回收站），待办不在列表中时返回 false
 #[tauri::command]
-pub fn load_archived_todos() -> Result<Value, String> {
-    let data_dir = dirs::data_dir()
-        .ok_or("Failed to get data directory")?
-        .join("Ton")
-        .join("data");
+pub async fn delete_todo(app: AppHandle, id: String) -> Result<bool, String> {
+    let removed = {
+        let _guard = TODO_LOCK.lock().await;
+        delete_subtree(&id).await?
+    };
+    if removed.is_empty() {
+        return Ok(false);
+    }
+
+    emit_changes(&app, &TodoDelta { removed, ..Default::default() });
+    Ok(true)
+}
+
+/// 把列表中受影响的待办从 `current` 的状态改为 `target` 的状态（调用方持有 `TODO_LOCK`），
+/// 返回写入后的待办与对应的变化
+///
+/// `current` 中的待办须与列表中的一致（按 lastUpdate 判断），只在 `target` 中的待办须不在列表中，
+/// 否则说明这些待办在记录之后又被改动过。只在 `current` 中的待办移入回收站，
+/// `order` 为目标顺序，之后新增的待办保持原有先后放在最后。
+async fn apply_history_states(
+    current: &[Todo],
+    target: &[Todo],
+    order: Option<&[String]>,
+) -> Result<(Vec<Todo>, TodoDelta), String> {
+    let tree = store::read_todo_tree().await?;
+    let mut parents: HashMap<String, Option<String>> = tree.iter().cloned().collect();
+    let current_ids = collect_ids(current);
+    let target_ids = collect_ids(target);
+
+    let mut previous = Vec::with_capacity(current.len());
+    for todo in current {
+        match store::read_todo(&todo.id).await? {
+            Some(saved) if saved.last_update == todo.last_update => previous.push(saved),
+            _ => return Err(format!("Todo {:?} has been changed since", todo.id)),
+        }
+    }
+    if let Some(todo) = target.iter().find(|todo| !current_ids.contains(&todo.id) && parents.contains_key(&todo.id)) {
+        return Err(format!("Todo {:?} has been changed since", todo.id));
+    }
+
+    let now = format_utc(Utc::now());
+    let trashed: Vec<Todo> = previous
+        .iter()
+        .filter(|todo| !target_ids.contains(&todo.id))
+        .cloned()
+        .map(|mut todo| {
+            todo.is_deleted = true;
+            todo.last_update = Some(now.clone());
+            todo
+        })
+        .collect();
+    let mut upserted: Vec<Todo> = target
+        .iter()
+        .cloned()
+        .map(|mut todo| {
+            // 仍在列表中的待办以当前的文本副本为准，把文本改回去
+            if current_ids.contains(&todo.id) {
+                todo.text_crdt = None;
+            }
+            todo.is_deleted = false;
+            todo.last_update = Some(now.clone());
+            todo
+        })
+        .collect();
+
+    for todo in &trashed {
+        parents.remove(&todo.id);
+    }
+    for todo in &upserted {
+        parents.insert(todo.id.clone(), todo.parent_id.clone());
+    }
+    let archived_ids = store::archived_ids().await?;
+    for todo in &upserted {
+        todo.validate().map_err(|e| format!("Todo {:?}: {}", todo.id, e))?;
+        if archived_ids.contains(&todo.id) {
+            return Err(format!("Todo {:?} is archived", todo.id));
+        }
+        if let Some(parent_id) = &todo.parent_id {
+            check_parent(&parents, &archived_ids, &todo.id, parent_id)?;
+        }
+    }
+    update_text_crdts(&mut upserted, &previous)?;
+
+    let order: Option<Vec<String>> = order.map(|order| {
+        let mut placed = HashSet::new();
+        order
+            .iter()
+            .chain(tree.iter().map(|(id, _)| id))
+            .chain(upserted.iter().map(|todo| &todo.id))
+            .filter(|id| parents.contains_key(*id) && placed.insert(id.as_str()))
+            .cloned()
+            .collect()
+    });
+
+    ensure_oplog().await?;
+    let ops = record_ops(&previous, &upserted).await?;
+    store::apply_todo_changes(&upserted, &trashed, order.as_deref(), &ops, &now, "manual").await?;
+
+    let delta = TodoDelta {
+        upserted: upserted.clone(),
+        removed: trashed.into_iter().map(|todo| todo.id).collect(),
+    };
+    Ok((upserted, delta))
+}
+
+/// 执行撤销栈或重做栈顶的一步，并把它移入另一个栈，栈为空时返回 None
+///
+/// 无法执行的一步（受影响的待办之后又被改动过）从历史中移除并返回错误。
+pub async fn step_history(stack: HistoryStack) -> Result<Option<TodoDelta>, String> {
+    let _guard = TODO_LOCK.lock().await;
 
-    let archive_file = data_dir.join("stage.json");
+    let Some(mut entry) = store::pop_history(stack).await? else {
+        return Ok(None);
+    };
+    let (current, target, order) = match stack {
+        HistoryStack::Undo => (&entry.after, &entry.before, entry.order_before.as_deref()),
+        HistoryStack::Redo => (&entry.before, &entry.after, entry.order_after.as_deref()),
+    };
+    let (written, delta) = apply_history_states(current, target, order)
+        .await
+        .map_err(|e| format!("Cannot {} {}: {}", stack.as_str(), entry.action, e))?;
 
-    if !archive_file.exists() {
-        return Ok(serde_json::json!({
-            "todos": [],
-            "archivedAt": ""
-        }));
+    // 记录实际写入的状态，反向执行时据此判断待办是否又被改动过
+    match stack {
+        HistoryStack::Undo => entry.before = written,
+        HistoryStack::Redo => entry.after = written,
     }
+    store::push_history(stack.other(), &entry, HISTORY_LIMIT).await?;
+
+    Ok(Some(delta))
+}
+
+/// 读取整个归档（不做引用校验）
+pub async fn read_archive() -> Result<ArchivedTodos, String> {
+    store::read_archive().await
+}
+
+/// 保存已归档的待办事项
+#[tauri::command]
+pub async fn save_archived_todos(archived_todos: ArchivedTodos) -> Result<(), String> {
+    let _guard = TODO_LOCK.lock().await;
+
+    let active_ids: HashSet<String> = store::read_todo_tree().await?.into_iter().map(|(id, _)| id).collect();
+    archived_todos.validate(&active_ids)?;
 
-    let json_str = std::fs::read_to_string(archive_file)
-        .map_err(|e| format!("Failed to read archive file: {}", e))?;
+    store::write_archive(&archived_todos).await
+}
 
-    let archived_todos: Value = serde_json::from_str(&json_str)
-        .map_err(|e| format!("Failed to parse archive file: {}", e))?;
+/// 加载已归档的待办事项
+#[tauri::command]
+pub async fn load_archived_todos() -> Result<ArchivedTodos, String> {
+    let archived_todos = store::read_archive().await?;
+    let active_ids: HashSet<String> = store::read_todo_tree().await?.into_iter().map(|(id, _)| id).collect();
+    archived_todos.validate(&active_ids)?;
 
     Ok(archived_todos)
 }
 
 /// 清空已归档的待办事项
 #[tauri::command]
-pub fn clear_archived_todos() -> Result<(), String> {
-    let data_dir = dirs::data_dir()
-        .ok_or("Failed to get data directory")?
-        .join("Ton")
-        .join("data");
+pub async fn clear_archived_todos() -> Result<(), String> {
+    let _guard = TODO_LOCK.lock().await;
+
+    // 清空前强制备份，便于误操作后恢复
+    store::backup_data("stage.json", true).await?;
+    store::write_archive(&ArchivedTodos::default()).await
+}
 
-    let archive_file = data_dir.join("stage.json");
+/// 把 `select` 从列表中选出的待办作为一个新批次移入归档，返回归档的数量
+///
+/// 选择与归档在同一次 `TODO_LOCK` 中完成，其间的保存不会被归档时的旧列表覆盖。
+pub async fn archive_todos<F: FnOnce(&[Todo]) -> HashSet<String>>(select: F) -> Result<usize, String> {
+    let _guard = TODO_LOCK.lock().await;
 
-    if archive_file.exists() {
-        std::fs::remove_file(archive_file)
-            .map_err(|e| format!("Failed to clear archive file: {}", e))?;
+    let todos = load_todos().await?.data;
+    let ids = select(&todos);
+    if ids.is_empty() {
+        return Ok(0);
     }
 
-    Ok(())
+    let batch = ArchiveBatch {
+        id: new_id(),
+        archived_at: format_utc(Utc::now()),
+        todos: todos.into_iter().filter(|todo| ids.contains(&todo.id)).collect(),
+    };
+    // 移出列表与写入归档在同一事务中完成；归档的待办仍然存在，不记录为删除，
+    // 否则其他设备会通过操作日志与逐项同步把它们删除
+    store::archive_batch(&batch, &batch.archived_at, "manual").await?;
+
+    Ok(batch.todos.len())
+}
+
+/// 把归档中的待办移回列表
+pub async fn unarchive_todos(todos: Vec<Todo>) -> Result<(), String> {
+    let _guard = TODO_LOCK.lock().await;
+
+    ensure_oplog().await?;
+    let ops = record_ops(&[], &todos).await?;
+    store::unarchive(&todos, &ops, &format_utc(Utc::now()), "manual").await
+}
+
+/// 在本地逻辑删除待办及其所有子项：从列表移入回收站并标记 isDeleted
+///
+/// 待办不在列表中时返回 false。
+pub async fn delete_todo_locally(id: &str) -> Result<bool, String> {
+    let _guard = TODO_LOCK.lock().await;
+    Ok(!delete_subtree(id).await?.is_empty())
+}
+
+/// 把待办及其所有子项移入回收站（调用方持有 `TODO_LOCK`），返回删除的 id，待办不在列表中时为空
+async fn delete_subtree(id: &str) -> Result<Vec<String>, String> {
+    let tree = store::read_todo_tree().await?;
+    if !tree.iter().any(|(item, _)| item == id) {
+        return Ok(Vec::new());
+    }
+
+    let links: Vec<(&str, Option<&str>)> = tree
+        .iter()
+        .map(|(item, parent)| (item.as_str(), parent.as_deref()))
+        .collect();
+    let ids = collect_descendants(&links, vec![id.to_string()]);
+
+    let mut before = Vec::with_capacity(ids.len());
+    for (item, _) in tree.iter().filter(|(item, _)| ids.contains(item)) {
+        if let Some(todo) = store::read_todo(item).await? {
+            before.push(todo);
+        }
+    }
+
+    let now = format_utc(Utc::now());
+    let deleted: Vec<Todo> = before
+        .iter()
+        .cloned()
+        .map(|mut todo| {
+            todo.is_deleted = true;
+            todo.last_update = Some(now.clone());
+            todo
+        })
+        .collect();
+
+    ensure_oplog().await?;
+    let ops = record_ops(&before, &[]).await?;
+    // 整棵子树作为一步撤销，撤销时按原来的位置放回
+    let order_before: Vec<String> = tree.iter().map(|(item, _)| item.clone()).collect();
+    let order_after: Vec<String> = order_before.iter().filter(|item| !ids.contains(*item)).cloned().collect();
+    let history = HistoryEntry::new("delete", before, Vec::new()).with_order(order_before, order_after);
+    store::move_todos_to_trash(&deleted, &ops, Some(&history), &now, "manual").await?;
+
+    Ok(deleted.into_iter().map(|todo| todo.id).collect())
+}
+
+/// 从本地回收站恢复待办
+///
+/// `WithAncestors` 同时恢复回收站中的父项链以及它们的子项，`Subtree` 只恢复该项及其子项；
+/// 父项仍在回收站中时，恢复的项移到顶层。待办不在回收站中时返回 false。
+pub async fn restore_todo_locally(id: &str, scope: RestoreScope) -> Result<bool, String> {
+    let _guard = TODO_LOCK.lock().await;
+
+    let trash = store::read_trash().await?;
+    let trashed: HashMap<&str, &Todo> = trash.todos.iter().map(|todo| (todo.id.as_str(), todo)).collect();
+    if !trashed.contains_key(id) {
+        return Ok(false);
+    }
+
+    let mut roots = vec![id.to_string()];
+    if scope == RestoreScope::WithAncestors {
+        while let Some(parent) = roots
+            .last()
+            .and_then(|current| trashed.get(current.as_str()))
+            .and_then(|todo| todo.parent_id.clone())
+            .filter(|parent| trashed.contains_key(parent.as_str()) && !roots.contains(parent))
+        {
+            roots.push(parent);
+        }
+    }
+    let ids = collect_subtree(&trash.todos, roots);
+
+    let mut todos = store::read_todos().await?;
+    let mut known_ids = collect_ids(&todos.data);
+    known_ids.extend(store::archived_ids().await?);
+    known_ids.extend(ids.iter().cloned());
+
+    let now = format_utc(Utc::now());
+    for todo in trash.todos.iter().filter(|todo| ids.contains(&todo.id)) {
+        let mut todo = todo.clone();
+        todo.is_deleted = false;
+        todo.last_update = Some(now.clone());
+        if todo.parent_id.as_ref().is_some_and(|parent| !known_ids.contains(parent)) {
+            todo.parent_id = None;
+        }
+        todos.data.push(todo);
+    }
+
+    // 保存列表时会把恢复的待办从回收站移除
+    save_todo_list(
+        TodoFile {
+            data: todos.data,
+            last_update: Some(now),
+            source: Some("manual".to_string()),
+        },
+        Some("restore"),
+        &[],
+    )
+    .await?;
+
+    Ok(true)
+}
+
+/// 本地回收站中的待办，最近删除的在前
+pub async fn deleted_todos_locally() -> Result<Vec<Todo>, String> {
+    let mut todos = store::read_trash().await?.todos;
+    todos.sort_by(|a, b| b.last_update.cmp(&a.last_update));
+    Ok(todos)
 }
@@ -1,17 +1,47 @@
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use tauri::State;
+use serde_json::{Map, Value};
+use tauri::{AppHandle, State};
 use tokio::sync::Mutex;
-use sqlx::{MySqlPool, Row};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 // 导入数据模块的函数
-use crate::modules::data::{save_todos, load_todos};
-use crate::modules::app::{save_app_settings, load_app_settings};
+use crate::modules::data::{
+    deleted_todos_locally, delete_todo_locally, load_todos, restore_todo_locally, save_synced_todos,
+};
+use crate::modules::app::{apply_app_settings_at, load_app_settings, load_settings_times, lock_settings, AppSettings};
+use crate::modules::crypto::{decrypt, encrypt, EncryptedEnvelope, KeySource};
+use crate::modules::storage::write_atomic;
+use crate::modules::backend::{self, RestoreScope, SyncBackend};
+use crate::modules::schema::RemoteSchemaStatus;
+use crate::modules::oplog;
+use crate::modules::store;
+use crate::modules::outbox::{self, Operation, RemoteSnapshot};
+use crate::modules::migration::{migrate, to_versioned_value, FileKind};
+use crate::modules::types::{format_utc, parse_utc, Todo};
+use crate::modules::sync::{
+    changed_since_base, drop_purged, load_sync_base, load_sync_conflicts, merge_todos, plan_todo_sync, save_sync_base,
+    latest_time, merge_settings, pick_settings, save_sync_conflicts, SettingsMerge, SyncBase, TodoSyncPlan,
+};
+
+// 远程数据库种类
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseKind {
+    #[default]
+    Mysql,
+    Postgres,
+    // 本地或网络共享目录中的数据库文件，`database` 字段为文件路径
+    Sqlite,
+}
 
 // 数据库配置结构
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseConfig {
+    // 旧配置没有该字段，默认为 MySQL
+    #[serde(default)]
+    pub kind: DatabaseKind,
     pub host: String,
     pub port: u16,
     pub username: String,
@@ -19,13 +49,15 @@ pub struct DatabaseConfig {
     pub database: String,
 }
 
-// 同步状态
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SyncStatus {
-    pub is_connected: bool,
-    pub is_syncing: bool,
-    pub last_sync_time: Option<String>,
-    pub error: Option<String>,
+impl DatabaseConfig {
+    // 同步基线使用的数据库标识，切换数据库后基线失效（MySQL 保持旧格式以沿用已有基线）
+    fn sync_key(&self) -> String {
+        match self.kind {
+            DatabaseKind::Mysql => format!("{}:{}/{}", self.host, self.port, self.database),
+            DatabaseKind::Postgres => format!("postgres://{}:{}/{}", self.host, self.port, self.database),
+            DatabaseKind::Sqlite => format!("sqlite://{}", self.database),
+        }
+    }
 }
 
 // 同步结果
@@ -41,36 +73,82 @@ pub struct SyncData {
     pub local_last_update: String,
     pub remote_last_update: String,
     pub synced_items: usize,
-}
-
-// 数据库连接池状态
+    pub uploaded_items: usize,
+    pub downloaded_items: usize,
+    pub merged_items: usize,
+    pub conflicted_items: usize,
+}
+
+// 同步预览：start_database_sync 此时执行会做的修改
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncPlan {
+    pub schema: RemoteSchemaStatus,
+    // 合并前先回放的离线操作数量
+    pub pending_operations: usize,
+    // 操作日志将上传与下载的数量
+    pub ops_to_upload: usize,
+    pub ops_to_download: usize,
+    pub todos: TodoSyncPlan,
+    pub settings: SettingsMerge,
+    // 会删除或覆盖本地数据，执行前需要用户确认
+    pub destructive: bool,
+}
+
+// 待办逐项同步统计
+#[derive(Debug, Clone, Default)]
+struct TodoSyncStats {
+    uploaded: usize,
+    downloaded: usize,
+    merged: usize,
+    conflicted: usize,
+    // 因远程已彻底删除而从本地移除的数量
+    purged: usize,
+    // 合并时本地待办列表的 lastUpdate
+    local_last_update: String,
+}
+
+// 数据库连接状态
 pub struct DatabaseState {
-    pub pool: Arc<Mutex<Option<MySqlPool>>>,
+    pub backend: Arc<Mutex<Option<Arc<dyn SyncBackend>>>>,
     pub config: Arc<Mutex<Option<DatabaseConfig>>>,
+    // 手动同步与自动同步互斥，同一时间只运行一次同步
+    pub sync_lock: Arc<Mutex<()>>,
 }
 
 impl Default for DatabaseState {
     fn default() -> Self {
         Self {
-            pool: Arc::new(Mutex::new(None)),
+            backend: Arc::new(Mutex::new(None)),
             config: Arc::new(Mutex::new(None)),
+            sync_lock: Arc::new(Mutex::new(())),
         }
     }
 }
 
-// Base64编码/解码函数
-fn encode_base64(data: &str) -> String {
-    use base64::{Engine as _, engine::general_purpose};
-    general_purpose::STANDARD.encode(data)
+// 获取当前连接的同步后端
+pub async fn current_backend(state: &DatabaseState) -> Result<Arc<dyn SyncBackend>, String> {
+    state.backend.lock().await
+        .clone()
+        .ok_or_else(|| "数据库连接未建立".to_string())
 }
 
+// Base64解码函数（用于迁移旧版本的配置文件）
 fn decode_base64(encoded: &str) -> Result<String, String> {
     use base64::{Engine as _, engine::general_purpose};
-    general_purpose::STANDARD.decode(encoded)
+    general_purpose::STANDARD.decode(encoded.trim())
         .map_err(|e| format!("Base64解码失败: {}", e))
         .and_then(|bytes| String::from_utf8(bytes).map_err(|e| format!("UTF-8解码失败: {}", e)))
 }
 
+// 选择配置文件的密钥来源：提供口令时由口令派生，否则使用本机密钥文件
+fn config_key_source<'a>(passphrase: &'a Option<String>, key_path: &'a std::path::Path) -> KeySource<'a> {
+    match passphrase.as_deref() {
+        Some(passphrase) if !passphrase.is_empty() => KeySource::Passphrase(passphrase),
+        _ => KeySource::KeyFile(key_path),
+    }
+}
+
 // 获取配置目录路径
 fn get_config_dir() -> Result<std::path::PathBuf, String> {
     let config_dir = dirs::config_dir()
@@ -84,424 +162,122 @@ fn get_config_dir() -> Result<std::path::PathBuf, String> {
     Ok(config_dir)
 }
 
-// 保存数据库配置（Base64加密）
+// 保存数据库配置（XChaCha20-Poly1305 加密）
 #[tauri::command]
-pub async fn save_database_config(config: DatabaseConfig) -> Result<(), String> {
+pub async fn save_database_config(config: DatabaseConfig, passphrase: Option<String>) -> Result<(), String> {
     let config_dir = get_config_dir()?;
     let config_path = config_dir.join("da.da");
+    let key_path = config_dir.join("da.key");
     
-    // 序列化配置
-    let config_json = serde_json::to_string(&config)
+    // 序列化配置（带格式版本）
+    let config_json = serde_json::to_string(&to_versioned_value(FileKind::DatabaseConfig, &config)?)
         .map_err(|e| format!("序列化配置失败: {}", e))?;
     
-    // Base64编码
-    let encoded_config = encode_base64(&config_json);
+    // 加密
+    let envelope = encrypt(config_json.as_bytes(), config_key_source(&passphrase, &key_path))?;
+    let envelope_json = serde_json::to_string_pretty(&envelope)
+        .map_err(|e| format!("序列化加密配置失败: {}", e))?;
     
     // 保存到文件
-    std::fs::write(&config_path, encoded_config)
+    write_atomic(&config_path, envelope_json.as_bytes())
         .map_err(|e| format!("保存配置文件失败: {}", e))?;
     
     Ok(())
 }
 
-// 加载数据库配置（Base64解密）
+// 加载数据库配置（解密，旧版 Base64 文件会在首次加载时转为加密格式）
 #[tauri::command]
-pub async fn load_database_config() -> Result<Option<DatabaseConfig>, String> {
+// 保存的配置是否用口令加密，没有口令时无法读取
+pub fn database_config_needs_passphrase() -> Result<bool, String> {
+    let config_path = get_config_dir()?.join("da.da");
+    if !config_path.exists() {
+        return Ok(false);
+    }
+    
+    let file_content = std::fs::read_to_string(&config_path)
+        .map_err(|e| format!("读取配置文件失败: {}", e))?;
+    if !file_content.trim_start().starts_with('{') {
+        return Ok(false);
+    }
+    let envelope: EncryptedEnvelope = serde_json::from_str(&file_content)
+        .map_err(|e| format!("配置文件格式无效或已被篡改: {}", e))?;
+    Ok(envelope.kdf.is_some())
+}
+
+pub async fn load_database_config(passphrase: Option<String>) -> Result<Option<DatabaseConfig>, String> {
     let config_dir = get_config_dir()?;
     let config_path = config_dir.join("da.da");
+    let key_path = config_dir.join("da.key");
     
     if !config_path.exists() {
         return Ok(None);
     }
     
     // 读取文件
-    let encoded_data = std::fs::read_to_string(&config_path)
+    let file_content = std::fs::read_to_string(&config_path)
         .map_err(|e| format!("读取配置文件失败: {}", e))?;
     
-    // Base64解码
-    let config_json = decode_base64(&encoded_data)?;
+    // 加密格式为 JSON 对象，旧版本为 Base64 文本
+    let is_legacy = !file_content.trim_start().starts_with('{');
+    let config_json = if is_legacy {
+        decode_base64(&file_content)?
+    } else {
+        let envelope: EncryptedEnvelope = serde_json::from_str(&file_content)
+            .map_err(|e| format!("配置文件格式无效或已被篡改: {}", e))?;
+        let plaintext = decrypt(&envelope, config_key_source(&passphrase, &key_path))?;
+        String::from_utf8(plaintext).map_err(|e| format!("UTF-8解码失败: {}", e))?
+    };
+    
+    let config_value: Value = serde_json::from_str(&config_json)
+        .map_err(|e| format!("反序列化配置失败: {}", e))?;
+    
+    // 迁移旧版本配置
+    let (config_value, migrated) = migrate(FileKind::DatabaseConfig, config_value)?;
     
     // 反序列化配置
-    let config: DatabaseConfig = serde_json::from_str(&config_json)
+    let config: DatabaseConfig = serde_json::from_value(config_value)
         .map_err(|e| format!("反序列化配置失败: {}", e))?;
     
+    if is_legacy || migrated {
+        save_database_config(config.clone(), passphrase).await?;
+    }
+    
     Ok(Some(config))
 }
 
 // 测试数据库连接
 #[tauri::command]
 pub async fn test_database_connection(config: DatabaseConfig) -> Result<bool, String> {
-    let connection_string = format!(
-        "mysql://{}:{}@{}:{}/{}",
-        config.username, config.password, config.host, config.port, config.database
-    );
-    
-    match MySqlPool::connect(&connection_string).await {
-        Ok(pool) => {
-            // 测试查询
-            match sqlx::query("SELECT 1").fetch_one(&pool).await {
-                Ok(_) => Ok(true),
-                Err(e) => Err(format!("数据库查询测试失败: {}", e)),
-            }
-        }
-        Err(e) => Err(format!("数据库连接失败: {}", e)),
-    }
-}
-
-// 创建数据库连接池
-async fn create_pool(config: &DatabaseConfig) -> Result<MySqlPool, String> {
-    let connection_string = format!(
-        "mysql://{}:{}@{}:{}/{}",
-        config.username, config.password, config.host, config.port, config.database
-    );
-    
-    MySqlPool::connect(&connection_string).await
-        .map_err(|e| format!("创建数据库连接池失败: {}", e))
-}
-
-// 检查表是否存在
-async fn table_exists(pool: &MySqlPool, table_name: &str) -> Result<bool, String> {
-    let query = "SELECT COUNT(*) as count FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = ?";
-    
-    match sqlx::query(query)
-        .bind(table_name)
-        .fetch_one(pool)
-        .await
-    {
-        Ok(row) => {
-            let count: i64 = row.get("count");
-            Ok(count > 0)
-        }
-        Err(e) => Err(format!("检查表存在性失败: {}", e)),
-    }
-}
-
-// 检查表结构是否匹配
-async fn check_table_structure(pool: &MySqlPool, table_name: &str, expected_columns: &[&str]) -> Result<bool, String> {
-    let query = r#"
-        SELECT COLUMN_NAME 
-        FROM information_schema.columns 
-        WHERE table_schema = DATABASE() AND table_name = ?
-        ORDER BY ORDINAL_POSITION
-    "#;
-    
-    let rows = sqlx::query(query)
-        .bind(table_name)
-        .fetch_all(pool)
-        .await
-        .map_err(|e| format!("检查表结构失败: {}", e))?;
-    
-    let existing_columns: Vec<String> = rows.iter()
-        .map(|row| row.get::<String, _>("COLUMN_NAME"))
-        .collect();
-    
-    // 检查是否包含所有必需的列
-    for expected_col in expected_columns {
-        if !existing_columns.contains(&expected_col.to_string()) {
-            return Ok(false);
-        }
-    }
+    let backend = backend::connect(&config).await
+        .map_err(|e| format!("数据库连接失败: {}", e))?;
     
+    // 测试查询
+    backend.ping().await?;
     Ok(true)
 }
 
-// 修改表结构以匹配期望的结构
-async fn alter_table_structure(pool: &MySqlPool, table_name: &str) -> Result<(), String> {
-    match table_name {
-        "todo_settings_sync" => {
-            // 检查并添加缺失的列
-            let alter_queries = vec![
-                "ALTER TABLE todo_settings_sync ADD COLUMN id INT AUTO_INCREMENT PRIMARY KEY FIRST",
-                "ALTER TABLE todo_settings_sync ADD COLUMN update_time VARCHAR(50) NOT NULL COMMENT '更新时间'",
-                "ALTER TABLE todo_settings_sync ADD COLUMN field_name VARCHAR(100) NOT NULL UNIQUE COMMENT '字段名'",
-                "ALTER TABLE todo_settings_sync ADD COLUMN data_type VARCHAR(50) NOT NULL COMMENT '数据类型'",
-                "ALTER TABLE todo_settings_sync ADD COLUMN field_value TEXT NOT NULL COMMENT '字段值'",
-                "ALTER TABLE todo_settings_sync ADD COLUMN last_update VARCHAR(50) NOT NULL COMMENT '最后更新时间'",
-                "ALTER TABLE todo_settings_sync ADD COLUMN created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP",
-                "ALTER TABLE todo_settings_sync ADD COLUMN updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP",
-            ];
-            
-            for query in alter_queries {
-                if let Err(e) = sqlx::query(query).execute(pool).await {
-                    // 忽略列已存在的错误
-                    if !e.to_string().contains("Duplicate column name") {
-                        return Err(format!("修改表结构失败: {}", e));
-                    }
-                }
-            }
-            
-            // 确保唯一约束存在
-            let unique_constraint_query = "ALTER TABLE todo_settings_sync ADD UNIQUE KEY unique_field_name (field_name)";
-            if let Err(e) = sqlx::query(unique_constraint_query).execute(pool).await {
-                // 忽略约束已存在的错误
-                if !e.to_string().contains("Duplicate key name") && !e.to_string().contains("already exists") {
-                    return Err(format!("添加唯一约束失败: {}", e));
-                }
-            }
-        }
-        "todo_items_sync" => {
-            // 首先检查是否需要修改现有表结构以支持UUID
-            let check_id_type_query = r#"
-                SELECT DATA_TYPE, CHARACTER_MAXIMUM_LENGTH 
-                FROM information_schema.columns 
-                WHERE table_schema = DATABASE() 
-                AND table_name = 'todo_items_sync' 
-                AND column_name = 'id'
-            "#;
-            
-            if let Ok(rows) = sqlx::query(check_id_type_query).fetch_all(pool).await {
-                if let Some(row) = rows.first() {
-                    let data_type: String = row.get("DATA_TYPE");
-                    let max_length: Option<i64> = row.get("CHARACTER_MAXIMUM_LENGTH");
-                    
-                    // 如果id字段不是VARCHAR(36)，需要修改
-                    if data_type != "varchar" || max_length != Some(36) {
-                        // 修改id字段类型
-                        if let Err(e) = sqlx::query("ALTER TABLE todo_items_sync MODIFY COLUMN id VARCHAR(36) PRIMARY KEY COMMENT '待办事项ID (UUID)'").execute(pool).await {
-                            if !e.to_string().contains("Duplicate column name") {
-                                return Err(format!("修改id字段类型失败: {}", e));
-                            }
-                        }
-                        
-                        // 修改parent_id字段类型
-                        if let Err(e) = sqlx::query("ALTER TABLE todo_items_sync MODIFY COLUMN parent_id VARCHAR(36) NULL COMMENT '父项ID，支持树形结构 (UUID)'").execute(pool).await {
-                            if !e.to_string().contains("Duplicate column name") {
-                                return Err(format!("修改parent_id字段类型失败: {}", e));
-                            }
-                        }
-                    }
-                }
-            }
-            
-            let alter_queries = vec![
-                "ALTER TABLE todo_items_sync ADD COLUMN id VARCHAR(36) PRIMARY KEY COMMENT '待办事项ID (UUID)'",
-                "ALTER TABLE todo_items_sync ADD COLUMN parent_id VARCHAR(36) NULL COMMENT '父项ID，支持树形结构 (UUID)'",
-                "ALTER TABLE todo_items_sync ADD COLUMN text TEXT NOT NULL COMMENT '待办事项内容'",
-                "ALTER TABLE todo_items_sync ADD COLUMN completed BOOLEAN NOT NULL DEFAULT FALSE COMMENT '是否完成'",
-                "ALTER TABLE todo_items_sync ADD COLUMN created_at VARCHAR(50) NOT NULL COMMENT '创建时间'",
-                "ALTER TABLE todo_items_sync ADD COLUMN completed_at VARCHAR(50) NULL COMMENT '完成时间'",
-                "ALTER TABLE todo_items_sync ADD COLUMN deadline VARCHAR(50) NULL COMMENT '截止时间'",
-                "ALTER TABLE todo_items_sync ADD COLUMN is_deleted BOOLEAN NOT NULL DEFAULT FALSE COMMENT '是否已删除（逻辑删除）'",
-                "ALTER TABLE todo_items_sync ADD COLUMN last_update VARCHAR(50) NOT NULL COMMENT '最后更新时间'",
-                "ALTER TABLE todo_items_sync ADD COLUMN created_timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP",
-                "ALTER TABLE todo_items_sync ADD COLUMN updated_timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP",
-            ];
-            
-            for query in alter_queries {
-                if let Err(e) = sqlx::query(query).execute(pool).await {
-                    // 忽略列已存在的错误
-                    if !e.to_string().contains("Duplicate column name") {
-                        return Err(format!("修改表结构失败: {}", e));
-                    }
-                }
-            }
-            
-            // 添加索引（如果不存在）
-            let index_queries = vec![
-                "ALTER TABLE todo_items_sync ADD INDEX idx_id (id)",
-                "ALTER TABLE todo_items_sync ADD INDEX idx_parent_id (parent_id)",
-                "ALTER TABLE todo_items_sync ADD INDEX idx_completed (completed)",
-                "ALTER TABLE todo_items_sync ADD INDEX idx_is_deleted (is_deleted)",
-                "ALTER TABLE todo_items_sync ADD INDEX idx_last_update (last_update)",
-                "ALTER TABLE todo_items_sync ADD INDEX idx_deadline (deadline)",
-                "ALTER TABLE todo_items_sync ADD INDEX idx_created_timestamp (created_timestamp)",
-                "ALTER TABLE todo_items_sync ADD INDEX idx_updated_timestamp (updated_timestamp)",
-            ];
-            
-            for query in index_queries {
-                if let Err(e) = sqlx::query(query).execute(pool).await {
-                    // 忽略索引已存在的错误
-                    if !e.to_string().contains("Duplicate key name") && !e.to_string().contains("already exists") {
-                        return Err(format!("添加索引失败: {}", e));
-                    }
-                }
-            }
-        }
-        _ => return Err(format!("未知的表名: {}", table_name)),
-    }
-    
-    Ok(())
-}
-
-// 清理重复的设置数据
-async fn cleanup_duplicate_settings(pool: &MySqlPool) -> Result<(), String> {
-    // 使用窗口函数删除重复数据，只保留每个field_name的最新记录
-    let cleanup_query = r#"
-        DELETE FROM todo_settings_sync 
-        WHERE id NOT IN (
-            SELECT id FROM (
-                SELECT id, 
-                       ROW_NUMBER() OVER (PARTITION BY field_name ORDER BY id DESC) as rn
-                FROM todo_settings_sync
-            ) ranked 
-            WHERE rn = 1
-        )
-    "#;
-    
-    sqlx::query(cleanup_query)
-        .execute(pool)
-        .await
-        .map_err(|e| format!("清理重复设置数据失败: {}", e))?;
-    
-    // 确保唯一约束存在
-    let ensure_unique_query = "ALTER TABLE todo_settings_sync ADD UNIQUE KEY unique_field_name (field_name)";
-    if let Err(e) = sqlx::query(ensure_unique_query).execute(pool).await {
-        // 忽略约束已存在的错误
-        if !e.to_string().contains("Duplicate key name") && !e.to_string().contains("already exists") {
-            return Err(format!("确保唯一约束失败: {}", e));
-        }
-    }
-    
-    Ok(())
-}
-
-// 检查数据完整性
-async fn check_data_integrity(pool: &MySqlPool) -> Result<String, String> {
-    let mut messages: Vec<String> = Vec::new();
-    
-    // 检查设置表重复数据
-    let duplicate_settings_query = r#"
-        SELECT field_name, COUNT(*) as count 
-        FROM todo_settings_sync 
-        GROUP BY field_name 
-        HAVING COUNT(*) > 1
-    "#;
-    
-    let duplicate_rows = sqlx::query(duplicate_settings_query)
-        .fetch_all(pool)
-        .await
-        .map_err(|e| format!("检查设置表重复数据失败: {}", e))?;
-    
-    if !duplicate_rows.is_empty() {
-        messages.push(format!("发现 {} 个重复的设置字段", duplicate_rows.len()));
-    } else {
-        messages.push("设置表数据完整性正常".to_string());
-    }
-    
-    // 检查待办表数据
-    let todo_count_query = "SELECT COUNT(*) as count FROM todo_items_sync WHERE is_deleted = FALSE";
-    let todo_count: i64 = sqlx::query(todo_count_query)
-        .fetch_one(pool)
-        .await
-        .map_err(|e| format!("检查待办数据失败: {}", e))?
-        .get("count");
-    
-    messages.push(format!("待办事项总数: {}", todo_count));
-    
-    Ok(messages.join("；"))
-}
-
 // 检查并初始化数据库表结构
 #[tauri::command]
 pub async fn check_and_initialize_tables(
     state: State<'_, DatabaseState>
 ) -> Result<String, String> {
-    let pool_guard = state.pool.lock().await;
-    let pool = pool_guard.as_ref()
-        .ok_or("数据库连接未建立")?;
-    
-    let mut messages: Vec<String> = Vec::new();
-    
-    // 检查设置同步表
-    let settings_table_exists = table_exists(pool, "todo_settings_sync").await?;
-    if !settings_table_exists {
-        create_settings_sync_table(pool).await?;
-        messages.push("创建了设置同步表".to_string());
-    } else {
-        let expected_columns = ["id", "update_time", "field_name", "data_type", "field_value", "last_update"];
-        let structure_matches = check_table_structure(pool, "todo_settings_sync", &expected_columns).await?;
-        if !structure_matches {
-            alter_table_structure(pool, "todo_settings_sync").await?;
-            messages.push("更新了设置同步表结构".to_string());
-        } else {
-            messages.push("设置同步表结构正常".to_string());
-        }
-        
-        // 清理重复的设置数据
-        cleanup_duplicate_settings(pool).await?;
-        messages.push("清理了重复的设置数据".to_string());
-        
-        // 检查数据完整性
-        let integrity_status = check_data_integrity(pool).await?;
-        messages.push(integrity_status);
-    }
+    let backend = current_backend(&state).await?;
     
-    // 检查待办同步表
-    let todos_table_exists = table_exists(pool, "todo_items_sync").await?;
-    if !todos_table_exists {
-        create_todos_sync_table(pool).await?;
-        messages.push("创建了待办同步表".to_string());
-    } else {
-        let expected_columns = ["id", "parent_id", "text", "completed", "created_at", "completed_at", "deadline", "is_deleted", "last_update"];
-        let structure_matches = check_table_structure(pool, "todo_items_sync", &expected_columns).await?;
-        if !structure_matches {
-            alter_table_structure(pool, "todo_items_sync").await?;
-            messages.push("更新了待办同步表结构".to_string());
-        } else {
-            messages.push("待办同步表结构正常".to_string());
-        }
+    // 按顺序执行未执行的结构迁移
+    let mut messages = backend.ensure_schema().await?;
+    if messages.is_empty() {
+        messages.push(format!("数据表结构已是最新版本 (v{})", backend.latest_schema_version()));
     }
     
-    Ok(messages.join("；"))
-}
-
-// 创建设置同步表
-async fn create_settings_sync_table(pool: &MySqlPool) -> Result<(), String> {
-    let create_table_sql = r#"
-        CREATE TABLE IF NOT EXISTS todo_settings_sync (
-            id INT AUTO_INCREMENT PRIMARY KEY,
-            update_time VARCHAR(50) NOT NULL COMMENT '更新时间',
-            field_name VARCHAR(100) NOT NULL UNIQUE COMMENT '字段名',
-            data_type VARCHAR(50) NOT NULL COMMENT '数据类型',
-            field_value TEXT NOT NULL COMMENT '字段值',
-            last_update VARCHAR(50) NOT NULL COMMENT '最后更新时间',
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
-            INDEX idx_field_name (field_name),
-            INDEX idx_last_update (last_update)
-        ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci
-    "#;
-    
-    sqlx::query(create_table_sql)
-        .execute(pool)
-        .await
-        .map_err(|e| format!("创建设置同步表失败: {}", e))?;
+    // 清理重复的设置数据
+    backend.cleanup_duplicate_settings().await?;
+    messages.push("清理了重复的设置数据".to_string());
     
-    Ok(())
-}
-
-// 创建待办同步表
-async fn create_todos_sync_table(pool: &MySqlPool) -> Result<(), String> {
-    let create_table_sql = r#"
-        CREATE TABLE IF NOT EXISTS todo_items_sync (
-            id VARCHAR(36) PRIMARY KEY COMMENT '待办事项ID (UUID)',
-            parent_id VARCHAR(36) NULL COMMENT '父项ID，支持树形结构 (UUID)',
-            text TEXT NOT NULL COMMENT '待办事项内容',
-            completed BOOLEAN NOT NULL DEFAULT FALSE COMMENT '是否完成',
-            created_at VARCHAR(50) NOT NULL COMMENT '创建时间',
-            completed_at VARCHAR(50) NULL COMMENT '完成时间',
-            deadline VARCHAR(50) NULL COMMENT '截止时间',
-            is_deleted BOOLEAN NOT NULL DEFAULT FALSE COMMENT '是否已删除（逻辑删除）',
-            last_update VARCHAR(50) NOT NULL COMMENT '最后更新时间',
-            created_timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            updated_timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
-            INDEX idx_id (id),
-            INDEX idx_parent_id (parent_id),
-            INDEX idx_completed (completed),
-            INDEX idx_is_deleted (is_deleted),
-            INDEX idx_last_update (last_update),
-            INDEX idx_deadline (deadline),
-            INDEX idx_created_timestamp (created_timestamp),
-            INDEX idx_updated_timestamp (updated_timestamp)
-        ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci
-    "#;
-    
-    sqlx::query(create_table_sql)
-        .execute(pool)
-        .await
-        .map_err(|e| format!("创建待办同步表失败: {}", e))?;
+    // 检查数据完整性
+    let integrity_status = backend.check_data_integrity().await?;
+    messages.push(integrity_status);
     
-    Ok(())
+    Ok(messages.join("；"))
 }
 
 // 注意：initialize_database_tables 函数已被 check_and_initialize_tables 替代
@@ -512,12 +288,12 @@ pub async fn connect_database(
     config: DatabaseConfig,
     state: State<'_, DatabaseState>
 ) -> Result<(), String> {
-    let pool = create_pool(&config).await?;
+    let backend = backend::connect(&config).await?;
     
-    // 保存连接池和配置到状态
+    // 保存连接和配置到状态
     {
-        let mut pool_guard = state.pool.lock().await;
-        *pool_guard = Some(pool);
+        let mut backend_guard = state.backend.lock().await;
+        *backend_guard = Some(backend.clone());
     }
     
     {
@@ -525,288 +301,37 @@ pub async fn connect_database(
         *config_guard = Some(config);
     }
     
-    Ok(())
-}
-
-
-// 同步设置数据（智能同步逻辑）
-async fn sync_settings_data(
-    pool: &MySqlPool,
-    local_settings: &Value,
-    local_last_update: &str
-) -> Result<usize, String> {
-    // 开始事务
-    let mut tx = pool.begin().await
-        .map_err(|e| format!("开始事务失败: {}", e))?;
-    
-    let mut synced_count = 0;
-    
-    // 检查远程是否有 lastUpdate 字段
-    let remote_last_update_query = "SELECT field_value FROM todo_settings_sync WHERE field_name = 'lastUpdate' LIMIT 1";
-    let remote_last_update = match sqlx::query(remote_last_update_query)
-        .fetch_optional(&mut *tx)
-        .await
-        .map_err(|e| format!("查询远程lastUpdate失败: {}", e))?
-    {
-        Some(row) => {
-            let value: String = row.get("field_value");
-            // 去掉JSON字符串的引号
-            value.trim_matches('"').to_string()
-        }
-        None => String::new(), // 远程没有数据
-    };
-    
-    // 决定同步方向
-    let should_upload = if remote_last_update.is_empty() {
-        // 远程没有数据，直接上传本地数据
-        true
-    } else {
-        // 比较时间戳，本地较新则上传
-        match (chrono::DateTime::parse_from_rfc3339(local_last_update), 
-               chrono::DateTime::parse_from_rfc3339(&remote_last_update)) {
-            (Ok(local_time), Ok(remote_time)) => local_time > remote_time,
-            _ => true, // 解析失败时默认上传本地数据
-        }
-    };
-    
-    if should_upload {
-        // 上传本地设置到远程
-        if let Some(settings_obj) = local_settings.as_object() {
-            for (key, value) in settings_obj {
-                let field_value = serde_json::to_string(value)
-                    .map_err(|e| format!("序列化设置值失败: {}", e))?;
-                
-                let data_type = match value {
-                    Value::String(_) => "string",
-                    Value::Number(_) => "number",
-                    Value::Bool(_) => "boolean",
-                    Value::Object(_) => "object",
-                    Value::Array(_) => "array",
-                    Value::Null => "null",
-                };
-                
-                // 使用 INSERT ... ON DUPLICATE KEY UPDATE 实现逐字段更新
-                let upsert_query = r#"
-                    INSERT INTO todo_settings_sync (update_time, field_name, data_type, field_value, last_update)
-                    VALUES (?, ?, ?, ?, ?)
-                    ON DUPLICATE KEY UPDATE
-                        update_time = VALUES(update_time),
-                        data_type = VALUES(data_type),
-                        field_value = VALUES(field_value),
-                        last_update = VALUES(last_update)
-                "#;
-                
-                sqlx::query(upsert_query)
-                    .bind(local_last_update)
-                    .bind(key)
-                    .bind(data_type)
-                    .bind(&field_value)
-                    .bind(local_last_update)
-                    .execute(&mut *tx)
-                    .await
-                    .map_err(|e| format!("同步设置数据失败: {}", e))?;
-                
-                synced_count += 1;
-            }
-        }
-    }
-    
-    // 提交事务
-    tx.commit().await
-        .map_err(|e| format!("提交事务失败: {}", e))?;
-    
-    Ok(synced_count)
-}
-
-// 同步待办数据（带事务保护）
-async fn sync_todos_data(
-    pool: &MySqlPool,
-    local_todos: &[Value],
-    local_last_update: &str
-) -> Result<usize, String> {
-    // 开始事务
-    let mut tx = pool.begin().await
-        .map_err(|e| format!("开始事务失败: {}", e))?;
-    
-    let mut synced_count = 0;
-    
-    for todo in local_todos {
-        if let Some(todo_obj) = todo.as_object() {
-            let id = todo_obj.get("id")
-                .and_then(|v| v.as_str())
-                .ok_or("待办事项缺少ID")?;
-            
-            let text = todo_obj.get("text")
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-            
-            let completed = todo_obj.get("completed")
-                .and_then(|v| v.as_bool())
-                .unwrap_or(false);
-            
-            let created_at = todo_obj.get("createdAt")
-                .and_then(|v| v.as_str())
-                .unwrap_or(&local_last_update);
-            
-            let completed_at = todo_obj.get("completedAt")
-                .and_then(|v| v.as_str());
-            
-            let deadline = todo_obj.get("deadline")
-                .and_then(|v| v.as_str());
-            
-            let parent_id = todo_obj.get("parentId")
-                .and_then(|v| v.as_str());
-            
-            // 检查是否已删除（逻辑删除）
-            let is_deleted = todo_obj.get("isDeleted")
-                .and_then(|v| v.as_bool())
-                .unwrap_or(false);
-            
-            // 插入或更新待办事项
-            let query = r#"
-                INSERT INTO todo_items_sync (id, parent_id, text, completed, created_at, completed_at, deadline, is_deleted, last_update)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
-                ON DUPLICATE KEY UPDATE
-                    parent_id = VALUES(parent_id),
-                    text = VALUES(text),
-                    completed = VALUES(completed),
-                    created_at = VALUES(created_at),
-                    completed_at = VALUES(completed_at),
-                    deadline = VALUES(deadline),
-                    is_deleted = VALUES(is_deleted),
-                    last_update = VALUES(last_update)
-            "#;
-            
-            sqlx::query(query)
-                .bind(id)
-                .bind(parent_id)
-                .bind(text)
-                .bind(completed)
-                .bind(created_at)
-                .bind(completed_at)
-                .bind(deadline)
-                .bind(is_deleted)
-                .bind(local_last_update)
-                .execute(&mut *tx)
-                .await
-                .map_err(|e| format!("同步待办数据失败: {}", e))?;
-            
-            synced_count += 1;
-        }
+    // 按顺序回放离线期间的操作，失败的操作连同错误保留在队列中，不影响连接；
+    // 与同步一样先检查并升级远程结构，远程结构比当前程序新时不回放
+    let _sync_guard = state.sync_lock.lock().await;
+    if backend.ensure_schema().await.is_ok() {
+        let _ = outbox::replay(backend.as_ref()).await;
     }
     
-    // 提交事务
-    tx.commit().await
-        .map_err(|e| format!("提交事务失败: {}", e))?;
-    
-    Ok(synced_count)
-}
-
-// 从远程下载设置数据
-async fn download_settings_data(pool: &MySqlPool) -> Result<Value, String> {
-    let query = "SELECT field_name, field_value FROM todo_settings_sync WHERE field_name != 'last_update'";
-    
-    let rows = sqlx::query(query)
-        .fetch_all(pool)
-        .await
-        .map_err(|e| format!("下载设置数据失败: {}", e))?;
-    
-    let mut settings = serde_json::Map::new();
-    
-    for row in rows {
-        let field_name: String = row.get("field_name");
-        let field_value: String = row.get("field_value");
-        
-        // 尝试解析 JSON 值
-        let parsed_value: Value = serde_json::from_str(&field_value)
-            .unwrap_or(Value::String(field_value));
-        
-        settings.insert(field_name, parsed_value);
-    }
-    
-    Ok(Value::Object(settings))
+    Ok(())
 }
 
-// 从远程下载待办数据
-async fn download_todos_data(pool: &MySqlPool) -> Result<Vec<Value>, String> {
-    let query = r#"
-        SELECT id, parent_id, text, completed, created_at, completed_at, deadline, is_deleted, last_update
-        FROM todo_items_sync
-        WHERE is_deleted = FALSE
-        ORDER BY created_timestamp
-    "#;
-    
-    let rows = sqlx::query(query)
-        .fetch_all(pool)
-        .await
-        .map_err(|e| format!("下载待办数据失败: {}", e))?;
-    
-    let mut todos = Vec::new();
-    
-    for row in rows {
-        let mut todo = serde_json::Map::new();
-        
-        todo.insert("id".to_string(), Value::String(row.get::<String, _>("id")));
-        
-        if let Some(parent_id) = row.get::<Option<String>, _>("parent_id") {
-            todo.insert("parentId".to_string(), Value::String(parent_id));
-        }
-        
-        todo.insert("text".to_string(), Value::String(row.get::<String, _>("text")));
-        todo.insert("completed".to_string(), Value::Bool(row.get::<bool, _>("completed")));
-        todo.insert("createdAt".to_string(), Value::String(row.get::<String, _>("created_at")));
-        
-        if let Some(completed_at) = row.get::<Option<String>, _>("completed_at") {
-            todo.insert("completedAt".to_string(), Value::String(completed_at));
-        }
-        
-        if let Some(deadline) = row.get::<Option<String>, _>("deadline") {
-            todo.insert("deadline".to_string(), Value::String(deadline));
-        }
-        
-        // 添加isDeleted字段（虽然查询时已过滤，但保持数据结构一致）
-        todo.insert("isDeleted".to_string(), Value::Bool(row.get::<bool, _>("is_deleted")));
-        
-        // 添加lastUpdate字段
-        todo.insert("lastUpdate".to_string(), Value::String(row.get::<String, _>("last_update")));
-        
-        todos.push(Value::Object(todo));
-    }
-    
-    Ok(todos)
-}
 
 // 获取远程数据用于比较
 #[tauri::command]
 pub async fn get_remote_data_for_comparison(
     state: State<'_, DatabaseState>
 ) -> Result<Value, String> {
-    let pool_guard = state.pool.lock().await;
-    let pool = pool_guard.as_ref()
-        .ok_or("数据库连接未建立")?;
+    let backend = current_backend(&state).await?;
     
     // 获取远程待办数据
-    let remote_todos = download_todos_data(pool).await?;
+    let remote_todos = backend.download_todos(false).await?;
     
     // 获取远程设置数据
-    let remote_settings = download_settings_data(pool).await?;
-    
-    // 获取远程数据的最后更新时间
-    let last_update_query = r#"
-        SELECT MAX(last_update) as last_update 
-        FROM (
-            SELECT last_update FROM todo_items_sync
-            UNION ALL
-            SELECT last_update FROM todo_settings_sync
-        ) as all_updates
-    "#;
+    let remote_settings = backend.download_settings().await?;
     
-    let last_update_result = sqlx::query(last_update_query)
-        .fetch_one(pool)
-        .await
-        .map_err(|e| format!("获取远程数据更新时间失败: {}", e))?;
+    // 获取远程数据的最后更新时间（解析后按时刻比较）
+    let todos_last_update = backend.todos_last_update().await?;
+    let settings_last_update = backend.settings_last_update().await?
+        .as_deref()
+        .and_then(parse_utc);
     
-    let last_update: Option<String> = last_update_result.get("last_update");
+    let last_update = todos_last_update.max(settings_last_update).map(format_utc);
     
     let result = serde_json::json!({
         "todos": remote_todos,
@@ -822,94 +347,232 @@ pub async fn get_remote_data_for_comparison(
 pub async fn cleanup_duplicate_data(
     state: State<'_, DatabaseState>
 ) -> Result<String, String> {
-    let pool_guard = state.pool.lock().await;
-    let pool = pool_guard.as_ref()
-        .ok_or("数据库连接未建立")?;
+    let backend = current_backend(&state).await?;
     
     let mut messages: Vec<String> = Vec::new();
     
     // 清理设置表重复数据
-    cleanup_duplicate_settings(pool).await?;
+    backend.cleanup_duplicate_settings().await?;
     messages.push("清理了设置表重复数据".to_string());
     
     // 检查数据完整性
-    let integrity_status = check_data_integrity(pool).await?;
+    let integrity_status = backend.check_data_integrity().await?;
     messages.push(integrity_status);
     
     Ok(messages.join("；"))
 }
 
 
-// 开始数据库同步（智能同步逻辑）
+// 开始数据库同步（待办逐项三方合并，设置按时间戳同步）
 #[tauri::command]
 pub async fn start_database_sync(
+    app: AppHandle,
     state: State<'_, DatabaseState>
 ) -> Result<SyncResult, String> {
-    let pool_guard = state.pool.lock().await;
-    let pool = pool_guard.as_ref()
-        .ok_or("数据库连接未建立")?;
+    run_database_sync(&app, &state).await
+}
+
+// 预览同步计划：按 start_database_sync 的步骤在内存中模拟，不修改本地与远程数据
+#[tauri::command]
+pub async fn preview_database_sync(
+    state: State<'_, DatabaseState>
+) -> Result<SyncPlan, String> {
+    let _sync_guard = state.sync_lock.lock().await;
+    let backend = current_backend(&state).await?;
+    
+    let database_key = {
+        let config_guard = state.config.lock().await;
+        config_guard.as_ref()
+            .map(DatabaseConfig::sync_key)
+            .unwrap_or_default()
+    };
+    
+    // 同步会先升级远程结构；尚未初始化的数据库按空库预览，其余情况需要先升级
+    let schema = backend.schema_status().await?;
+    if !schema.compatible {
+        return Err(schema.error.clone().unwrap_or_else(|| "远程数据库结构与当前程序不兼容".to_string()));
+    }
+    let initialized = schema.current_version > 0;
+    if initialized && !schema.pending_versions.is_empty() {
+        return Err(format!(
+            "远程数据库结构需要从 v{} 升级到 v{}，请先检查并升级表结构后再预览",
+            schema.current_version, schema.latest_version
+        ));
+    }
+    
+    // 离线队列先于合并回放
+    let mut remote = if initialized {
+        RemoteSnapshot {
+            todos: backend.download_todos(true).await?,
+            settings: backend.download_settings().await?,
+            settings_last_update: backend.settings_last_update().await?,
+            settings_times: backend.download_settings_times().await?,
+            purged: backend.purged_todo_ids().await?.into_iter().collect(),
+        }
+    } else {
+        RemoteSnapshot {
+            todos: Vec::new(),
+            settings: Map::new(),
+            settings_last_update: None,
+            settings_times: HashMap::new(),
+            purged: HashSet::new(),
+        }
+    };
+    let pending = outbox::load_pending_operations().await?;
+    remote.replay(&pending);
+    
+    let local_settings = load_app_settings().await?;
+    let purge_remote = match trash_retention_cutoff(&local_settings) {
+        Some(before) if initialized => remote.purge(before),
+        _ => Vec::new(),
+    };
+    
+    // 操作日志中其他设备的修改先应用到本地
+    let local_todos = load_todos().await?;
+    let now = format_utc(Utc::now());
+    let ops = if initialized {
+        oplog::preview_ops(backend.as_ref(), &database_key, local_todos.data).await?
+    } else {
+        oplog::OpsPreview { upload: 0, download: 0, todos: local_todos.data }
+    };
+    let local_last_update = match (ops.download, local_todos.last_update) {
+        (0, Some(last_update)) => last_update,
+        _ => now.clone(),
+    };
     
-    // 获取本地数据
-    let local_todos = load_todos()?;
-    let local_settings = load_app_settings()?;
-    let default_time = chrono::Utc::now().to_rfc3339();
+    let mut base = load_sync_base(&database_key).await?;
+    let mut pending_conflicts = load_sync_conflicts().await?;
+    let mut local = ops.todos.clone();
+    drop_purged(&mut local, &mut base.items, &mut pending_conflicts, &remote.purged);
+    let outcome = merge_todos(
+        &local,
+        &local_last_update,
+        &remote.todos,
+        &base.items,
+        &pending_conflicts,
+        &store::archived_ids().await?,
+        &now,
+    );
+    let todos = TodoSyncPlan {
+        purge_remote,
+        ..plan_todo_sync(&ops.todos, &remote.todos, &base.items, &outcome)
+    };
     
-    // 获取本地待办事项的 lastUpdate
-    let local_todos_last_update = local_todos.get("lastUpdate")
-        .and_then(|v| v.as_str())
-        .unwrap_or(&default_time);
+    let settings = merge_settings(
+        &local_settings.to_sync_map(),
+        &load_settings_times().await?,
+        local_settings.last_update.as_deref(),
+        &remote.settings,
+        &remote.settings_times,
+        remote.settings_last_update.as_deref(),
+    );
     
-    // 获取本地设置的 lastUpdate（如果存在）
-    let local_settings_last_update = local_settings.get("lastUpdate")
-        .and_then(|v| v.as_str())
-        .unwrap_or(&default_time);
+    let destructive = todos.is_destructive() || !settings.download_fields.is_empty();
     
-    // 检查远程待办事项的 lastUpdate
-    let remote_todos_last_update_query = "SELECT MAX(last_update) as last_update FROM todo_items_sync";
-    let remote_todos_last_update = match sqlx::query(remote_todos_last_update_query)
-        .fetch_optional(pool)
-        .await
-        .map_err(|e| format!("查询远程待办事项lastUpdate失败: {}", e))?
-    {
-        Some(row) => {
-            let value: Option<String> = row.get("last_update");
-            value
+    Ok(SyncPlan {
+        schema,
+        pending_operations: pending.len(),
+        ops_to_upload: ops.upload,
+        ops_to_download: ops.download,
+        todos,
+        settings,
+        destructive,
+    })
+}
+
+// 执行一次完整同步，供同步命令与自动同步共用
+//
+// 同步失败（包括未连接）时，把自上次同步以来的本地修改加入离线队列，重连后回放。
+pub async fn run_database_sync(app: &AppHandle, state: &DatabaseState) -> Result<SyncResult, String> {
+    let _sync_guard = state.sync_lock.lock().await;
+    
+    let result = sync_with_backend(app, state).await;
+    if let Err(e) = &result {
+        if let Err(queue_error) = enqueue_local_changes(state).await {
+            return Err(format!("{}；加入离线队列失败: {}", e, queue_error));
         }
-        None => None, // 远程没有待办事项数据
+    }
+    result
+}
+
+// 把自上次同步以来的本地待办与设置修改加入离线队列
+async fn enqueue_local_changes(state: &DatabaseState) -> Result<(), String> {
+    let database_key = {
+        let config_guard = state.config.lock().await;
+        config_guard.as_ref()
+            .map(DatabaseConfig::sync_key)
+            .unwrap_or_default()
     };
+    let base = load_sync_base(&database_key).await?;
     
-    // 检查远程设置的 lastUpdate
-    let remote_settings_last_update_query = "SELECT field_value FROM todo_settings_sync WHERE field_name = 'lastUpdate' LIMIT 1";
-    let remote_settings_last_update = match sqlx::query(remote_settings_last_update_query)
-        .fetch_optional(pool)
-        .await
-        .map_err(|e| format!("查询远程设置lastUpdate失败: {}", e))?
-    {
-        Some(row) => {
-            let value: String = row.get("field_value");
-            // 去掉JSON字符串的引号
-            Some(value.trim_matches('"').to_string())
+    let mut operations: Vec<Operation> = changed_since_base(&load_todos().await?.data, &base)
+        .into_iter()
+        .map(|todo| Operation::UpsertTodo { todo: Box::new(todo) })
+        .collect();
+    
+    let local_settings = load_app_settings().await?;
+    if let Some(last_update) = local_settings.last_update.as_deref() {
+        let changed = match (parse_utc(last_update), parse_utc(&base.last_sync)) {
+            (Some(local_time), Some(base_time)) => local_time > base_time,
+            _ => true,
+        };
+        if changed {
+            operations.push(Operation::UploadSettings {
+                settings: local_settings.to_sync_map(),
+                last_update: last_update.to_string(),
+                field_times: load_settings_times().await?,
+            });
         }
-        None => None, // 远程没有设置数据
+    }
+    
+    outbox::enqueue(operations).await
+}
+
+async fn sync_with_backend(app: &AppHandle, state: &DatabaseState) -> Result<SyncResult, String> {
+    let backend = current_backend(state).await?;
+    
+    // 远程结构比当前程序新时拒绝同步，旧结构先升级
+    backend.ensure_schema().await?;
+    
+    // 先按顺序回放离线期间的操作，再进行合并
+    outbox::replay(backend.as_ref()).await?;
+    
+    // 基线按数据库区分，切换数据库后重新建立
+    let database_key = {
+        let config_guard = state.config.lock().await;
+        config_guard.as_ref()
+            .map(DatabaseConfig::sync_key)
+            .unwrap_or_default()
+    };
+    
+    // 交换操作日志，其他设备的修改先应用到本地，再与远程待办表逐项合并
+    let (ops_uploaded, ops_downloaded) = oplog::sync_ops(backend.as_ref(), &database_key).await?;
+    
+    let local_settings = load_app_settings().await?;
+    
+    // 按回收站保留期限彻底删除过期的待办，合并时本地随之移除
+    let purged = match trash_retention_cutoff(&local_settings) {
+        Some(before) => backend.purge_deleted_todos(before).await?.len(),
+        None => 0,
     };
     
+    // 检查远程待办事项的 lastUpdate
+    let remote_todos_last_update = backend.todos_last_update().await?.map(format_utc);
+    
     let mut sync_messages = Vec::new();
+    if ops_uploaded > 0 || ops_downloaded > 0 {
+        sync_messages.push(format!("操作日志: 上传 {} 条，下载 {} 条", ops_uploaded, ops_downloaded));
+    }
+    if purged > 0 {
+        sync_messages.push(format!("回收站: 已彻底删除 {} 项过期待办", purged));
+    }
+    
+    // 待办事项逐项合并
+    let (todo_stats, todos_message) = sync_todos_per_item(backend.as_ref(), &database_key).await?;
     
-    // 分别处理待办事项和设置的同步
-    let (todos_synced, todos_message) = sync_todos_with_separate_time(
-        pool, 
-        &local_todos, 
-        local_todos_last_update, 
-        remote_todos_last_update.as_deref()
-    ).await?;
-    
-    let (settings_synced, settings_message) = sync_settings_with_separate_time(
-        pool, 
-        &local_settings, 
-        local_settings_last_update, 
-        remote_settings_last_update.as_deref()
-    ).await?;
+    let (settings_synced, settings_message) = sync_settings_per_field(app, backend.as_ref()).await?;
     
+    let todos_synced = todo_stats.uploaded + todo_stats.downloaded + todo_stats.merged + todo_stats.purged;
     let synced_items = todos_synced + settings_synced;
     
     // 组合同步消息
@@ -920,7 +583,7 @@ pub async fn start_database_sync(
         sync_messages.push(settings_message);
     }
     
-    let message = if sync_messages.is_empty() {
+    let message = if synced_items == 0 && todo_stats.conflicted == 0 {
         "数据已是最新版本，无需同步".to_string()
     } else {
         format!("同步完成，共处理 {} 项数据。{}", synced_items, sync_messages.join(" "))
@@ -930,62 +593,88 @@ pub async fn start_database_sync(
         success: true,
         message,
         data: Some(SyncData {
-            local_last_update: local_todos_last_update.to_string(),
+            local_last_update: todo_stats.local_last_update.clone(),
             remote_last_update: remote_todos_last_update.unwrap_or_else(|| "无远程数据".to_string()),
             synced_items,
+            uploaded_items: todo_stats.uploaded,
+            downloaded_items: todo_stats.downloaded,
+            merged_items: todo_stats.merged,
+            conflicted_items: todo_stats.conflicted,
         }),
     })
 }
 
+// 强制推送：本地待办按各自的修改时间覆盖远程，远程多出的待办（不在本地列表与归档中）在远程逻辑删除
+//
+// 推送后的状态作为新的同步基线，本地已推送待办的未解决冲突随之移除。
+#[tauri::command]
+pub async fn force_push_todos(
+    state: State<'_, DatabaseState>
+) -> Result<SyncResult, String> {
+    let _sync_guard = state.sync_lock.lock().await;
+    let backend = current_backend(&state).await?;
+    
+    backend.ensure_schema().await?;
+    outbox::replay(backend.as_ref()).await?;
+    
+    let database_key = {
+        let config_guard = state.config.lock().await;
+        config_guard.as_ref()
+            .map(DatabaseConfig::sync_key)
+            .unwrap_or_default()
+    };
+    let now = format_utc(Utc::now());
+    
+    // 远程已彻底删除的待办不再写回
+    let purged: HashSet<String> = backend.purged_todo_ids().await?.into_iter().collect();
+    let mut upload: Vec<Todo> = load_todos().await?.data
+        .into_iter()
+        .filter(|todo| !purged.contains(&todo.id))
+        .collect();
+    let uploaded = upload.len();
+    
+    let keep: HashSet<String> = upload.iter()
+        .map(|todo| todo.id.clone())
+        .chain(store::archived_ids().await?)
+        .collect();
+    for mut todo in backend.download_todos(false).await? {
+        if !keep.contains(&todo.id) {
+            todo.is_deleted = true;
+            todo.last_update = Some(now.clone());
+            upload.push(todo);
+        }
+    }
+    let deleted = upload.len() - uploaded;
+    
+    if !upload.is_empty() {
+        backend.upsert_todos(&upload, &now).await?;
+    }
+    
+    let base = SyncBase {
+        database: database_key,
+        last_sync: now,
+        items: upload.into_iter().map(|todo| (todo.id.clone(), todo)).collect(),
+    };
+    let mut conflicts = load_sync_conflicts().await?;
+    conflicts.retain(|conflict| !base.items.contains_key(&conflict.id));
+    save_sync_base(&base).await?;
+    save_sync_conflicts(&conflicts).await?;
+    
+    Ok(SyncResult {
+        success: true,
+        message: format!("强制推送完成：已上传 {} 项，远程删除 {} 项", uploaded, deleted),
+        data: None,
+    })
+}
+
 // 逻辑删除待办事项（支持级联删除子项，带事务保护）
 #[tauri::command]
 pub async fn delete_todo_logically(
     todo_id: String,
     state: State<'_, DatabaseState>
 ) -> Result<bool, String> {
-    let pool_guard = state.pool.lock().await;
-    let pool = pool_guard.as_ref()
-        .ok_or("数据库连接未建立")?;
-    
-    let current_time = chrono::Utc::now().to_rfc3339();
-    
-    // 开始事务
-    let mut tx = pool.begin().await
-        .map_err(|e| format!("开始事务失败: {}", e))?;
-    
-    // 使用递归CTE查找所有子项
-    let cascade_delete_query = r#"
-        WITH RECURSIVE todo_hierarchy AS (
-            -- 基础查询：找到要删除的根项
-            SELECT id, parent_id, 0 as level
-            FROM todo_items_sync 
-            WHERE id = ? AND is_deleted = FALSE
-            
-            UNION ALL
-            
-            -- 递归查询：找到所有子项
-            SELECT t.id, t.parent_id, th.level + 1
-            FROM todo_items_sync t
-            INNER JOIN todo_hierarchy th ON t.parent_id = th.id
-            WHERE t.is_deleted = FALSE
-        )
-        UPDATE todo_items_sync 
-        SET is_deleted = TRUE, last_update = ?
-        WHERE id IN (SELECT id FROM todo_hierarchy)
-    "#;
-    
-    let result = sqlx::query(cascade_delete_query)
-        .bind(&todo_id)
-        .bind(&current_time)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| format!("级联逻辑删除待办事项失败: {}", e))?;
-    
-    // 提交事务
-    tx.commit().await
-        .map_err(|e| format!("提交事务失败: {}", e))?;
-    
-    Ok(result.rows_affected() > 0)
+    // 级联删除所有子项（事务由后端负责）
+    apply_or_enqueue(&state, Operation::DeleteTodo { id: todo_id }).await
 }
 
 // 恢复已删除的待办事项（支持级联恢复，带事务保护）
@@ -994,57 +683,8 @@ pub async fn restore_todo(
     todo_id: String,
     state: State<'_, DatabaseState>
 ) -> Result<bool, String> {
-    let pool_guard = state.pool.lock().await;
-    let pool = pool_guard.as_ref()
-        .ok_or("数据库连接未建立")?;
-    
-    let current_time = chrono::Utc::now().to_rfc3339();
-    
-    // 开始事务
-    let mut tx = pool.begin().await
-        .map_err(|e| format!("开始事务失败: {}", e))?;
-    
-    // 使用递归CTE查找所有需要恢复的项（包括父项和子项）
-    let cascade_restore_query = r#"
-        WITH RECURSIVE todo_hierarchy AS (
-            -- 基础查询：找到要恢复的根项
-            SELECT id, parent_id, 0 as level
-            FROM todo_items_sync 
-            WHERE id = ? AND is_deleted = TRUE
-            
-            UNION ALL
-            
-            -- 向上递归：找到所有父项（如果父项也被删除了）
-            SELECT t.id, t.parent_id, th.level + 1
-            FROM todo_items_sync t
-            INNER JOIN todo_hierarchy th ON t.id = th.parent_id
-            WHERE t.is_deleted = TRUE
-            
-            UNION ALL
-            
-            -- 向下递归：找到所有子项
-            SELECT t.id, t.parent_id, th.level + 1
-            FROM todo_items_sync t
-            INNER JOIN todo_hierarchy th ON t.parent_id = th.id
-            WHERE t.is_deleted = TRUE
-        )
-        UPDATE todo_items_sync 
-        SET is_deleted = FALSE, last_update = ?
-        WHERE id IN (SELECT id FROM todo_hierarchy)
-    "#;
-    
-    let result = sqlx::query(cascade_restore_query)
-        .bind(&todo_id)
-        .bind(&current_time)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| format!("级联恢复待办事项失败: {}", e))?;
-    
-    // 提交事务
-    tx.commit().await
-        .map_err(|e| format!("提交事务失败: {}", e))?;
-    
-    Ok(result.rows_affected() > 0)
+    // 级联恢复，包括被删除的父项和子项
+    apply_or_enqueue(&state, Operation::RestoreTodo { id: todo_id, scope: RestoreScope::WithAncestors }).await
 }
 
 // 安全恢复已删除的待办事项（只恢复当前项和其子项，不恢复父项，带事务保护）
@@ -1053,179 +693,227 @@ pub async fn restore_todo_safe(
     todo_id: String,
     state: State<'_, DatabaseState>
 ) -> Result<bool, String> {
-    let pool_guard = state.pool.lock().await;
-    let pool = pool_guard.as_ref()
-        .ok_or("数据库连接未建立")?;
-    
-    let current_time = chrono::Utc::now().to_rfc3339();
-    
-    // 开始事务
-    let mut tx = pool.begin().await
-        .map_err(|e| format!("开始事务失败: {}", e))?;
-    
-    // 使用递归CTE只查找当前项和其子项
-    let safe_restore_query = r#"
-        WITH RECURSIVE todo_hierarchy AS (
-            -- 基础查询：找到要恢复的根项
-            SELECT id, parent_id, 0 as level
-            FROM todo_items_sync 
-            WHERE id = ? AND is_deleted = TRUE
-            
-            UNION ALL
-            
-            -- 向下递归：只找到子项
-            SELECT t.id, t.parent_id, th.level + 1
-            FROM todo_items_sync t
-            INNER JOIN todo_hierarchy th ON t.parent_id = th.id
-            WHERE t.is_deleted = TRUE
-        )
-        UPDATE todo_items_sync 
-        SET is_deleted = FALSE, last_update = ?
-        WHERE id IN (SELECT id FROM todo_hierarchy)
-    "#;
-    
-    let result = sqlx::query(safe_restore_query)
-        .bind(&todo_id)
-        .bind(&current_time)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| format!("安全恢复待办事项失败: {}", e))?;
-    
-    // 提交事务
-    tx.commit().await
-        .map_err(|e| format!("提交事务失败: {}", e))?;
-    
-    Ok(result.rows_affected() > 0)
+    // 只恢复当前项和其子项
+    apply_or_enqueue(&state, Operation::RestoreTodo { id: todo_id, scope: RestoreScope::Subtree }).await
+}
+
+// 执行远程操作：先回放离线队列保证顺序，因连接不上数据库而失败时加入队列（返回 true），重连后回放
+//
+// 连接正常时的失败（SQL、约束或校验错误）直接返回给调用方，不加入队列。
+//
+// 没有连接数据库时在本地回收站执行，之后的同步会把删除与恢复带到远程。
+async fn apply_or_enqueue(state: &DatabaseState, operation: Operation) -> Result<bool, String> {
+    let _sync_guard = state.sync_lock.lock().await;
+    
+    let Some(backend) = state.backend.lock().await.clone() else {
+        return match &operation {
+            Operation::DeleteTodo { id } => delete_todo_locally(id).await,
+            Operation::RestoreTodo { id, scope } => restore_todo_locally(id, *scope).await,
+            _ => Err("该操作需要连接数据库".to_string()),
+        };
+    };
+    
+    let applied = async {
+        outbox::replay(backend.as_ref()).await?;
+        outbox::apply_now(backend.as_ref(), &operation).await
+    }.await;
+    
+    match applied {
+        Ok(changed) => Ok(changed),
+        // 失败后仍能连上数据库，说明不是连接问题，重试也不会成功
+        Err(e) if backend.ping().await.is_ok() => Err(e),
+        Err(_) => {
+            outbox::enqueue(vec![operation]).await?;
+            Ok(true)
+        }
+    }
 }
 
-// 获取已删除的待办事项列表
+// 获取已删除的待办事项列表（没有连接数据库时返回本地回收站）
 #[tauri::command]
 pub async fn get_deleted_todos(
     state: State<'_, DatabaseState>
-) -> Result<Vec<Value>, String> {
-    let pool_guard = state.pool.lock().await;
-    let pool = pool_guard.as_ref()
-        .ok_or("数据库连接未建立")?;
-    
-    let query = r#"
-        SELECT id, parent_id, text, completed, created_at, completed_at, deadline, is_deleted, last_update
-        FROM todo_items_sync
-        WHERE is_deleted = TRUE
-        ORDER BY updated_timestamp DESC
-    "#;
-    
-    let rows = sqlx::query(query)
-        .fetch_all(pool)
-        .await
-        .map_err(|e| format!("获取已删除待办事项失败: {}", e))?;
-    
-    let mut todos = Vec::new();
-    
-    for row in rows {
-        let mut todo = serde_json::Map::new();
-        
-        todo.insert("id".to_string(), Value::String(row.get::<String, _>("id")));
-        
-        if let Some(parent_id) = row.get::<Option<String>, _>("parent_id") {
-            todo.insert("parentId".to_string(), Value::String(parent_id));
-        }
-        
-        todo.insert("text".to_string(), Value::String(row.get::<String, _>("text")));
-        todo.insert("completed".to_string(), Value::Bool(row.get::<bool, _>("completed")));
-        todo.insert("createdAt".to_string(), Value::String(row.get::<String, _>("created_at")));
-        
-        if let Some(completed_at) = row.get::<Option<String>, _>("completed_at") {
-            todo.insert("completedAt".to_string(), Value::String(completed_at));
-        }
-        
-        if let Some(deadline) = row.get::<Option<String>, _>("deadline") {
-            todo.insert("deadline".to_string(), Value::String(deadline));
-        }
-        
-        todo.insert("isDeleted".to_string(), Value::Bool(row.get::<bool, _>("is_deleted")));
-        
-        todos.push(Value::Object(todo));
+) -> Result<Vec<Todo>, String> {
+    let backend = state.backend.lock().await.clone();
+    match backend {
+        Some(backend) => backend.deleted_todos().await,
+        None => deleted_todos_locally().await,
     }
-    
-    Ok(todos)
 }
 
-// 分离时间同步：待办事项
-async fn sync_todos_with_separate_time(
-    pool: &MySqlPool,
-    local_todos: &Value,
-    local_last_update: &str,
-    remote_last_update: Option<&str>
-) -> Result<(usize, String), String> {
-    let empty_vec = vec![];
-    let todos_data = local_todos.get("data")
-        .and_then(|v| v.as_array())
-        .unwrap_or(&empty_vec);
-    
-    // 比较时间戳决定同步方向
-    let should_upload = if let Some(remote_time_str) = remote_last_update {
-        // 远程有数据，比较时间戳
-        match (chrono::DateTime::parse_from_rfc3339(local_last_update), 
-               chrono::DateTime::parse_from_rfc3339(remote_time_str)) {
-            (Ok(local_time), Ok(remote_time)) => local_time > remote_time,
-            _ => true, // 解析失败时默认上传本地数据
-        }
-    } else {
-        // 远程没有数据，直接上传本地数据
-        true
-    };
+// 彻底删除逻辑删除超过 `older_than_days` 天的待办（级联已删除的子项），其他设备同步时随之移除
+#[tauri::command]
+pub async fn purge_deleted_todos(
+    older_than_days: u32,
+    state: State<'_, DatabaseState>
+) -> Result<usize, String> {
+    let _sync_guard = state.sync_lock.lock().await;
+    let backend = current_backend(&state).await?;
     
-    if should_upload {
-        // 上传本地待办事项到远程
-        let todos_count = sync_todos_data(pool, todos_data, local_last_update).await?;
-        Ok((todos_count, format!("待办事项: 已上传 {} 项到远程", todos_count)))
-    } else {
-        // 从远程下载待办事项到本地
-        let remote_todos = download_todos_data(pool).await?;
-        
-        // 保存到本地
-        let mut local_todos_obj = local_todos.as_object().unwrap().clone();
-        local_todos_obj.insert("data".to_string(), Value::Array(remote_todos.clone()));
-        local_todos_obj.insert("lastUpdate".to_string(), Value::String(remote_last_update.unwrap().to_string()));
+    backend.ensure_schema().await?;
+    let before = Utc::now() - chrono::Duration::days(i64::from(older_than_days));
+    Ok(backend.purge_deleted_todos(before).await?.len())
+}
+
+// 设置中 trashRetentionDays 对应的清除时间点，未设置或为 0 时不自动清除
+fn trash_retention_cutoff(settings: &AppSettings) -> Option<DateTime<Utc>> {
+    let days = settings.trash_retention_days;
+    if days == 0 {
+        return None;
+    }
+    Some(Utc::now() - chrono::Duration::days(i64::try_from(days).ok()?))
+}
+
+// 逐项同步：待办事项（本地、远程与上次同步基线三方合并）
+//
+// 先下载远程数据，再在待办锁内重新读取本地列表进行合并并写回，同步期间的本地修改参与合并而不会被覆盖。
+async fn sync_todos_per_item(
+    backend: &dyn SyncBackend,
+    database_key: &str
+) -> Result<(TodoSyncStats, String), String> {
+    let mut base = load_sync_base(database_key).await?;
+    let mut pending_conflicts = load_sync_conflicts().await?;
+    let remote_todos = backend.download_todos(true).await?;
+    let purged_ids: HashSet<String> = backend.purged_todo_ids().await?.into_iter().collect();
+    let now = format_utc(Utc::now());
+    
+    let (outcome, purged, local_last_update) = save_synced_todos(|local_todos, archived_ids| {
+        let local_last_update = local_todos.last_update.unwrap_or_else(|| now.clone());
         
-        let updated_todos = Value::Object(local_todos_obj);
-        save_todos(updated_todos)?;
+        // 其他设备彻底删除的待办直接从本地移除，不再参与合并
+        let mut local = local_todos.data;
+        let purged = drop_purged(&mut local, &mut base.items, &mut pending_conflicts, &purged_ids);
         
-        Ok((remote_todos.len(), format!("待办事项: 已从远程下载 {} 项", remote_todos.len())))
+        let outcome = merge_todos(
+            &local,
+            &local_last_update,
+            &remote_todos,
+            &base.items,
+            &pending_conflicts,
+            archived_ids,
+            &now,
+        );
+        let changed = (outcome.local_changed() || purged > 0).then(|| outcome.local.clone());
+        Ok((changed, (outcome, purged, local_last_update)))
+    }).await?;
+    
+    // 基线最后写入，远程写入失败时下次同步仍会上传这些修改，基线不会领先于远程
+    if !outcome.upload.is_empty() {
+        backend.upsert_todos(&outcome.upload, &local_last_update).await?;
+    }
+    
+    save_sync_base(&SyncBase {
+        database: database_key.to_string(),
+        last_sync: now,
+        items: outcome.base,
+    }).await?;
+    
+    // 记录未解决的冲突，直到用户通过 resolve_sync_conflict 处理
+    save_sync_conflicts(&outcome.conflicts).await?;
+    
+    let stats = TodoSyncStats {
+        uploaded: outcome.uploaded,
+        downloaded: outcome.downloaded,
+        merged: outcome.merged,
+        conflicted: outcome.conflicts.len(),
+        purged,
+        local_last_update,
+    };
+    
+    let mut parts = Vec::new();
+    if stats.uploaded > 0 {
+        parts.push(format!("已上传 {} 项", stats.uploaded));
+    }
+    if stats.downloaded > 0 {
+        parts.push(format!("已下载 {} 项", stats.downloaded));
+    }
+    if stats.merged > 0 {
+        parts.push(format!("已合并 {} 项", stats.merged));
+    }
+    if stats.purged > 0 {
+        parts.push(format!("已移除 {} 项已彻底删除的待办", stats.purged));
     }
+    if stats.conflicted > 0 {
+        parts.push(format!("{} 项存在冲突待解决", stats.conflicted));
+    }
+    
+    let message = if parts.is_empty() {
+        String::new()
+    } else {
+        format!("待办事项: {}", parts.join("，"))
+    };
+    
+    Ok((stats, message))
 }
 
-// 分离时间同步：设置
-async fn sync_settings_with_separate_time(
-    pool: &MySqlPool,
-    local_settings: &Value,
-    local_last_update: &str,
-    remote_last_update: Option<&str>
+// 逐字段同步设置：每个字段按两端各自的修改时间取较新的一端，只属于本机的字段不参与
+//
+// 远程数据下载后，在设置锁内重新读取本地设置进行合并，只把下载的字段写入本地并广播变化；
+// 同步期间的本地修改不会被覆盖，较新的修改留到下次同步上传。
+async fn sync_settings_per_field(
+    app: &AppHandle,
+    backend: &dyn SyncBackend,
 ) -> Result<(usize, String), String> {
-    // 比较时间戳决定同步方向
-    let should_upload = if let Some(remote_time_str) = remote_last_update {
-        // 远程有数据，比较时间戳
-        match (chrono::DateTime::parse_from_rfc3339(local_last_update), 
-               chrono::DateTime::parse_from_rfc3339(remote_time_str)) {
-            (Ok(local_time), Ok(remote_time)) => local_time > remote_time,
-            _ => true, // 解析失败时默认上传本地数据
+    let remote = backend.download_settings().await?;
+    let remote_times = backend.download_settings_times().await?;
+    let remote_last_update = backend.settings_last_update().await?;
+
+    let (merge, upload) = {
+        let _guard = lock_settings().await;
+        let local_settings = load_app_settings().await?;
+        let local = local_settings.to_sync_map();
+        let local_times = load_settings_times().await?;
+        let local_last_update = local_settings.last_update.as_deref();
+
+        let merge = merge_settings(
+            &local,
+            &local_times,
+            local_last_update,
+            &remote,
+            &remote_times,
+            remote_last_update.as_deref(),
+        );
+        let last_update = latest_time(local_last_update, remote_last_update.as_deref())
+            .unwrap_or_else(|| format_utc(Utc::now()));
+
+        // 本地较新的字段，释放锁后上传
+        let upload = (!merge.upload_fields.is_empty()).then(|| {
+            let (mut settings, field_times) = pick_settings(
+                &local,
+                &local_times,
+                local_last_update.unwrap_or(&last_update),
+                &merge.upload_fields,
+            );
+            settings.insert("lastUpdate".to_string(), Value::String(last_update.clone()));
+            (settings, field_times, last_update.clone())
+        });
+
+        if !merge.download_fields.is_empty() {
+            // 远程较新的字段写入本地，保留远程记录的修改时间
+            let (settings, field_times) = pick_settings(
+                &remote,
+                &remote_times,
+                remote_last_update.as_deref().unwrap_or(&last_update),
+                &merge.download_fields,
+            );
+            apply_app_settings_at(app, local_settings.with_synced_fields(settings), &last_update, &field_times).await?;
         }
-    } else {
-        // 远程没有数据，直接上传本地数据
-        true
+        (merge, upload)
     };
-    
-    if should_upload {
-        // 上传本地设置到远程
-        let settings_count = sync_settings_data(pool, local_settings, local_last_update).await?;
-        Ok((settings_count, format!("设置: 已上传 {} 项到远程", settings_count)))
-    } else {
-        // 从远程下载设置到本地
-        let remote_settings = download_settings_data(pool).await?;
-        let settings_count = remote_settings.as_object().map_or(0, |obj| obj.len());
-        
-        // 保存到本地
-        save_app_settings(remote_settings)?;
-        Ok((settings_count, format!("设置: 已从远程下载 {} 项", settings_count)))
+
+    let mut parts = Vec::new();
+    if let Some((settings, field_times, last_update)) = upload {
+        backend.upload_settings(&settings, &field_times, &last_update).await?;
+        parts.push(format!("已上传 {} 项到远程", merge.upload_fields.len()));
     }
-}
\ No newline at end of file
+    if !merge.download_fields.is_empty() {
+        parts.push(format!("已从远程下载 {} 项", merge.download_fields.len()));
+    }
+
+    let message = if parts.is_empty() {
+        String::new()
+    } else {
+        format!("设置: {}", parts.join("，"))
+    };
+    Ok((merge.upload_fields.len() + merge.download_fields.len(), message))
+}
@@ -0,0 +1,76 @@
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::modules::data::{emit_changes, step_history};
+use crate::modules::store;
+
+/// 撤销栈与重做栈各自保留的最多步数
+pub const HISTORY_LIMIT: usize = 100;
+
+/// 撤销历史中的栈
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryStack {
+    Undo,
+    Redo,
+}
+
+impl HistoryStack {
+    /// 在本地数据库中的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HistoryStack::Undo => "undo",
+            HistoryStack::Redo => "redo",
+        }
+    }
+
+    /// 执行该栈中的一步后，记录移入的另一个栈
+    pub fn other(self) -> HistoryStack {
+        match self {
+            HistoryStack::Undo => HistoryStack::Redo,
+            HistoryStack::Redo => HistoryStack::Undo,
+        }
+    }
+}
+
+/// 可撤销与可重做的步数
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryStatus {
+    pub undo: usize,
+    pub redo: usize,
+}
+
+/// 执行一步撤销或重做并通知前端，栈为空时返回 false
+async fn step(app: &AppHandle, stack: HistoryStack) -> Result<bool, String> {
+    match step_history(stack).await? {
+        Some(delta) => {
+            emit_changes(app, &delta);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// 撤销最近一步待办修改，没有可撤销的修改时返回 false
+///
+/// 批量保存与级联删除作为一步撤销。受影响的待办在之后被其他修改（如同步）改动过时，
+/// 这一步无法撤销，返回错误并从历史中移除。
+#[tauri::command]
+pub async fn undo(app: AppHandle) -> Result<bool, String> {
+    step(&app, HistoryStack::Undo).await
+}
+
+/// 重做最近撤销的一步，没有可重做的修改时返回 false
+#[tauri::command]
+pub async fn redo(app: AppHandle) -> Result<bool, String> {
+    step(&app, HistoryStack::Redo).await
+}
+
+/// 获取可撤销与可重做的步数
+#[tauri::command]
+pub async fn get_history_status() -> Result<HistoryStatus, String> {
+    Ok(HistoryStatus {
+        undo: store::history_len(HistoryStack::Undo).await?,
+        redo: store::history_len(HistoryStack::Redo).await?,
+    })
+}
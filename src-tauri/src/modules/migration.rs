@@ -0,0 +1,298 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+use crate::modules::storage::{backup_file, write_atomic};
+
+/// 文件中记录格式版本的字段名
+pub const FORMAT_VERSION_KEY: &str = "formatVersion";
+
+/// 带格式版本的文件种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// todos.json
+    Todos,
+    /// stage.json
+    Archive,
+    /// settings.json
+    Settings,
+    /// window.json
+    Window,
+    /// da.da（解码后的 JSON）
+    DatabaseConfig,
+    /// trash.json
+    Trash,
+    /// profiles.json
+    Profiles,
+}
+
+/// 单步迁移：把 `from` 版本的内容升级到 `from + 1`
+struct Migration {
+    from: u32,
+    migrate: fn(Value) -> Result<Value, String>,
+}
+
+impl FileKind {
+    fn name(self) -> &'static str {
+        match self {
+            FileKind::Todos => "todos.json",
+            FileKind::Archive => "stage.json",
+            FileKind::Settings => "settings.json",
+            FileKind::Window => "window.json",
+            FileKind::DatabaseConfig => "da.da",
+            FileKind::Trash => "trash.json",
+            FileKind::Profiles => "profiles.json",
+        }
+    }
+
+    /// 该种类文件的迁移注册表，按版本顺序排列
+    fn migrations(self) -> &'static [Migration] {
+        match self {
+            FileKind::Todos => &[Migration { from: 0, migrate: todos_v0_to_v1 }],
+            FileKind::Archive => &[
+                Migration { from: 0, migrate: archive_v0_to_v1 },
+                Migration { from: 1, migrate: archive_v1_to_v2 },
+            ],
+            FileKind::Settings => &[Migration { from: 0, migrate: object_v0_to_v1 }],
+            FileKind::Window => &[Migration { from: 0, migrate: object_v0_to_v1 }],
+            FileKind::DatabaseConfig => &[Migration { from: 0, migrate: object_v0_to_v1 }],
+            FileKind::Trash => &[Migration { from: 0, migrate: object_v0_to_v1 }],
+            FileKind::Profiles => &[Migration { from: 0, migrate: object_v0_to_v1 }],
+        }
+    }
+
+    /// 当前程序写出的格式版本
+    pub fn current_version(self) -> u32 {
+        self.migrations().len() as u32
+    }
+}
+
+/// 读取内容中的格式版本，没有版本字段的旧文件视为版本 0
+fn version_of(value: &Value) -> Result<u32, String> {
+    match value.get(FORMAT_VERSION_KEY) {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| format!("Invalid {}: {}", FORMAT_VERSION_KEY, version)),
+    }
+}
+
+/// 写入格式版本字段
+fn set_version(value: &mut Value, version: u32) -> Result<(), String> {
+    let obj = value
+        .as_object_mut()
+        .ok_or("Versioned content must be a JSON object")?;
+    obj.insert(FORMAT_VERSION_KEY.to_string(), Value::from(version));
+    Ok(())
+}
+
+/// 将内容逐步迁移到当前版本，返回迁移后的内容以及是否发生了迁移
+pub fn migrate(kind: FileKind, mut value: Value) -> Result<(Value, bool), String> {
+    let current = kind.current_version();
+    let mut version = version_of(&value).map_err(|e| format!("{}: {}", kind.name(), e))?;
+
+    if version > current {
+        return Err(format!(
+            "{} has format version {}, but this version of Ton only supports up to {}",
+            kind.name(),
+            version,
+            current
+        ));
+    }
+
+    let migrated = version < current;
+    while version < current {
+        let step = kind
+            .migrations()
+            .iter()
+            .find(|m| m.from == version)
+            .ok_or_else(|| format!("{}: no migration from version {}", kind.name(), version))?;
+        value = (step.migrate)(value)
+            .map_err(|e| format!("{}: migration from version {} failed: {}", kind.name(), version, e))?;
+        version += 1;
+        set_version(&mut value, version)?;
+    }
+
+    Ok((value, migrated))
+}
+
+/// 序列化为带当前格式版本的 JSON 值
+pub fn to_versioned_value<T: Serialize>(kind: FileKind, data: &T) -> Result<Value, String> {
+    let mut value = serde_json::to_value(data)
+        .map_err(|e| format!("Failed to serialize {}: {}", kind.name(), e))?;
+    set_version(&mut value, kind.current_version())?;
+    Ok(value)
+}
+
+/// 读取 JSON 文件并迁移到当前版本；发生迁移时先备份原文件再写回升级后的内容
+pub fn load_versioned(kind: FileKind, path: &Path) -> Result<Option<Value>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let json_str = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", kind.name(), e))?;
+    let value: Value = serde_json::from_str(&json_str)
+        .map_err(|e| format!("Failed to parse {}: {}", kind.name(), e))?;
+
+    let (value, migrated) = migrate(kind, value)?;
+
+    if migrated {
+        if matches!(kind, FileKind::Todos | FileKind::Archive | FileKind::Trash | FileKind::Profiles) {
+            backup_file(path, true)?;
+        }
+        let json_str = serde_json::to_string_pretty(&value)
+            .map_err(|e| format!("Failed to serialize {}: {}", kind.name(), e))?;
+        write_atomic(path, json_str.as_bytes())?;
+    }
+
+    Ok(Some(value))
+}
+
+/// todos.json v0 -> v1：旧版直接保存的待办数组包装为 `{data, source}` 对象
+fn todos_v0_to_v1(value: Value) -> Result<Value, String> {
+    match value {
+        Value::Array(items) => {
+            let mut obj = Map::new();
+            obj.insert("data".to_string(), Value::Array(items));
+            obj.insert("source".to_string(), Value::String("manual".to_string()));
+            Ok(Value::Object(obj))
+        }
+        Value::Object(obj) => Ok(Value::Object(obj)),
+        other => Err(format!("expected an array or object, found {}", other)),
+    }
+}
+
+/// stage.json v0 -> v1：旧版直接保存的归档数组包装为 `{todos, archivedAt}` 对象
+fn archive_v0_to_v1(value: Value) -> Result<Value, String> {
+    match value {
+        Value::Array(items) => {
+            let mut obj = Map::new();
+            obj.insert("todos".to_string(), Value::Array(items));
+            obj.insert("archivedAt".to_string(), Value::String(String::new()));
+            Ok(Value::Object(obj))
+        }
+        Value::Object(obj) => Ok(Value::Object(obj)),
+        other => Err(format!("expected an array or object, found {}", other)),
+    }
+}
+
+/// stage.json v1 -> v2：单个 `{todos, archivedAt}` 归档改为 `{batches}`，原有内容成为一个批次
+fn archive_v1_to_v2(value: Value) -> Result<Value, String> {
+    let Value::Object(mut obj) = value else {
+        return Err(format!("expected an object, found {}", value));
+    };
+    let todos = obj.remove("todos").unwrap_or_else(|| Value::Array(Vec::new()));
+    let archived_at = obj.remove("archivedAt").unwrap_or_else(|| Value::String(String::new()));
+
+    let mut batches = Vec::new();
+    if todos.as_array().is_some_and(|todos| !todos.is_empty()) {
+        let mut batch = Map::new();
+        batch.insert("id".to_string(), Value::String("legacy".to_string()));
+        batch.insert("archivedAt".to_string(), archived_at);
+        batch.insert("todos".to_string(), todos);
+        batches.push(Value::Object(batch));
+    }
+    obj.insert("batches".to_string(), Value::Array(batches));
+    Ok(Value::Object(obj))
+}
+
+/// v0 -> v1：内容本身不变，只需要是对象以便记录版本
+fn object_v0_to_v1(value: Value) -> Result<Value, String> {
+    match value {
+        Value::Object(obj) => Ok(Value::Object(obj)),
+        other => Err(format!("expected an object, found {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn todos_bare_array_is_wrapped() {
+        let old = json!([{ "id": "a", "text": "x" }]);
+        let (value, migrated) = migrate(FileKind::Todos, old).unwrap();
+        assert!(migrated);
+        assert_eq!(value["formatVersion"], 1);
+        assert_eq!(value["source"], "manual");
+        assert_eq!(value["data"][0]["id"], "a");
+    }
+
+    #[test]
+    fn todos_object_without_version_keeps_fields() {
+        let old = json!({ "data": [], "lastUpdate": "2024-01-01T00:00:00Z", "source": "sync" });
+        let (value, migrated) = migrate(FileKind::Todos, old).unwrap();
+        assert!(migrated);
+        assert_eq!(value["formatVersion"], 1);
+        assert_eq!(value["lastUpdate"], "2024-01-01T00:00:00Z");
+        assert_eq!(value["source"], "sync");
+    }
+
+    #[test]
+    fn todos_rejects_scalar() {
+        assert!(migrate(FileKind::Todos, json!("oops")).is_err());
+    }
+
+    #[test]
+    fn archive_bare_array_is_wrapped() {
+        let old = json!([{ "id": "a" }]);
+        let (value, migrated) = migrate(FileKind::Archive, old).unwrap();
+        assert!(migrated);
+        assert_eq!(value["formatVersion"], 2);
+        assert_eq!(value["batches"][0]["todos"][0]["id"], "a");
+        assert_eq!(value["batches"][0]["archivedAt"], "");
+    }
+
+    #[test]
+    fn archive_object_gets_version() {
+        let old = json!({ "todos": [], "archivedAt": "2024-01-01T00:00:00Z" });
+        let (value, _) = migrate(FileKind::Archive, old).unwrap();
+        assert_eq!(value["formatVersion"], 2);
+        assert_eq!(value["batches"], json!([]));
+    }
+
+    #[test]
+    fn archive_v1_becomes_single_batch() {
+        let old = json!({ "formatVersion": 1, "todos": [{ "id": "a" }], "archivedAt": "2024-01-01T00:00:00Z" });
+        let (value, migrated) = migrate(FileKind::Archive, old).unwrap();
+        assert!(migrated);
+        assert_eq!(value["formatVersion"], 2);
+        assert_eq!(value["batches"][0]["archivedAt"], "2024-01-01T00:00:00Z");
+        assert_eq!(value["batches"][0]["todos"][0]["id"], "a");
+        assert!(value.get("todos").is_none());
+    }
+
+    #[test]
+    fn settings_window_and_config_get_version() {
+        for kind in [FileKind::Settings, FileKind::Window, FileKind::DatabaseConfig, FileKind::Profiles, FileKind::Trash] {
+            let (value, migrated) = migrate(kind, json!({ "a": 1 })).unwrap();
+            assert!(migrated);
+            assert_eq!(value["formatVersion"], 1);
+            assert_eq!(value["a"], 1);
+            assert!(migrate(kind, json!([1, 2])).is_err());
+        }
+    }
+
+    #[test]
+    fn current_version_is_left_untouched() {
+        let current = json!({ "formatVersion": 1, "data": [] });
+        let (value, migrated) = migrate(FileKind::Todos, current.clone()).unwrap();
+        assert!(!migrated);
+        assert_eq!(value, current);
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let future = json!({ "formatVersion": 99, "data": [] });
+        assert!(migrate(FileKind::Todos, future).is_err());
+    }
+
+    #[test]
+    fn versioned_value_is_stamped() {
+        let value = to_versioned_value(FileKind::Window, &json!({ "x": 1.0 })).unwrap();
+        assert_eq!(value["formatVersion"], FileKind::Window.current_version());
+    }
+}
@@ -1,12 +1,34 @@
 pub mod app;
+pub mod archive;
+pub mod backend;
+pub mod bundle;
+pub mod crdt;
+pub mod crypto;
 pub mod data;
 pub mod database;
+pub mod history;
+pub mod migration;
+pub mod oplog;
+pub mod outbox;
+pub mod scheduler;
+pub mod schema;
+pub mod storage;
+pub mod store;
+pub mod sync;
 pub mod tray;
 pub mod types;
 pub mod window;
 
 // 重新导出所有命令，方便在lib.rs中使用
 pub use app::*;
+pub use archive::*;
+pub use bundle::*;
 pub use data::*;
 pub use database::*;
+pub use history::*;
+pub use oplog::*;
+pub use outbox::*;
+pub use schema::*;
+pub use storage::*;
+pub use sync::*;
 pub use window::*;
@@ -0,0 +1,405 @@
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::modules::backend::SyncBackend;
+use crate::modules::data::save_remote_ops;
+use crate::modules::storage::write_atomic;
+use crate::modules::store;
+use crate::modules::types::Todo;
+
+// 操作日志是待办修改的审计记录，也是其他设备的字段级修改传到本设备的途径；
+// 待办的当前状态仍以本地数据库为准，同步时与远程的逐项三方合并照常进行。
+
+// 由操作日志推导并写回待办列表时使用的来源，这类写入不再记录操作
+pub const OPLOG_SOURCE: &str = "oplog";
+
+// 操作日志同步进度在本地数据库 sync_meta 中的键
+const OPLOG_STATE_KEY: &str = "oplogState";
+
+// 推进时钟与读取日志时加锁，保证操作按时钟顺序产生
+static LOG_LOCK: Mutex<Option<Clock>> = Mutex::const_new(None);
+
+// 混合逻辑时钟：物理毫秒 + 逻辑计数器，设备 id 用于打破平局，三者给出全序
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hlc {
+    pub wall: i64,
+    pub counter: u32,
+    pub device: String,
+}
+
+// 一次待办修改
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TodoOp {
+    // 全局唯一的操作 id（设备 id + 时钟）
+    pub id: String,
+    pub hlc: Hlc,
+    pub todo_id: String,
+    // 被修改字段的新值（待办的 JSON 字段名，null 表示清除）
+    pub patch: Map<String, Value>,
+}
+
+// 本设备的时钟状态
+struct Clock {
+    device: String,
+    wall: i64,
+    counter: u32,
+}
+
+impl Clock {
+    // 本地产生新操作时推进时钟
+    fn tick(&mut self) -> Hlc {
+        let now = Utc::now().timestamp_millis();
+        if now > self.wall {
+            self.wall = now;
+            self.counter = 0;
+        } else {
+            self.counter += 1;
+        }
+        self.current()
+    }
+
+    // 收到其他设备的操作后推进时钟，保证之后的本地操作排在它之后
+    fn observe(&mut self, remote: &Hlc) {
+        if remote.wall > self.wall {
+            self.wall = remote.wall;
+            self.counter = remote.counter;
+        } else if remote.wall == self.wall {
+            self.counter = self.counter.max(remote.counter);
+        }
+    }
+
+    fn current(&self) -> Hlc {
+        Hlc { wall: self.wall, counter: self.counter, device: self.device.clone() }
+    }
+}
+
+// 同步进度，按数据库区分
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OpSyncState {
+    database: String,
+    // 已上传到远程的日志序号（本地数据库 oplog 表的 seq）
+    uploaded_through: i64,
+    // 已下载的远程操作序号
+    remote_cursor: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceFile {
+    device_id: String,
+}
+
+// 获取本设备 id，首次调用时生成并保存到配置目录
+pub fn device_id() -> Result<String, String> {
+    let config_dir = dirs::config_dir()
+        .ok_or("无法获取配置目录")?
+        .join("Ton")
+        .join("config");
+    let path = config_dir.join("device.json");
+
+    if path.exists() {
+        let json_str = std::fs::read_to_string(&path)
+            .map_err(|e| format!("读取设备 id 失败: {}", e))?;
+        let device: DeviceFile = serde_json::from_str(&json_str)
+            .map_err(|e| format!("解析设备 id 失败: {}", e))?;
+        return Ok(device.device_id);
+    }
+
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    let device = DeviceFile {
+        device_id: uuid::Builder::from_random_bytes(bytes).into_uuid().to_string(),
+    };
+
+    std::fs::create_dir_all(&config_dir)
+        .map_err(|e| format!("创建配置目录失败: {}", e))?;
+    let json_str = serde_json::to_string_pretty(&device)
+        .map_err(|e| format!("序列化设备 id 失败: {}", e))?;
+    write_atomic(&path, json_str.as_bytes())
+        .map_err(|e| format!("保存设备 id 失败: {}", e))?;
+
+    Ok(device.device_id)
+}
+
+// 获取日志锁，首次使用时从日志中恢复时钟
+async fn lock_log() -> Result<MutexGuard<'static, Option<Clock>>, String> {
+    let mut guard = LOG_LOCK.lock().await;
+
+    if guard.is_none() {
+        let mut clock = Clock { device: device_id()?, wall: 0, counter: 0 };
+        for (_, op) in store::read_ops(0).await? {
+            clock.observe(&op.hlc);
+        }
+        *guard = Some(clock);
+    }
+
+    Ok(guard)
+}
+
+fn new_op(clock: &mut Clock, todo_id: &str, patch: Map<String, Value>) -> TodoOp {
+    let hlc = clock.tick();
+    TodoOp {
+        id: format!("{}:{}:{}", hlc.device, hlc.wall, hlc.counter),
+        hlc,
+        todo_id: todo_id.to_string(),
+        patch,
+    }
+}
+
+// 记录到日志中的待办字段
+//
+// 文本副本不记录：每次编辑都会改变整个副本，文本的修改已由 text 字段记录，
+// 副本本身在逐项同步时合并。
+fn todo_fields(todo: &Todo) -> Result<Map<String, Value>, String> {
+    match serde_json::to_value(todo).map_err(|e| format!("序列化待办失败: {}", e))? {
+        Value::Object(mut fields) => {
+            fields.remove("id");
+            fields.remove("textCrdt");
+            Ok(fields)
+        }
+        _ => Err("待办序列化结果不是对象".to_string()),
+    }
+}
+
+// 比较两个版本的待办，返回变化的字段
+fn changed_fields(before: &Map<String, Value>, after: &Map<String, Value>) -> Map<String, Value> {
+    let keys: HashSet<&String> = before.keys().chain(after.keys()).collect();
+    keys.into_iter()
+        .filter(|key| before.get(*key) != after.get(*key))
+        .map(|key| (key.clone(), after.get(key).cloned().unwrap_or(Value::Null)))
+        .collect()
+}
+
+// 为 `todos` 中的每一项生成完整快照，日志为空时先记录，之后受影响待办的状态都可以从日志推导
+pub async fn snapshot_ops(todos: &[Todo]) -> Result<Vec<TodoOp>, String> {
+    let mut guard = lock_log().await?;
+    let clock = guard.as_mut().ok_or("操作日志时钟未初始化")?;
+
+    todos.iter().map(|todo| Ok(new_op(clock, &todo.id, todo_fields(todo)?))).collect()
+}
+
+// 生成从 `before` 到 `after` 的操作：新增与修改记录变化的字段，移除的待办记录为逻辑删除
+//
+// 操作由调用方与待办的修改在同一事务中写入日志。
+pub async fn change_ops(before: &[Todo], after: &[Todo]) -> Result<Vec<TodoOp>, String> {
+    let mut guard = lock_log().await?;
+    let clock = guard.as_mut().ok_or("操作日志时钟未初始化")?;
+
+    let before_fields: HashMap<&str, Map<String, Value>> = before
+        .iter()
+        .map(|todo| Ok((todo.id.as_str(), todo_fields(todo)?)))
+        .collect::<Result<_, String>>()?;
+
+    let mut ops = Vec::new();
+    for todo in after {
+        let fields = todo_fields(todo)?;
+        let patch = match before_fields.get(todo.id.as_str()) {
+            Some(previous) => changed_fields(previous, &fields),
+            // 重新出现的待办（如从回收站恢复）需要显式覆盖之前记录的删除
+            None => {
+                let mut fields = fields;
+                fields.entry("isDeleted").or_insert(Value::Bool(false));
+                fields
+            }
+        };
+        if !patch.is_empty() {
+            ops.push(new_op(clock, &todo.id, patch));
+        }
+    }
+
+    let after_ids: HashSet<&str> = after.iter().map(|todo| todo.id.as_str()).collect();
+    for todo in before {
+        if !after_ids.contains(todo.id.as_str()) {
+            let mut patch = Map::new();
+            patch.insert("isDeleted".to_string(), Value::Bool(true));
+            ops.push(new_op(clock, &todo.id, patch));
+        }
+    }
+
+    Ok(ops)
+}
+
+// 按时钟顺序应用操作，推导每个待办的当前状态（每个字段取最后一次写入的值）
+//
+// 结果包含已逻辑删除的待办，按首次出现的时钟排序；某个待办的字段不完整或无效时返回错误。
+pub fn derive_todos(ops: &[TodoOp]) -> Result<Vec<Todo>, String> {
+    let mut sorted: Vec<&TodoOp> = ops.iter().collect();
+    sorted.sort_by(|a, b| a.hlc.cmp(&b.hlc));
+
+    let mut order: Vec<&str> = Vec::new();
+    let mut states: HashMap<&str, Map<String, Value>> = HashMap::new();
+    for op in sorted {
+        let state = states.entry(op.todo_id.as_str()).or_insert_with(|| {
+            order.push(op.todo_id.as_str());
+            Map::new()
+        });
+        for (key, value) in &op.patch {
+            state.insert(key.clone(), value.clone());
+        }
+    }
+
+    order
+        .into_iter()
+        .filter_map(|id| Some((id, states.remove(id)?)))
+        .map(|(id, mut fields)| {
+            // null 表示字段已清除，按缺省值处理
+            fields.retain(|_, value| !value.is_null());
+            fields.insert("id".to_string(), Value::String(id.to_string()));
+            serde_json::from_value(Value::Object(fields))
+                .map_err(|e| format!("操作日志中待办 {} 的状态无效: {}", id, e))
+        })
+        .collect()
+}
+
+// 用从日志推导出的状态替换受 `ops` 影响的待办，已删除的移除，新增的按创建时间追加
+//
+// 日志中的其他待办不参与推导，其中的错误不影响这次应用。
+fn apply_ops_to_todos(todos: Vec<Todo>, ops: &[TodoOp], log: &[TodoOp]) -> Result<Vec<Todo>, String> {
+    let touched: HashSet<&str> = ops.iter().map(|op| op.todo_id.as_str()).collect();
+    let touched_log: Vec<TodoOp> = log.iter().filter(|op| touched.contains(op.todo_id.as_str())).cloned().collect();
+    let mut derived: HashMap<String, Todo> = derive_todos(&touched_log)?
+        .into_iter()
+        .map(|todo| (todo.id.clone(), todo))
+        .collect();
+
+    let mut data: Vec<Todo> = todos
+        .into_iter()
+        .filter_map(|todo| match derived.remove(&todo.id) {
+            // 补丁不含 textCrdt，已有待办保留本地文本，文本改动由逐项同步的字符级合并带来；
+            // 否则本地会把远程的文本改动当作本设备的编辑写入 CRDT，合并时重复插入字符
+            Some(mut state) => (!state.is_deleted).then(|| {
+                state.text = todo.text;
+                state.text_crdt = todo.text_crdt;
+                state
+            }),
+            None => Some(todo),
+        })
+        .collect();
+    let mut added: Vec<Todo> = derived.into_values().filter(|todo| !todo.is_deleted).collect();
+    added.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    data.extend(added);
+    Ok(data)
+}
+
+async fn load_sync_state(database: &str) -> Result<OpSyncState, String> {
+    let state: OpSyncState = store::read_sync_meta(OPLOG_STATE_KEY).await
+        .map_err(|e| format!("读取操作日志同步进度失败: {}", e))?
+        .unwrap_or_default();
+
+    // 切换数据库后重新上传与下载（远程按操作 id 去重）
+    if state.database != database {
+        return Ok(OpSyncState { database: database.to_string(), ..OpSyncState::default() });
+    }
+
+    Ok(state)
+}
+
+async fn save_sync_state(state: &OpSyncState) -> Result<(), String> {
+    store::write_sync_meta(OPLOG_STATE_KEY, state).await
+        .map_err(|e| format!("保存操作日志同步进度失败: {}", e))
+}
+
+// 与远程交换操作日志：上传本设备的新操作，下载其他设备的操作并应用到本地
+//
+// 返回 (上传数量, 下载数量)。
+pub async fn sync_ops(backend: &dyn SyncBackend, database: &str) -> Result<(usize, usize), String> {
+    let mut state = load_sync_state(database).await?;
+
+    let log = store::read_ops(0).await?;
+    let device = device_id()?;
+    let pending: Vec<TodoOp> = log
+        .iter()
+        .filter(|(seq, op)| *seq > state.uploaded_through && op.hlc.device == device)
+        .map(|(_, op)| op.clone())
+        .collect();
+
+    let uploaded = backend.upload_ops(&pending).await?;
+    if let Some((seq, _)) = log.last() {
+        state.uploaded_through = *seq;
+    }
+    let log: Vec<TodoOp> = log.into_iter().map(|(_, op)| op).collect();
+    let known_ids: HashSet<&str> = log.iter().map(|op| op.id.as_str()).collect();
+
+    let remote = backend.download_ops(state.remote_cursor).await?;
+    if let Some((seq, _)) = remote.last() {
+        state.remote_cursor = *seq;
+    }
+    let incoming: Vec<TodoOp> = remote
+        .into_iter()
+        .map(|(_, op)| op)
+        .filter(|op| !known_ids.contains(op.id.as_str()))
+        .collect();
+
+    if !incoming.is_empty() {
+        {
+            let mut guard = lock_log().await?;
+            let clock = guard.as_mut().ok_or("操作日志时钟未初始化")?;
+            for op in &incoming {
+                clock.observe(&op.hlc);
+            }
+        }
+        // 受影响的待办替换为从日志推导出的状态，操作与新的列表在同一事务中写入
+        let log: Vec<TodoOp> = log.iter().chain(&incoming).cloned().collect();
+        save_remote_ops(&incoming, |todos| apply_ops_to_todos(todos, &incoming, &log)).await?;
+    }
+
+    save_sync_state(&state).await?;
+    Ok((uploaded, incoming.len()))
+}
+
+// 同步预览中的操作日志交换结果
+pub struct OpsPreview {
+    // 将上传的本设备操作数量
+    pub upload: usize,
+    // 将下载的其他设备操作数量
+    pub download: usize,
+    // 应用下载的操作后的本地待办
+    pub todos: Vec<Todo>,
+}
+
+// 预览与远程交换操作日志的结果，只读取远程与本地，不写入任何数据
+pub async fn preview_ops(backend: &dyn SyncBackend, database: &str, todos: Vec<Todo>) -> Result<OpsPreview, String> {
+    let state = load_sync_state(database).await?;
+
+    let log = store::read_ops(0).await?;
+    let device = device_id()?;
+    let upload = log
+        .iter()
+        .filter(|(seq, op)| *seq > state.uploaded_through && op.hlc.device == device)
+        .count();
+    let log: Vec<TodoOp> = log.into_iter().map(|(_, op)| op).collect();
+
+    let known_ids: HashSet<&str> = log.iter().map(|op| op.id.as_str()).collect();
+    let incoming: Vec<TodoOp> = backend
+        .download_ops(state.remote_cursor)
+        .await?
+        .into_iter()
+        .map(|(_, op)| op)
+        .filter(|op| !known_ids.contains(op.id.as_str()))
+        .collect();
+
+    let todos = if incoming.is_empty() {
+        todos
+    } else {
+        let log: Vec<TodoOp> = log.iter().chain(&incoming).cloned().collect();
+        apply_ops_to_todos(todos, &incoming, &log)?
+    };
+
+    Ok(OpsPreview { upload, download: incoming.len(), todos })
+}
+
+// 获取待办的修改历史（按时钟顺序）
+#[tauri::command]
+pub async fn get_todo_history(todo_id: String) -> Result<Vec<TodoOp>, String> {
+    let mut ops = store::read_todo_ops(&todo_id).await?;
+    ops.sort_by(|a, b| a.hlc.cmp(&b.hlc));
+    Ok(ops)
+}
@@ -0,0 +1,553 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::Mutex;
+
+use crate::modules::backend::{RestoreScope, SyncBackend};
+use crate::modules::data::collect_descendants;
+use crate::modules::store;
+use crate::modules::sync::{latest_time, merge_settings, pick_settings};
+use crate::modules::types::{format_utc, parse_utc, Todo};
+
+// 入队时加锁，避免两次入队都没有看到对方要取代的操作
+static OUTBOX_LOCK: Mutex<()> = Mutex::const_new(());
+
+// 回放失败达到该次数的操作移出回放顺序，等待用户重试或放弃
+pub const MAX_REPLAY_ATTEMPTS: u32 = 5;
+
+// 离线时暂存、等待写入远程的操作
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Operation {
+    // 写入本地新增或修改的待办
+    UpsertTodo { todo: Box<Todo> },
+    // 级联逻辑删除待办
+    DeleteTodo { id: String },
+    // 恢复已删除的待办
+    RestoreTodo { id: String, scope: RestoreScope },
+    // 上传本地设置
+    UploadSettings {
+        settings: Map<String, Value>,
+        #[serde(rename = "lastUpdate")]
+        last_update: String,
+        // 各字段的修改时间，旧版本入队的操作没有
+        #[serde(default, rename = "fieldTimes", skip_serializing_if = "HashMap::is_empty")]
+        field_times: HashMap<String, String>,
+    },
+}
+
+// 队列中的一项操作
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingOperation {
+    // 队列内递增的序号
+    pub seq: u64,
+    // 入队时间，回放删除与恢复时作为操作时间
+    pub queued_at: String,
+    #[serde(flatten)]
+    pub operation: Operation,
+    // 回放失败的次数与最近一次的错误
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    // 失败次数达到上限，回放与同步预览都跳过它
+    #[serde(default)]
+    pub failed: bool,
+}
+
+impl Operation {
+    // 同一目标的新操作取代队列中尚未回放的旧操作
+    fn supersedes(&self, older: &Operation) -> bool {
+        match (self, older) {
+            (Operation::UpsertTodo { todo }, Operation::UpsertTodo { todo: old }) => todo.id == old.id,
+            (Operation::UploadSettings { .. }, Operation::UploadSettings { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+// 读取尚未回放的操作（本地数据库的 outbox 表）
+pub async fn load_pending_operations() -> Result<Vec<PendingOperation>, String> {
+    store::read_outbox().await
+}
+
+// 将操作依次加入队列末尾，同一目标的旧操作在同一事务中移除
+pub async fn enqueue(operations: Vec<Operation>) -> Result<(), String> {
+    if operations.is_empty() {
+        return Ok(());
+    }
+
+    let _guard = OUTBOX_LOCK.lock().await;
+    let superseded: Vec<u64> = load_pending_operations()
+        .await?
+        .into_iter()
+        .filter(|pending| operations.iter().any(|operation| operation.supersedes(&pending.operation)))
+        .map(|pending| pending.seq)
+        .collect();
+    // 本批中被之后的操作取代的也不再入队
+    let kept: Vec<Operation> = operations
+        .iter()
+        .enumerate()
+        .filter(|(i, operation)| !operations[i + 1..].iter().any(|later| later.supersedes(operation)))
+        .map(|(_, operation)| operation.clone())
+        .collect();
+
+    store::enqueue_operations(&superseded, &kept, &format_utc(Utc::now())).await
+}
+
+// 远程待办的更新时间与墓碑
+struct RemoteTodoTimes {
+    times: HashMap<String, Option<DateTime<Utc>>>,
+    purged: HashSet<String>,
+}
+
+// 回放时按需下载一次远程待办的更新时间
+#[derive(Default)]
+struct RemoteTimes(Option<RemoteTodoTimes>);
+
+impl RemoteTimes {
+    async fn load(&mut self, backend: &dyn SyncBackend) -> Result<&RemoteTodoTimes, String> {
+        let loaded = match self.0.take() {
+            Some(loaded) => loaded,
+            None => {
+                let remote_todos = backend.download_todos(true).await?;
+                let times = remote_todos
+                    .into_iter()
+                    .map(|todo| (todo.id, todo.last_update.as_deref().and_then(parse_utc)))
+                    .collect();
+                let purged = backend.purged_todo_ids().await?.into_iter().collect();
+                RemoteTodoTimes { times, purged }
+            }
+        };
+
+        Ok(self.0.insert(loaded))
+    }
+
+    async fn get(&mut self, backend: &dyn SyncBackend, id: &str) -> Result<Option<DateTime<Utc>>, String> {
+        Ok(self.load(backend).await?.times.get(id).copied().flatten())
+    }
+
+    // 已彻底删除的待办不再写入
+    async fn purged(&mut self, backend: &dyn SyncBackend, id: &str) -> Result<bool, String> {
+        Ok(self.load(backend).await?.purged.contains(id))
+    }
+}
+
+// 执行一项操作，`time` 为操作发生的时间
+//
+// 操作可以重复执行：删除与恢复对已处于目标状态的待办不做修改，
+// 待办与设置只在远程版本不比操作中的版本新时写入，避免覆盖其他设备之后的修改。
+async fn apply_at(
+    backend: &dyn SyncBackend,
+    operation: &Operation,
+    time: DateTime<Utc>,
+    remote_times: &mut RemoteTimes,
+) -> Result<bool, String> {
+    match operation {
+        Operation::UpsertTodo { todo } => {
+            let local_time = todo.last_update.as_deref().and_then(parse_utc).unwrap_or(time);
+            if remote_times.purged(backend, &todo.id).await?
+                || remote_times.get(backend, &todo.id).await?.is_some_and(|remote| remote > local_time)
+            {
+                return Ok(false);
+            }
+            let count = backend.upsert_todos(std::slice::from_ref(todo), &format_utc(time)).await?;
+            Ok(count > 0)
+        }
+        Operation::DeleteTodo { id } => backend.delete_todo(id, time).await,
+        Operation::RestoreTodo { id, scope } => backend.restore_todo(id, *scope, time).await,
+        Operation::UploadSettings { settings, last_update, field_times } => {
+            // 只上传比远程新的字段
+            let remote_last_update = backend.settings_last_update().await?;
+            let merge = merge_settings(
+                settings,
+                field_times,
+                Some(last_update),
+                &backend.download_settings().await?,
+                &backend.download_settings_times().await?,
+                remote_last_update.as_deref(),
+            );
+            if merge.upload_fields.is_empty() {
+                return Ok(false);
+            }
+            let latest = latest_time(Some(last_update), remote_last_update.as_deref())
+                .unwrap_or_else(|| last_update.clone());
+            let (mut upload, upload_times) = pick_settings(settings, field_times, last_update, &merge.upload_fields);
+            upload.insert("lastUpdate".to_string(), Value::String(latest.clone()));
+            let count = backend.upload_settings(&upload, &upload_times, &latest).await?;
+            Ok(count > 0)
+        }
+    }
+}
+
+// 立即执行一项操作
+pub async fn apply_now(backend: &dyn SyncBackend, operation: &Operation) -> Result<bool, String> {
+    apply_at(backend, operation, Utc::now(), &mut RemoteTimes::default()).await
+}
+
+// 按入队顺序回放队列，返回成功回放的数量
+//
+// 某项回放失败时停止，记录错误并保留它和之后的操作，等待下次回放；
+// 失败次数达到 `MAX_REPLAY_ATTEMPTS` 的操作不再阻塞队列，跳过它继续回放之后的操作。
+pub async fn replay(backend: &dyn SyncBackend) -> Result<usize, String> {
+    let mut remote_times = RemoteTimes::default();
+    let mut replayed = 0;
+
+    loop {
+        let Some(pending) = load_pending_operations().await?.into_iter().find(|pending| !pending.failed) else {
+            return Ok(replayed);
+        };
+        let time = parse_utc(&pending.queued_at).unwrap_or_else(Utc::now);
+
+        match apply_at(backend, &pending.operation, time, &mut remote_times).await {
+            Ok(_) => {
+                store::remove_queued_operation(pending.seq).await?;
+                replayed += 1;
+            }
+            Err(e) => {
+                store::record_queued_failure(pending.seq, &e).await?;
+                if pending.attempts + 1 < MAX_REPLAY_ATTEMPTS {
+                    return Err(format!("回放离线操作 {} 失败: {}", pending.seq, e));
+                }
+            }
+        }
+    }
+}
+
+// 取出一项已失败的操作，`seq` 不存在或未失败时返回错误
+async fn failed_operation(seq: u64) -> Result<PendingOperation, String> {
+    load_pending_operations()
+        .await?
+        .into_iter()
+        .find(|pending| pending.seq == seq && pending.failed)
+        .ok_or_else(|| format!("离线操作 {} 不存在或未失败", seq))
+}
+
+// 远程数据的内存副本，同步预览时在其上模拟回放
+pub struct RemoteSnapshot {
+    // 包括已逻辑删除的待办
+    pub todos: Vec<Todo>,
+    pub settings: Map<String, Value>,
+    pub settings_last_update: Option<String>,
+    // 各设置字段的修改时间
+    pub settings_times: HashMap<String, String>,
+    // 已彻底删除的待办 id
+    pub purged: HashSet<String>,
+}
+
+// 从 `roots` 出发沿 parent_id 向下查找删除状态为 `deleted` 的子项
+fn collect_in_state(todos: &[Todo], roots: Vec<String>, deleted: bool) -> HashSet<String> {
+    let links: Vec<(&str, Option<&str>)> = todos
+        .iter()
+        .filter(|todo| todo.is_deleted == deleted)
+        .map(|todo| (todo.id.as_str(), todo.parent_id.as_deref()))
+        .collect();
+    collect_descendants(&links, roots)
+}
+
+impl RemoteSnapshot {
+    fn is_deleted(&self, id: &str) -> Option<bool> {
+        self.todos.iter().find(|todo| todo.id == id).map(|todo| todo.is_deleted)
+    }
+
+    // 与 apply_at 对远程数据的修改一致
+    fn apply(&mut self, operation: &Operation, time: DateTime<Utc>) {
+        match operation {
+            Operation::UpsertTodo { todo } => {
+                if self.purged.contains(&todo.id) {
+                    return;
+                }
+                let local_time = todo.last_update.as_deref().and_then(parse_utc).unwrap_or(time);
+                let position = self.todos.iter().position(|remote| remote.id == todo.id);
+                let remote_time = position.and_then(|i| self.todos[i].last_update.as_deref().and_then(parse_utc));
+                if remote_time.is_some_and(|remote| remote > local_time) {
+                    return;
+                }
+                let mut todo = Todo::clone(todo);
+                todo.last_update.get_or_insert_with(|| format_utc(time));
+                match position {
+                    Some(i) => self.todos[i] = todo,
+                    None => self.todos.push(todo),
+                }
+            }
+            Operation::DeleteTodo { id } => {
+                let roots = match self.is_deleted(id) {
+                    Some(false) => vec![id.clone()],
+                    _ => Vec::new(),
+                };
+                let ids = collect_in_state(&self.todos, roots, false);
+                self.set_deleted(&ids, true, time);
+            }
+            Operation::RestoreTodo { id, scope } => {
+                let mut roots = Vec::new();
+                if self.is_deleted(id) == Some(true) {
+                    roots.push(id.clone());
+                    if *scope == RestoreScope::WithAncestors {
+                        let mut current = id.clone();
+                        while let Some(parent) = self
+                            .todos
+                            .iter()
+                            .find(|todo| todo.id == current)
+                            .and_then(|todo| todo.parent_id.clone())
+                            .filter(|parent| self.is_deleted(parent) == Some(true) && !roots.contains(parent))
+                        {
+                            roots.push(parent.clone());
+                            current = parent;
+                        }
+                    }
+                }
+                let ids = collect_in_state(&self.todos, roots, true);
+                self.set_deleted(&ids, false, time);
+            }
+            Operation::UploadSettings { settings, last_update, field_times } => {
+                let merge = merge_settings(
+                    settings,
+                    field_times,
+                    Some(last_update),
+                    &self.settings,
+                    &self.settings_times,
+                    self.settings_last_update.as_deref(),
+                );
+                if merge.upload_fields.is_empty() {
+                    return;
+                }
+                let (upload, upload_times) = pick_settings(settings, field_times, last_update, &merge.upload_fields);
+                self.settings.extend(upload);
+                self.settings_times.extend(upload_times);
+                self.settings_last_update = latest_time(Some(last_update), self.settings_last_update.as_deref());
+            }
+        }
+    }
+
+    fn set_deleted(&mut self, ids: &HashSet<String>, deleted: bool, time: DateTime<Utc>) {
+        for todo in self.todos.iter_mut().filter(|todo| ids.contains(&todo.id)) {
+            todo.is_deleted = deleted;
+            todo.last_update = Some(format_utc(time));
+        }
+    }
+
+    // 模拟彻底删除在 `before` 之前逻辑删除的待办，返回被清除的 id
+    pub fn purge(&mut self, before: DateTime<Utc>) -> Vec<String> {
+        let roots: Vec<String> = self
+            .todos
+            .iter()
+            .filter(|todo| todo.is_deleted)
+            .filter(|todo| todo.last_update.as_deref().and_then(parse_utc).is_some_and(|time| time < before))
+            .map(|todo| todo.id.clone())
+            .collect();
+        let ids = collect_in_state(&self.todos, roots, true);
+
+        self.todos.retain(|todo| !ids.contains(&todo.id));
+        self.purged.extend(ids.iter().cloned());
+        let mut ids: Vec<String> = ids.into_iter().collect();
+        ids.sort();
+        ids
+    }
+
+    // 模拟按顺序回放队列中的全部操作，已失败的操作与 `replay` 一样跳过
+    pub fn replay(&mut self, operations: &[PendingOperation]) {
+        for pending in operations.iter().filter(|pending| !pending.failed) {
+            let time = parse_utc(&pending.queued_at).unwrap_or_else(Utc::now);
+            self.apply(&pending.operation, time);
+        }
+    }
+}
+
+// 获取尚未同步到远程的操作
+#[tauri::command]
+pub async fn get_pending_operations() -> Result<Vec<PendingOperation>, String> {
+    load_pending_operations().await
+}
+
+// 放弃一项已失败的操作，不再写入远程
+#[tauri::command]
+pub async fn discard_failed_operation(seq: u64) -> Result<(), String> {
+    let _guard = OUTBOX_LOCK.lock().await;
+    let pending = failed_operation(seq).await?;
+    store::remove_queued_operation(pending.seq).await
+}
+
+// 清零一项已失败操作的失败次数，下次回放时按原顺序重新执行
+#[tauri::command]
+pub async fn retry_failed_operation(seq: u64) -> Result<(), String> {
+    let _guard = OUTBOX_LOCK.lock().await;
+    let pending = failed_operation(seq).await?;
+    store::reset_queued_failures(pending.seq).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const OLD_TIME: &str = "2024-01-01T00:00:00.000Z";
+    const NEW_TIME: &str = "2024-01-03T00:00:00.000Z";
+
+    fn todo(id: &str, parent: Option<&str>, deleted: bool, last_update: &str) -> Todo {
+        serde_json::from_value(json!({
+            "id": id,
+            "text": id,
+            "createdAt": OLD_TIME,
+            "parentId": parent,
+            "isDeleted": deleted,
+            "lastUpdate": last_update,
+        }))
+        .unwrap()
+    }
+
+    fn snapshot(todos: Vec<Todo>) -> RemoteSnapshot {
+        RemoteSnapshot {
+            todos,
+            settings: Map::new(),
+            settings_last_update: None,
+            settings_times: HashMap::new(),
+            purged: HashSet::new(),
+        }
+    }
+
+    fn upsert(todo: Todo) -> Operation {
+        Operation::UpsertTodo { todo: Box::new(todo) }
+    }
+
+    fn pending(seq: u64, operation: Operation, failed: bool) -> PendingOperation {
+        PendingOperation {
+            seq,
+            queued_at: NEW_TIME.to_string(),
+            operation,
+            attempts: if failed { MAX_REPLAY_ATTEMPTS } else { 0 },
+            last_error: None,
+            failed,
+        }
+    }
+
+    fn time(value: &str) -> DateTime<Utc> {
+        parse_utc(value).unwrap()
+    }
+
+    fn deleted_ids(remote: &RemoteSnapshot) -> Vec<&str> {
+        let mut ids: Vec<&str> = remote.todos.iter().filter(|todo| todo.is_deleted).map(|todo| todo.id.as_str()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn newer_operation_supersedes_same_target() {
+        let a = upsert(todo("a", None, false, OLD_TIME));
+        let settings = Operation::UploadSettings {
+            settings: Map::new(),
+            last_update: OLD_TIME.to_string(),
+            field_times: HashMap::new(),
+        };
+
+        assert!(upsert(todo("a", None, false, NEW_TIME)).supersedes(&a));
+        assert!(!upsert(todo("b", None, false, NEW_TIME)).supersedes(&a));
+        assert!(settings.supersedes(&settings.clone()));
+        assert!(!Operation::DeleteTodo { id: "a".to_string() }.supersedes(&a));
+        assert!(!a.supersedes(&Operation::DeleteTodo { id: "a".to_string() }));
+    }
+
+    #[test]
+    fn upsert_keeps_newer_remote_and_purged_items() {
+        let mut remote = snapshot(vec![todo("a", None, false, NEW_TIME)]);
+        remote.purged.insert("b".to_string());
+
+        let mut stale = todo("a", None, false, OLD_TIME);
+        stale.text = "stale".to_string();
+        remote.apply(&upsert(stale), time(OLD_TIME));
+        remote.apply(&upsert(todo("b", None, false, NEW_TIME)), time(NEW_TIME));
+        remote.apply(&upsert(todo("c", None, false, NEW_TIME)), time(NEW_TIME));
+
+        assert_eq!(remote.todos.len(), 2);
+        assert_eq!(remote.todos[0].text, "a");
+        assert_eq!(remote.todos[1].id, "c");
+    }
+
+    #[test]
+    fn delete_and_restore_follow_the_tree() {
+        let mut remote = snapshot(vec![
+            todo("parent", None, false, OLD_TIME),
+            todo("child", Some("parent"), false, OLD_TIME),
+            todo("other", None, false, OLD_TIME),
+        ]);
+
+        remote.apply(&Operation::DeleteTodo { id: "parent".to_string() }, time(NEW_TIME));
+        assert_eq!(deleted_ids(&remote), ["child", "parent"]);
+
+        remote.apply(
+            &Operation::RestoreTodo { id: "child".to_string(), scope: RestoreScope::Subtree },
+            time(NEW_TIME),
+        );
+        assert_eq!(deleted_ids(&remote), ["parent"]);
+
+        // 已删除的父项不再级联删除已恢复的子项
+        remote.apply(&Operation::DeleteTodo { id: "parent".to_string() }, time(NEW_TIME));
+        assert_eq!(deleted_ids(&remote), ["parent"]);
+
+        remote.apply(&Operation::DeleteTodo { id: "child".to_string() }, time(NEW_TIME));
+        remote.apply(
+            &Operation::RestoreTodo { id: "child".to_string(), scope: RestoreScope::WithAncestors },
+            time(NEW_TIME),
+        );
+        assert!(deleted_ids(&remote).is_empty());
+    }
+
+    #[test]
+    fn settings_upload_only_writes_newer_fields() {
+        let mut remote = snapshot(Vec::new());
+        remote.settings = json!({ "theme": "dark", "language": "en" }).as_object().unwrap().clone();
+        remote.settings_times = HashMap::from([
+            ("theme".to_string(), NEW_TIME.to_string()),
+            ("language".to_string(), OLD_TIME.to_string()),
+        ]);
+        remote.settings_last_update = Some(NEW_TIME.to_string());
+
+        let local_time = "2024-01-02T00:00:00.000Z";
+        remote.apply(
+            &Operation::UploadSettings {
+                settings: json!({ "theme": "light", "language": "zh" }).as_object().unwrap().clone(),
+                last_update: local_time.to_string(),
+                field_times: HashMap::from([
+                    ("theme".to_string(), local_time.to_string()),
+                    ("language".to_string(), local_time.to_string()),
+                ]),
+            },
+            time(local_time),
+        );
+
+        assert_eq!(remote.settings["theme"], "dark");
+        assert_eq!(remote.settings["language"], "zh");
+        assert_eq!(remote.settings_last_update.as_deref(), Some(NEW_TIME));
+    }
+
+    #[test]
+    fn purge_removes_old_deleted_subtrees() {
+        let mut remote = snapshot(vec![
+            todo("old", None, true, OLD_TIME),
+            todo("old-child", Some("old"), true, NEW_TIME),
+            todo("recent", None, true, NEW_TIME),
+            todo("kept", None, false, OLD_TIME),
+        ]);
+
+        let purged = remote.purge(time("2024-01-02T00:00:00.000Z"));
+
+        assert_eq!(purged, ["old", "old-child"]);
+        assert!(remote.purged.contains("old-child"));
+        let ids: Vec<&str> = remote.todos.iter().map(|todo| todo.id.as_str()).collect();
+        assert_eq!(ids, ["recent", "kept"]);
+    }
+
+    #[test]
+    fn replay_skips_failed_operations() {
+        let mut remote = snapshot(vec![todo("a", None, false, OLD_TIME)]);
+
+        remote.replay(&[
+            pending(1, Operation::DeleteTodo { id: "a".to_string() }, true),
+            pending(2, upsert(todo("b", None, false, NEW_TIME)), false),
+        ]);
+
+        assert!(deleted_ids(&remote).is_empty());
+        assert_eq!(remote.todos.len(), 2);
+    }
+}
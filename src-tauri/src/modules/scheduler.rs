@@ -0,0 +1,205 @@
+use chrono::Utc;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::watch;
+
+use crate::modules::backend;
+use crate::modules::database::{
+    database_config_needs_passphrase, load_database_config, run_database_sync, DatabaseState, SyncResult,
+};
+use crate::modules::types::format_utc;
+
+// 同步失败后首次重试的等待时间，之后每次翻倍，最长不超过同步周期
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(30);
+
+// 自动同步事件（sync-started / sync-finished / sync-failed / sync-skipped）的内容
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoSyncEvent {
+    pub result: Option<SyncResult>,
+    pub error: Option<String>,
+    // 连续失败的次数
+    pub failures: u32,
+    // 下次自动同步的时间
+    pub next_sync_at: Option<String>,
+}
+
+// 后台自动同步调度器
+//
+// 保存当前周期和停止信号，丢弃发送端即可让后台任务在下一次等待时退出，
+// 正在进行的同步不会被打断。
+#[derive(Default)]
+pub struct SyncScheduler {
+    running: Mutex<Option<(Duration, watch::Sender<()>)>>,
+}
+
+// 解析 autoSync 周期（"15m"、"1h"），"0" 或无法解析时表示不自动同步
+pub fn parse_auto_sync(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(value.len().checked_sub(1)?);
+    let number: u64 = number.parse().ok().filter(|n| *n > 0)?;
+
+    match unit {
+        "m" => Some(Duration::from_secs(number * 60)),
+        "h" => Some(Duration::from_secs(number * 60 * 60)),
+        _ => None,
+    }
+}
+
+impl SyncScheduler {
+    // 按新的周期启动或重启自动同步，周期未变化时保持原任务
+    pub fn apply(&self, app: &AppHandle, interval: Option<Duration>) {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        if running.as_ref().map(|(current, _)| *current) == interval {
+            return;
+        }
+
+        // 丢弃旧的发送端，旧任务随之停止
+        *running = interval.map(|interval| {
+            let (stop_tx, stop_rx) = watch::channel(());
+            tauri::async_runtime::spawn(run_auto_sync(app.clone(), interval, stop_rx));
+            (interval, stop_tx)
+        });
+    }
+}
+
+// 第 `failures` 次失败后的重试等待时间
+fn retry_delay(interval: Duration, failures: u32) -> Duration {
+    RETRY_BASE_DELAY
+        .saturating_mul(1u32 << failures.saturating_sub(1).min(16))
+        .min(interval)
+}
+
+fn next_sync_at(delay: Duration) -> Option<String> {
+    chrono::Duration::from_std(delay).ok().map(|delay| format_utc(Utc::now() + delay))
+}
+
+// 自动同步前的连接状态
+enum Connection {
+    Connected,
+    // 没有保存的配置
+    NotConfigured,
+    // 配置用口令加密，需要用户在界面中输入口令连接
+    NeedsPassphrase,
+}
+
+// 尚未连接时使用保存的配置连接（窗口隐藏在托盘时前端不会建立连接）
+//
+// 之前连接过时沿用内存中的配置；没有配置或配置需要口令时本次跳过同步。
+async fn ensure_connected(state: &DatabaseState) -> Result<Connection, String> {
+    if state.backend.lock().await.is_some() {
+        return Ok(Connection::Connected);
+    }
+
+    let current = state.config.lock().await.clone();
+    let config = match current {
+        Some(config) => config,
+        None if database_config_needs_passphrase()? => return Ok(Connection::NeedsPassphrase),
+        None => match load_database_config(None).await? {
+            Some(config) => config,
+            None => return Ok(Connection::NotConfigured),
+        },
+    };
+    let backend = backend::connect(&config).await?;
+
+    *state.backend.lock().await = Some(backend);
+    *state.config.lock().await = Some(config);
+    Ok(Connection::Connected)
+}
+
+// 自动同步循环：按周期同步，失败后按指数退避重试
+async fn run_auto_sync(app: AppHandle, interval: Duration, mut stop: watch::Receiver<()>) {
+    let mut failures: u32 = 0;
+    // 需要口令时只提示一次，不作为失败反复重试
+    let mut passphrase_reported = false;
+
+    loop {
+        let delay = if failures == 0 { interval } else { retry_delay(interval, failures) };
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = stop.changed() => return,
+        }
+
+        let state = app.state::<DatabaseState>();
+        let outcome = match ensure_connected(&state).await {
+            Ok(Connection::NotConfigured) => continue,
+            Ok(Connection::NeedsPassphrase) => {
+                if !passphrase_reported {
+                    passphrase_reported = true;
+                    let _ = app.emit("sync-skipped", AutoSyncEvent {
+                        result: None,
+                        error: Some("数据库配置已设置口令，输入口令连接数据库后自动同步".to_string()),
+                        failures,
+                        next_sync_at: next_sync_at(interval),
+                    });
+                }
+                continue;
+            }
+            Ok(Connection::Connected) => {
+                passphrase_reported = false;
+                let _ = app.emit("sync-started", AutoSyncEvent {
+                    result: None,
+                    error: None,
+                    failures,
+                    next_sync_at: None,
+                });
+                run_database_sync(&app, &state).await
+            }
+            Err(e) => Err(format!("自动同步连接数据库失败: {}", e)),
+        };
+
+        match outcome {
+            Ok(result) => {
+                failures = 0;
+                let _ = app.emit("sync-finished", AutoSyncEvent {
+                    result: Some(result),
+                    error: None,
+                    failures,
+                    next_sync_at: next_sync_at(interval),
+                });
+            }
+            Err(e) => {
+                failures = failures.saturating_add(1);
+                let _ = app.emit("sync-failed", AutoSyncEvent {
+                    result: None,
+                    error: Some(e),
+                    failures,
+                    next_sync_at: next_sync_at(retry_delay(interval, failures)),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn parses_minutes_and_hours() {
+        assert_eq!(parse_auto_sync("15m"), Some(15 * MINUTE));
+        assert_eq!(parse_auto_sync(" 2h "), Some(120 * MINUTE));
+    }
+
+    #[test]
+    fn disabled_or_invalid_intervals_do_not_sync() {
+        for value in ["0", "0m", "", "m", "15", "15s", "-5m", "1.5h"] {
+            assert_eq!(parse_auto_sync(value), None, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_interval() {
+        let interval = 60 * MINUTE;
+        assert_eq!(retry_delay(interval, 1), RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(interval, 2), 2 * RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(interval, 3), 4 * RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(interval, 10), interval);
+        assert_eq!(retry_delay(interval, u32::MAX), interval);
+        assert_eq!(retry_delay(MINUTE / 2, 1), MINUTE / 2);
+    }
+}
@@ -0,0 +1,254 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::pin::Pin;
+use tauri::State;
+
+use crate::modules::database::{current_backend, DatabaseState};
+
+pub type StepFuture<'c> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'c>>;
+
+// 迁移中的一步，`C` 为对应后端的数据库连接
+pub enum MigrationStep<C: 'static> {
+    // 直接执行的 SQL 语句
+    Sql(&'static str),
+    // 需要在 Rust 中转换数据的步骤，名称参与校验和
+    Rust(&'static str, for<'c> fn(&'c mut C) -> StepFuture<'c>),
+}
+
+// 一次远程结构迁移（各后端的迁移独立编号）
+pub struct SchemaMigration<C: 'static> {
+    pub version: u32,
+    pub name: &'static str,
+    pub steps: &'static [MigrationStep<C>],
+}
+
+// 迁移执行器需要的数据库操作，由各后端的连接实现
+#[async_trait]
+pub trait MigrationConnection: Send {
+    // 执行一条 SQL 语句
+    async fn execute_sql(&mut self, sql: &str) -> Result<(), String>;
+    // 读取已执行的迁移（版本, 校验和），迁移记录表不存在时返回空列表
+    async fn applied_migrations(&mut self) -> Result<Vec<(u32, String)>, String>;
+    // 记录一次已执行的迁移
+    async fn record_migration(&mut self, version: u32, name: &str, checksum: &str) -> Result<(), String>;
+}
+
+// 远程结构版本状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteSchemaStatus {
+    // 远程已执行的最高迁移版本（0 表示尚未初始化）
+    pub current_version: u32,
+    // 当前程序已知的最高迁移版本
+    pub latest_version: u32,
+    // 尚未执行的迁移版本
+    pub pending_versions: Vec<u32>,
+    // 当前程序能否与该远程结构同步
+    pub compatible: bool,
+    // 不能同步时的原因
+    pub error: Option<String>,
+}
+
+// 当前程序已知的最高迁移版本
+pub fn latest_version<C>(migrations: &[SchemaMigration<C>]) -> u32 {
+    migrations.last().map_or(0, |m| m.version)
+}
+
+// 计算迁移步骤的校验和
+fn checksum<C>(migration: &SchemaMigration<C>) -> String {
+    let mut hasher = Sha256::new();
+    for step in migration.steps {
+        match step {
+            MigrationStep::Sql(statement) => hasher.update(statement.trim().as_bytes()),
+            MigrationStep::Rust(name, _) => hasher.update(format!("rust:{}", name).as_bytes()),
+        }
+        hasher.update(b";");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// 校验已执行迁移的校验和，并拒绝比当前程序更新的远程结构
+fn verify_applied<C>(applied: &[(u32, String)], migrations: &[SchemaMigration<C>]) -> Result<(), String> {
+    for (version, applied_checksum) in applied {
+        match migrations.iter().find(|m| m.version == *version) {
+            Some(migration) => {
+                if checksum(migration) != *applied_checksum {
+                    return Err(format!(
+                        "远程迁移 {} ({}) 的校验和与当前程序不一致，远程结构可能被手动修改",
+                        version, migration.name
+                    ));
+                }
+            }
+            None => {
+                return Err(format!(
+                    "远程数据库结构版本 {} 高于当前程序支持的版本 {}，请先升级 Ton",
+                    version,
+                    latest_version(migrations)
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// 根据已执行的迁移计算结构版本状态
+pub fn schema_status<C>(applied: &[(u32, String)], migrations: &[SchemaMigration<C>]) -> RemoteSchemaStatus {
+    let current_version = applied.iter().map(|(v, _)| *v).max().unwrap_or(0);
+    let error = verify_applied(applied, migrations).err();
+    let pending_versions = migrations
+        .iter()
+        .map(|m| m.version)
+        .filter(|v| !applied.iter().any(|(applied_version, _)| applied_version == v))
+        .collect();
+
+    RemoteSchemaStatus {
+        current_version,
+        latest_version: latest_version(migrations),
+        pending_versions,
+        compatible: error.is_none(),
+        error,
+    }
+}
+
+// 按顺序执行所有未执行的迁移，返回本次执行的迁移说明
+//
+// 远程结构比当前程序新或已执行迁移的校验和不一致时返回错误，不做任何修改。
+// 加锁与事务由调用方（各后端）负责。
+pub async fn apply_pending<C: MigrationConnection>(
+    conn: &mut C,
+    migrations: &'static [SchemaMigration<C>],
+) -> Result<Vec<String>, String> {
+    let applied = conn.applied_migrations().await?;
+    verify_applied(&applied, migrations)?;
+
+    let mut messages = Vec::new();
+    for migration in migrations {
+        if applied.iter().any(|(version, _)| *version == migration.version) {
+            continue;
+        }
+
+        for step in migration.steps {
+            match step {
+                MigrationStep::Sql(statement) => conn.execute_sql(statement).await,
+                MigrationStep::Rust(_, run) => run(conn).await,
+            }
+            .map_err(|e| format!("执行迁移 {} ({}) 失败: {}", migration.version, migration.name, e))?;
+        }
+
+        conn.record_migration(migration.version, migration.name, &checksum(migration)).await
+            .map_err(|e| format!("记录迁移 {} 失败: {}", migration.version, e))?;
+
+        messages.push(format!("执行了迁移 {} ({})", migration.version, migration.name));
+    }
+
+    Ok(messages)
+}
+
+// 获取远程数据库结构版本
+#[tauri::command]
+pub async fn get_remote_schema_version(
+    state: State<'_, DatabaseState>
+) -> Result<RemoteSchemaStatus, String> {
+    current_backend(&state).await?.schema_status().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 记录执行过的语句与迁移的内存连接
+    #[derive(Default)]
+    struct FakeConnection {
+        executed: Vec<String>,
+        applied: Vec<(u32, String)>,
+    }
+
+    #[async_trait]
+    impl MigrationConnection for FakeConnection {
+        async fn execute_sql(&mut self, sql: &str) -> Result<(), String> {
+            self.executed.push(sql.to_string());
+            Ok(())
+        }
+
+        async fn applied_migrations(&mut self) -> Result<Vec<(u32, String)>, String> {
+            Ok(self.applied.clone())
+        }
+
+        async fn record_migration(&mut self, version: u32, _name: &str, checksum: &str) -> Result<(), String> {
+            self.applied.push((version, checksum.to_string()));
+            Ok(())
+        }
+    }
+
+    static MIGRATIONS: &[SchemaMigration<FakeConnection>] = &[
+        SchemaMigration { version: 1, name: "create_todos", steps: &[MigrationStep::Sql("CREATE TABLE todos (id TEXT)")] },
+        SchemaMigration { version: 2, name: "add_parent", steps: &[MigrationStep::Sql("ALTER TABLE todos ADD parent_id TEXT")] },
+    ];
+
+    fn applied(versions: &[u32]) -> Vec<(u32, String)> {
+        versions
+            .iter()
+            .map(|version| (*version, checksum(&MIGRATIONS[*version as usize - 1])))
+            .collect()
+    }
+
+    #[test]
+    fn status_lists_pending_versions() {
+        let status = schema_status(&applied(&[1]), MIGRATIONS);
+        assert_eq!((status.current_version, status.latest_version), (1, 2));
+        assert_eq!(status.pending_versions, [2]);
+        assert!(status.compatible && status.error.is_none());
+
+        let status = schema_status(&[], MIGRATIONS);
+        assert_eq!(status.current_version, 0);
+        assert_eq!(status.pending_versions, [1, 2]);
+    }
+
+    #[test]
+    fn status_rejects_modified_migrations() {
+        let status = schema_status(&[(1, "modified".to_string())], MIGRATIONS);
+        assert!(!status.compatible);
+        assert!(status.error.unwrap().contains("校验和"));
+    }
+
+    #[test]
+    fn status_rejects_newer_remote() {
+        let mut newer = applied(&[1, 2]);
+        newer.push((3, "future".to_string()));
+        let status = schema_status(&newer, MIGRATIONS);
+        assert_eq!(status.current_version, 3);
+        assert!(!status.compatible);
+        assert!(status.error.unwrap().contains("请先升级"));
+    }
+
+    #[test]
+    fn checksum_ignores_surrounding_whitespace_only() {
+        let padded: SchemaMigration<FakeConnection> =
+            SchemaMigration { version: 1, name: "create_todos", steps: &[MigrationStep::Sql("  CREATE TABLE todos (id TEXT)\n")] };
+        let changed: SchemaMigration<FakeConnection> =
+            SchemaMigration { version: 1, name: "create_todos", steps: &[MigrationStep::Sql("CREATE TABLE todos (id INT)")] };
+        assert_eq!(checksum(&padded), checksum(&MIGRATIONS[0]));
+        assert_ne!(checksum(&changed), checksum(&MIGRATIONS[0]));
+    }
+
+    #[tokio::test]
+    async fn apply_pending_runs_only_missing_migrations() {
+        let mut conn = FakeConnection { applied: applied(&[1]), ..Default::default() };
+        let messages = apply_pending(&mut conn, MIGRATIONS).await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(conn.executed, ["ALTER TABLE todos ADD parent_id TEXT"]);
+        assert_eq!(conn.applied, applied(&[1, 2]));
+
+        assert!(apply_pending(&mut conn, MIGRATIONS).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn apply_pending_changes_nothing_when_incompatible() {
+        let mut conn = FakeConnection { applied: vec![(1, "modified".to_string())], ..Default::default() };
+        assert!(apply_pending(&mut conn, MIGRATIONS).await.is_err());
+        assert!(conn.executed.is_empty());
+        assert_eq!(conn.applied.len(), 1);
+    }
+}
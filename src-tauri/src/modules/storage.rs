@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+use crate::modules::app::{apply_app_settings, import_profiles, lock_settings, AppSettings};
+use crate::modules::bundle::ImportMode;
+use crate::modules::data::{import_todo_data, ImportedData};
+use crate::modules::migration::{migrate, FileKind};
+use crate::modules::store;
+use crate::modules::types::{detach_orphans, format_utc, ArchivedTodos, TodoFile, TrashFile};
+
+/// 每个数据文件保留的备份数量
+const MAX_BACKUPS: usize = 10;
+
+/// 两次自动备份之间的最小间隔（秒），避免每次保存都产生备份
+const BACKUP_INTERVAL_SECS: i64 = 10 * 60;
+
+/// 可以备份和恢复的数据（待办、归档、回收站与设置保存在本地数据库中，备份使用原 JSON 文件的格式）
+const BACKUP_FILES: [&str; 5] = ["todos.json", "stage.json", "trash.json", "settings.json", "profiles.json"];
+
+/// 备份文件名中的时间格式
+const BACKUP_TIME_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// 备份信息
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    pub name: String,
+    pub file: String,
+    pub created_at: String,
+    pub size: u64,
+}
+
+/// 获取数据目录
+fn get_data_dir() -> Result<PathBuf, String> {
+    Ok(dirs::data_dir()
+        .ok_or("Failed to get data directory")?
+        .join("Ton")
+        .join("data"))
+}
+
+/// 获取备份目录
+fn get_backup_dir() -> Result<PathBuf, String> {
+    Ok(get_data_dir()?.join("backups"))
+}
+
+/// 原子写入：先写入同目录的临时文件并 fsync，再重命名覆盖目标文件
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let dir = path.parent().ok_or("Invalid file path")?;
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("Invalid file path")?;
+    let tmp_path = dir.join(format!(".{}.tmp", file_name));
+
+    let result = (|| {
+        let mut file = std::fs::File::create(&tmp_path)
+            .map_err(|e| format!("Failed to create temp file: {}", e))?;
+        file.write_all(contents)
+            .map_err(|e| format!("Failed to write temp file: {}", e))?;
+        file.sync_all()
+            .map_err(|e| format!("Failed to sync temp file: {}", e))?;
+        std::fs::rename(&tmp_path, path)
+            .map_err(|e| format!("Failed to replace file: {}", e))
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+        return result;
+    }
+
+    // 同步目录项，确保重命名本身落盘（Windows 上不支持打开目录）
+    #[cfg(unix)]
+    {
+        if let Ok(dir_file) = std::fs::File::open(dir) {
+            let _ = dir_file.sync_all();
+        }
+    }
+
+    Ok(())
+}
+
+/// 解析备份文件名，返回 (原文件名, 创建时间)
+fn parse_backup_name(name: &str) -> Option<(String, chrono::DateTime<chrono::Utc>)> {
+    let stem = name.strip_suffix(".json")?;
+    let (file_stem, time) = stem.rsplit_once('-')?;
+    let created_at = chrono::NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT).ok()?;
+    let file = format!("{}.json", file_stem);
+    if !BACKUP_FILES.contains(&file.as_str()) {
+        return None;
+    }
+    Some((file, created_at.and_utc()))
+}
+
+/// 列出某个数据文件的所有备份（按时间从新到旧）
+fn backups_of(file: Option<&str>) -> Result<Vec<BackupInfo>, String> {
+    let backup_dir = get_backup_dir()?;
+
+    if !backup_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let entries = std::fs::read_dir(&backup_dir)
+        .map_err(|e| format!("Failed to read backup directory: {}", e))?;
+
+    let mut backups = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some((backup_file, created_at)) = parse_backup_name(&name) else {
+            continue;
+        };
+        if file.is_some_and(|file| file != backup_file) {
+            continue;
+        }
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        backups.push(BackupInfo {
+            name,
+            file: backup_file,
+            created_at: created_at.to_rfc3339(),
+            size,
+        });
+    }
+
+    backups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(backups)
+}
+
+/// 距最近一次备份是否已超过 `BACKUP_INTERVAL_SECS`（没有备份时为 true）
+pub fn backup_due(file_name: &str) -> Result<bool, String> {
+    let now = chrono::Utc::now();
+    let recent = backups_of(Some(file_name))?
+        .first()
+        .and_then(|backup| chrono::DateTime::parse_from_rfc3339(&backup.created_at).ok())
+        .is_some_and(|created_at| (now - created_at.to_utc()).num_seconds() < BACKUP_INTERVAL_SECS);
+    Ok(!recent)
+}
+
+/// 把 `contents` 写成数据文件 `file_name` 的一份新备份并清理多余的旧备份
+pub fn write_backup(file_name: &str, contents: &[u8]) -> Result<(), String> {
+    let file_stem = file_name.strip_suffix(".json").ok_or("Only JSON files can be backed up")?;
+
+    let backup_dir = get_backup_dir()?;
+    std::fs::create_dir_all(&backup_dir)
+        .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+
+    let backup_name = format!("{}-{}.json", file_stem, chrono::Utc::now().format(BACKUP_TIME_FORMAT));
+    write_atomic(&backup_dir.join(&backup_name), contents)?;
+
+    // 只保留最新的 MAX_BACKUPS 份
+    for old in backups_of(Some(file_name))?.iter().skip(MAX_BACKUPS) {
+        let _ = std::fs::remove_file(backup_dir.join(&old.name));
+    }
+
+    Ok(())
+}
+
+/// 备份数据文件的当前内容并清理多余的旧备份
+///
+/// `force` 为 false 时，若最近一次备份距今不足 `BACKUP_INTERVAL_SECS` 则跳过。
+pub fn backup_file(path: &Path, force: bool) -> Result<(), String> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("Invalid file path")?;
+
+    if !force && !backup_due(file_name)? {
+        return Ok(());
+    }
+
+    let contents = std::fs::read(path).map_err(|e| format!("Failed to read file for backup: {}", e))?;
+    write_backup(file_name, &contents)
+}
+
+/// 列出所有数据文件的备份
+#[tauri::command]
+pub fn list_backups() -> Result<Vec<BackupInfo>, String> {
+    backups_of(None)
+}
+
+/// 从备份恢复待办、归档、回收站、设置或配置方案，恢复前会先备份当前内容
+#[tauri::command]
+pub async fn restore_backup(app: AppHandle, name: String) -> Result<(), String> {
+    let (file, _) = parse_backup_name(&name).ok_or_else(|| format!("Invalid backup name: {}", name))?;
+    let backup_path = get_backup_dir()?.join(&name);
+
+    if !backup_path.exists() {
+        return Err(format!("Backup not found: {}", name));
+    }
+
+    let contents = std::fs::read(&backup_path)
+        .map_err(|e| format!("Failed to read backup: {}", e))?;
+
+    // 确认备份内容可以解析（旧格式的备份按迁移后的结果检查），避免用损坏的备份覆盖数据
+    let value: Value = serde_json::from_slice(&contents)
+        .map_err(|e| format!("Backup {} is not valid JSON: {}", name, e))?;
+    match file.as_str() {
+        // 设置与配置方案和导入一样写入，发出变化事件
+        "settings.json" => {
+            let (value, _) = migrate(FileKind::Settings, value)?;
+            let settings: Map<String, Value> = serde_json::from_value(value)
+                .map_err(|e| format!("Backup {} is not a valid settings file: {}", name, e))?;
+            let _guard = lock_settings().await;
+            store::backup_data("settings.json", true).await?;
+            apply_app_settings(&app, AppSettings::parse_lenient(settings)).await?;
+            return Ok(());
+        }
+        "profiles.json" => {
+            let (value, _) = migrate(FileKind::Profiles, value)?;
+            import_profiles(&app, value, ImportMode::Replace).await?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let mut restored = ImportedData { todos: None, archive: None, trash: None };
+    match file.as_str() {
+        "todos.json" => {
+            let (value, _) = migrate(FileKind::Todos, value)?;
+            let mut todos: TodoFile = serde_json::from_value(value)
+                .map_err(|e| format!("Backup {} is not a valid todo file: {}", name, e))?;
+            todos.last_update = Some(format_utc(chrono::Utc::now()));
+            todos.source = Some("restore".to_string());
+            restored.todos = Some(todos);
+        }
+        "trash.json" => {
+            let (value, _) = migrate(FileKind::Trash, value)?;
+            restored.trash = Some(serde_json::from_value::<TrashFile>(value)
+                .map_err(|e| format!("Backup {} is not a valid trash file: {}", name, e))?);
+        }
+        _ => {
+            let (value, _) = migrate(FileKind::Archive, value)?;
+            restored.archive = Some(serde_json::from_value::<ArchivedTodos>(value)
+                .map_err(|e| format!("Backup {} is not a valid archive file: {}", name, e))?);
+        }
+    }
+
+    // 与导入一样在待办锁内校验并写入，列表的修改记入操作日志与撤销历史；
+    // 父项已不在列表与当前归档中的待办移到顶层
+    import_todo_data("restore", |_, archive, _| {
+        if let Some(todos) = &mut restored.todos {
+            let archive_ids = restored.archive.as_ref().unwrap_or(&archive).todos().map(|todo| todo.id.clone()).collect();
+            detach_orphans(&mut todos.data, &archive_ids);
+        }
+        Ok(restored)
+    })
+    .await
+}
@@ -0,0 +1,1208 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+use crate::modules::backend::SQLITE_MIGRATIONS_TABLE_SQL;
+use crate::modules::history::{HistoryStack, HISTORY_LIMIT};
+use crate::modules::migration::{migrate, to_versioned_value, FileKind, FORMAT_VERSION_KEY};
+use crate::modules::oplog::TodoOp;
+use crate::modules::outbox::{Operation, PendingOperation, MAX_REPLAY_ATTEMPTS};
+use crate::modules::schema::{apply_pending, MigrationStep, SchemaMigration, StepFuture};
+use crate::modules::storage::{backup_due, write_backup};
+use crate::modules::types::{ArchiveBatch, ArchivedTodos, HistoryEntry, Todo, TodoFile, TrashFile};
+
+/// 本地数据库文件名（位于数据目录）
+const STORE_FILE: &str = "ton.db";
+
+/// 等待其他连接释放写锁的最长时间
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 待办列表的更新时间与来源在 meta 表中的键
+const TODOS_LAST_UPDATE_KEY: &str = "todos.lastUpdate";
+const TODOS_SOURCE_KEY: &str = "todos.source";
+
+/// 本地数据库的结构迁移，已发布的迁移不能再修改
+///
+/// 待办、归档与回收站按行保存完整的 JSON（保留前端附加的字段），`position` 为列表中的顺序；
+/// 设置按顶层字段逐行保存，`modified_at` 为该字段最后一次改变的时间；sync_meta 保存同步基线、冲突记录与操作日志同步进度；
+/// history 按 `stack` 区分撤销栈与重做栈，id 越大越靠近栈顶；oplog 按 `seq` 顺序保存操作日志，
+/// outbox 按 `seq` 顺序保存等待写入远程的离线操作。
+const LOCAL_MIGRATIONS: &[SchemaMigration<SqliteConnection>] = &[
+    SchemaMigration {
+        version: 1,
+        name: "create_local_store",
+        steps: &[
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS todos (
+                    id TEXT PRIMARY KEY,
+                    parent_id TEXT NULL,
+                    position INTEGER NOT NULL,
+                    data TEXT NOT NULL
+                )
+            "#),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_todos_parent_id ON todos (parent_id)"),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_todos_position ON todos (position)"),
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS archive_batches (
+                    id TEXT PRIMARY KEY,
+                    position INTEGER NOT NULL,
+                    archived_at TEXT NOT NULL
+                )
+            "#),
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS archived_todos (
+                    id TEXT PRIMARY KEY,
+                    batch_id TEXT NOT NULL,
+                    position INTEGER NOT NULL,
+                    data TEXT NOT NULL
+                )
+            "#),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_archived_todos_batch_id ON archived_todos (batch_id)"),
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS trash (
+                    id TEXT PRIMARY KEY,
+                    data TEXT NOT NULL
+                )
+            "#),
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS settings (
+                    key TEXT PRIMARY KEY,
+                    value TEXT NOT NULL
+                )
+            "#),
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS sync_meta (
+                    key TEXT PRIMARY KEY,
+                    value TEXT NOT NULL
+                )
+            "#),
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS meta (
+                    key TEXT PRIMARY KEY,
+                    value TEXT NOT NULL
+                )
+            "#),
+        ],
+    },
+    SchemaMigration {
+        version: 2,
+        name: "import_json_files",
+        steps: &[MigrationStep::Rust("import_json_files", import_json_files)],
+    },
+    SchemaMigration {
+        version: 3,
+        name: "create_history",
+        steps: &[
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    stack TEXT NOT NULL,
+                    data TEXT NOT NULL
+                )
+            "#),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_history_stack ON history (stack, id)"),
+        ],
+    },
+    SchemaMigration {
+        version: 4,
+        name: "add_settings_modified_at",
+        steps: &[MigrationStep::Sql("ALTER TABLE settings ADD COLUMN modified_at TEXT NULL")],
+    },
+    SchemaMigration {
+        version: 5,
+        name: "create_oplog_and_outbox",
+        steps: &[
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS oplog (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    op_id TEXT NOT NULL UNIQUE,
+                    todo_id TEXT NOT NULL,
+                    data TEXT NOT NULL
+                )
+            "#),
+            MigrationStep::Sql("CREATE INDEX IF NOT EXISTS idx_oplog_todo_id ON oplog (todo_id)"),
+            MigrationStep::Sql(r#"
+                CREATE TABLE IF NOT EXISTS outbox (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    queued_at TEXT NOT NULL,
+                    operation TEXT NOT NULL,
+                    attempts INTEGER NOT NULL DEFAULT 0,
+                    last_error TEXT NULL
+                )
+            "#),
+            MigrationStep::Rust("import_oplog_and_outbox", import_oplog_and_outbox),
+        ],
+    },
+];
+
+/// 导入时 sync_meta 的来源文件（位于数据目录）
+const SYNC_META_FILES: [(&str, &str); 3] = [
+    ("sync_base.json", "syncBase"),
+    ("sync_conflicts.json", "syncConflicts"),
+    ("oplog_state.json", "oplogState"),
+];
+
+static POOL: OnceCell<SqlitePool> = OnceCell::const_new();
+
+fn get_data_dir() -> Result<PathBuf, String> {
+    Ok(dirs::data_dir()
+        .ok_or("Failed to get data directory")?
+        .join("Ton")
+        .join("data"))
+}
+
+fn get_config_dir() -> Result<PathBuf, String> {
+    Ok(dirs::config_dir()
+        .ok_or("Failed to get config directory")?
+        .join("Ton")
+        .join("config"))
+}
+
+/// 本地数据库连接池，首次使用时打开数据库并执行迁移
+async fn pool() -> Result<&'static SqlitePool, String> {
+    POOL.get_or_try_init(open).await
+}
+
+async fn open() -> Result<SqlitePool, String> {
+    let data_dir = get_data_dir()?;
+    std::fs::create_dir_all(&data_dir)
+        .map_err(|e| format!("Failed to create data directory: {}", e))?;
+
+    let options = SqliteConnectOptions::new()
+        .filename(data_dir.join(STORE_FILE))
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .busy_timeout(BUSY_TIMEOUT);
+    let pool = SqlitePool::connect_with(options).await
+        .map_err(|e| format!("Failed to open local database: {}", e))?;
+
+    let mut conn = pool.acquire().await
+        .map_err(|e| format!("Failed to acquire local database connection: {}", e))?;
+
+    // 迁移（包括首次导入 JSON 文件）在同一事务中完成，中途失败时下次启动重新执行
+    sqlx::query("BEGIN IMMEDIATE")
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to lock local database: {}", e))?;
+
+    let result = async {
+        sqlx::query(SQLITE_MIGRATIONS_TABLE_SQL)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to create migration table: {}", e))?;
+
+        apply_pending(&mut *conn, LOCAL_MIGRATIONS).await
+    }
+    .await;
+
+    let end = if result.is_ok() { "COMMIT" } else { "ROLLBACK" };
+    sqlx::query(end)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to finish local database migration: {}", e))?;
+    result.map_err(|e| format!("Failed to migrate local database: {}", e))?;
+
+    drop(conn);
+    Ok(pool)
+}
+
+/// 读取 JSON 文件并迁移到当前格式（不写回文件），文件不存在时返回 None
+fn read_json_file(kind: Option<FileKind>, path: &Path) -> Result<Option<Value>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let name = path.display();
+    let json_str = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    let value: Value = serde_json::from_str(&json_str)
+        .map_err(|e| format!("Failed to parse {}: {}", name, e))?;
+
+    match kind {
+        Some(kind) => Ok(Some(migrate(kind, value)?.0)),
+        None => Ok(Some(value)),
+    }
+}
+
+fn parse_json<T: DeserializeOwned>(value: Value, what: &str) -> Result<T, String> {
+    serde_json::from_value(value).map_err(|e| format!("Failed to parse {}: {}", what, e))
+}
+
+/// 首次运行时导入原有的 JSON 文件；原文件保留不动，不再读取
+fn import_json_files(conn: &mut SqliteConnection) -> StepFuture<'_> {
+    Box::pin(async move {
+        let data_dir = get_data_dir()?;
+
+        if let Some(value) = read_json_file(Some(FileKind::Todos), &data_dir.join("todos.json"))? {
+            let todos: TodoFile = parse_json(value, "todos.json")?;
+            write_todo_rows(conn, &todos.data).await?;
+            write_todos_meta(conn, todos.last_update.as_deref(), todos.source.as_deref()).await?;
+        }
+
+        if let Some(value) = read_json_file(Some(FileKind::Archive), &data_dir.join("stage.json"))? {
+            let archive: ArchivedTodos = parse_json(value, "stage.json")?;
+            write_archive_rows(conn, &archive).await?;
+        }
+
+        if let Some(value) = read_json_file(Some(FileKind::Trash), &data_dir.join("trash.json"))? {
+            let trash: TrashFile = parse_json(value, "trash.json")?;
+            write_trash_rows(conn, &trash.todos).await?;
+        }
+
+        let settings_file = get_config_dir()?.join("settings.json");
+        if let Some(Value::Object(settings)) = read_json_file(Some(FileKind::Settings), &settings_file)? {
+            // 只写入此时已有的列（modified_at 由之后的迁移添加），格式版本只属于原来的设置文件
+            for (key, value) in settings.iter().filter(|(key, _)| key.as_str() != FORMAT_VERSION_KEY) {
+                write_meta_row(conn, "settings", key, &to_json(value, key)?).await?;
+            }
+        }
+
+        for (file, key) in SYNC_META_FILES {
+            if let Some(value) = read_json_file(None, &data_dir.join(file))? {
+                write_meta_row(conn, "sync_meta", key, &to_json(&value, file)?).await?;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// 导入原有的操作日志（oplog.jsonl）与离线队列（outbox.json）；原文件保留不动，不再读取
+fn import_oplog_and_outbox(conn: &mut SqliteConnection) -> StepFuture<'_> {
+    Box::pin(async move {
+        let data_dir = get_data_dir()?;
+
+        let oplog_file = data_dir.join("oplog.jsonl");
+        if oplog_file.exists() {
+            let content = std::fs::read_to_string(&oplog_file)
+                .map_err(|e| format!("Failed to read oplog.jsonl: {}", e))?;
+            let lines: Vec<&str> = content.lines().filter(|line| !line.trim().is_empty()).collect();
+            let mut ops: Vec<TodoOp> = Vec::with_capacity(lines.len());
+            for (index, line) in lines.iter().enumerate() {
+                match serde_json::from_str(line) {
+                    Ok(op) => ops.push(op),
+                    // 最后一行写入不完整时忽略
+                    Err(_) if index + 1 == lines.len() && !content.ends_with('\n') => break,
+                    Err(e) => return Err(format!("Failed to parse oplog.jsonl line {}: {}", index + 1, e)),
+                }
+            }
+            insert_op_rows(conn, &ops).await?;
+        }
+
+        if let Some(outbox) = read_json_file(None, &data_dir.join("outbox.json"))? {
+            let operations = outbox.get("operations").cloned().unwrap_or(Value::Array(Vec::new()));
+            let operations: Vec<PendingOperation> = parse_json(operations, "outbox.json")?;
+            for pending in &operations {
+                sqlx::query("INSERT INTO outbox (seq, queued_at, operation, attempts, last_error) VALUES (?, ?, ?, ?, ?)")
+                    .bind(pending.seq as i64)
+                    .bind(&pending.queued_at)
+                    .bind(to_json(&pending.operation, "queued operation")?)
+                    .bind(pending.attempts as i64)
+                    .bind(&pending.last_error)
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(|e| format!("Failed to write queued operation {}: {}", pending.seq, e))?;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T, what: &str) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| format!("Failed to serialize {}: {}", what, e))
+}
+
+fn from_json<T: DeserializeOwned>(json: &str, what: &str) -> Result<T, String> {
+    serde_json::from_str(json).map_err(|e| format!("Failed to parse {}: {}", what, e))
+}
+
+fn todo_from_data(row: &sqlx::sqlite::SqliteRow) -> Result<Todo, String> {
+    let id: String = row.get("id");
+    from_json(row.get("data"), &format!("todo {}", id))
+}
+
+async fn begin() -> Result<sqlx::Transaction<'static, sqlx::Sqlite>, String> {
+    pool().await?.begin().await
+        .map_err(|e| format!("Failed to begin local transaction: {}", e))
+}
+
+async fn commit(tx: sqlx::Transaction<'static, sqlx::Sqlite>) -> Result<(), String> {
+    tx.commit().await
+        .map_err(|e| format!("Failed to commit local transaction: {}", e))
+}
+
+// ---------- 待办 ----------
+
+/// 把待办列表写入 todos 表：列表外的行删除，内容或顺序有变化的行更新
+async fn write_todo_rows(conn: &mut SqliteConnection, todos: &[Todo]) -> Result<(), String> {
+    let keep: HashSet<&str> = todos.iter().map(|todo| todo.id.as_str()).collect();
+    let existing: Vec<String> = sqlx::query("SELECT id FROM todos")
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to read todo ids: {}", e))?
+        .iter()
+        .map(|row| row.get("id"))
+        .collect();
+
+    for id in existing.iter().filter(|id| !keep.contains(id.as_str())) {
+        sqlx::query("DELETE FROM todos WHERE id = ?")
+            .bind(id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to delete todo {}: {}", id, e))?;
+    }
+
+    for (position, todo) in todos.iter().enumerate() {
+        sqlx::query(r#"
+            INSERT INTO todos (id, parent_id, position, data) VALUES (?, ?, ?, ?)
+            ON CONFLICT (id) DO UPDATE SET
+                parent_id = excluded.parent_id,
+                position = excluded.position,
+                data = excluded.data
+            WHERE todos.position != excluded.position OR todos.data != excluded.data
+        "#)
+            .bind(&todo.id)
+            .bind(&todo.parent_id)
+            .bind(position as i64)
+            .bind(to_json(todo, "todo")?)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to write todo {}: {}", todo.id, e))?;
+    }
+
+    Ok(())
+}
+
+async fn write_todos_meta(
+    conn: &mut SqliteConnection,
+    last_update: Option<&str>,
+    source: Option<&str>,
+) -> Result<(), String> {
+    for (key, value) in [(TODOS_LAST_UPDATE_KEY, last_update), (TODOS_SOURCE_KEY, source)] {
+        match value {
+            Some(value) => write_meta_row(conn, "meta", key, value).await?,
+            None => delete_meta_row(conn, "meta", key).await?,
+        }
+    }
+    Ok(())
+}
+
+/// 读取整个待办列表（按列表顺序）
+pub async fn read_todos() -> Result<TodoFile, String> {
+    let pool = pool().await?;
+    let rows = sqlx::query("SELECT id, data FROM todos ORDER BY position")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to read todos: {}", e))?;
+
+    let mut conn = pool.acquire().await
+        .map_err(|e| format!("Failed to acquire local database connection: {}", e))?;
+
+    Ok(TodoFile {
+        data: rows.iter().map(todo_from_data).collect::<Result<_, _>>()?,
+        last_update: read_meta_row(&mut conn, "meta", TODOS_LAST_UPDATE_KEY).await?,
+        source: read_meta_row(&mut conn, "meta", TODOS_SOURCE_KEY).await?,
+    })
+}
+
+/// 读取单个待办
+pub async fn read_todo(id: &str) -> Result<Option<Todo>, String> {
+    sqlx::query("SELECT id, data FROM todos WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool().await?)
+        .await
+        .map_err(|e| format!("Failed to read todo {}: {}", id, e))?
+        .map(|row| todo_from_data(&row))
+        .transpose()
+}
+
+/// 按列表顺序读取所有待办的 (id, parentId)
+pub async fn read_todo_tree() -> Result<Vec<(String, Option<String>)>, String> {
+    Ok(sqlx::query("SELECT id, parent_id FROM todos ORDER BY position")
+        .fetch_all(pool().await?)
+        .await
+        .map_err(|e| format!("Failed to read todo tree: {}", e))?
+        .iter()
+        .map(|row| (row.get("id"), row.get("parent_id")))
+        .collect())
+}
+
+/// 替换整个待办列表，并把重新出现在列表中的待办从回收站移除；`history` 与列表在同一事务中记入撤销历史
+pub async fn write_todos(todos: &TodoFile, ops: &[TodoOp], history: Option<&HistoryEntry>) -> Result<(), String> {
+    backup_data("todos.json", false).await?;
+
+    let mut tx = begin().await?;
+    write_todo_rows(&mut tx, &todos.data).await?;
+    insert_op_rows(&mut tx, ops).await?;
+    write_todos_meta(&mut tx, todos.last_update.as_deref(), todos.source.as_deref()).await?;
+    prune_trash(&mut tx).await?;
+    record_history_row(&mut tx, history).await?;
+    commit(tx).await
+}
+
+/// 写入若干待办行：已存在的原位更新，新的按顺序追加到列表末尾；同时从回收站移除
+async fn put_todo_rows(conn: &mut SqliteConnection, todos: &[Todo]) -> Result<(), String> {
+    for todo in todos {
+        sqlx::query(r#"
+            INSERT INTO todos (id, parent_id, position, data)
+            VALUES (?, ?, COALESCE((SELECT MAX(position) FROM todos), -1) + 1, ?)
+            ON CONFLICT (id) DO UPDATE SET parent_id = excluded.parent_id, data = excluded.data
+        "#)
+            .bind(&todo.id)
+            .bind(&todo.parent_id)
+            .bind(to_json(todo, "todo")?)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to write todo {}: {}", todo.id, e))?;
+        sqlx::query("DELETE FROM trash WHERE id = ?")
+            .bind(&todo.id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to update trash: {}", e))?;
+    }
+    Ok(())
+}
+
+/// 按 `order` 重新排列列表
+async fn order_todo_rows(conn: &mut SqliteConnection, order: &[String]) -> Result<(), String> {
+    for (position, id) in order.iter().enumerate() {
+        sqlx::query("UPDATE todos SET position = ? WHERE id = ? AND position != ?")
+            .bind(position as i64)
+            .bind(id)
+            .bind(position as i64)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to reorder todo {}: {}", id, e))?;
+    }
+    Ok(())
+}
+
+/// 把待办行从列表移入回收站（回收站中已有的同 id 项被替换）
+async fn trash_todo_rows(conn: &mut SqliteConnection, trashed: &[Todo]) -> Result<(), String> {
+    for todo in trashed {
+        sqlx::query("DELETE FROM todos WHERE id = ?")
+            .bind(&todo.id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to delete todo {}: {}", todo.id, e))?;
+    }
+    put_trash_rows(conn, trashed).await
+}
+
+/// 写入若干待办：已存在的原位更新，新的按顺序追加到列表末尾；同时从回收站移除
+pub async fn put_todos(
+    todos: &[Todo],
+    ops: &[TodoOp],
+    history: Option<&HistoryEntry>,
+    last_update: &str,
+    source: &str,
+) -> Result<(), String> {
+    backup_data("todos.json", false).await?;
+
+    let mut tx = begin().await?;
+    put_todo_rows(&mut tx, todos).await?;
+    insert_op_rows(&mut tx, ops).await?;
+    write_todos_meta(&mut tx, Some(last_update), Some(source)).await?;
+    record_history_row(&mut tx, history).await?;
+    commit(tx).await
+}
+
+/// 写入若干待办并按 `order` 重新排列列表（`order` 须包含所有待办 id）
+pub async fn put_todos_in_order(
+    todos: &[Todo],
+    order: &[String],
+    ops: &[TodoOp],
+    history: Option<&HistoryEntry>,
+    last_update: &str,
+    source: &str,
+) -> Result<(), String> {
+    backup_data("todos.json", false).await?;
+
+    let mut tx = begin().await?;
+    for todo in todos {
+        sqlx::query("UPDATE todos SET parent_id = ?, data = ? WHERE id = ?")
+            .bind(&todo.parent_id)
+            .bind(to_json(todo, "todo")?)
+            .bind(&todo.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to write todo {}: {}", todo.id, e))?;
+    }
+    order_todo_rows(&mut tx, order).await?;
+    insert_op_rows(&mut tx, ops).await?;
+    write_todos_meta(&mut tx, Some(last_update), Some(source)).await?;
+    record_history_row(&mut tx, history).await?;
+    commit(tx).await
+}
+
+/// 把待办从列表移入回收站（回收站中已有的同 id 项被替换）
+pub async fn move_todos_to_trash(
+    trashed: &[Todo],
+    ops: &[TodoOp],
+    history: Option<&HistoryEntry>,
+    last_update: &str,
+    source: &str,
+) -> Result<(), String> {
+    backup_data("todos.json", false).await?;
+    backup_data("trash.json", false).await?;
+
+    let mut tx = begin().await?;
+    trash_todo_rows(&mut tx, trashed).await?;
+    insert_op_rows(&mut tx, ops).await?;
+    write_todos_meta(&mut tx, Some(last_update), Some(source)).await?;
+    record_history_row(&mut tx, history).await?;
+    commit(tx).await
+}
+
+/// 在一个事务中把 `trashed` 移入回收站、写入 `upserted`（新的追加到末尾），
+/// `order` 不为 None 时再按其重新排列列表
+pub async fn apply_todo_changes(
+    upserted: &[Todo],
+    trashed: &[Todo],
+    order: Option<&[String]>,
+    ops: &[TodoOp],
+    last_update: &str,
+    source: &str,
+) -> Result<(), String> {
+    backup_data("todos.json", false).await?;
+    backup_data("trash.json", false).await?;
+
+    let mut tx = begin().await?;
+    trash_todo_rows(&mut tx, trashed).await?;
+    put_todo_rows(&mut tx, upserted).await?;
+    if let Some(order) = order {
+        order_todo_rows(&mut tx, order).await?;
+    }
+    insert_op_rows(&mut tx, ops).await?;
+    write_todos_meta(&mut tx, Some(last_update), Some(source)).await?;
+    commit(tx).await
+}
+
+// ---------- 归档 ----------
+
+async fn write_archive_rows(conn: &mut SqliteConnection, archive: &ArchivedTodos) -> Result<(), String> {
+    for statement in ["DELETE FROM archived_todos", "DELETE FROM archive_batches"] {
+        sqlx::query(statement)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to clear archive: {}", e))?;
+    }
+    for batch in &archive.batches {
+        insert_archive_batch(conn, batch).await?;
+    }
+    Ok(())
+}
+
+async fn insert_archive_batch(conn: &mut SqliteConnection, batch: &ArchiveBatch) -> Result<(), String> {
+    sqlx::query(r#"
+        INSERT INTO archive_batches (id, position, archived_at)
+        VALUES (?, COALESCE((SELECT MAX(position) FROM archive_batches), -1) + 1, ?)
+    "#)
+        .bind(&batch.id)
+        .bind(&batch.archived_at)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to write archive batch {}: {}", batch.id, e))?;
+
+    for (position, todo) in batch.todos.iter().enumerate() {
+        sqlx::query("INSERT INTO archived_todos (id, batch_id, position, data) VALUES (?, ?, ?, ?)")
+            .bind(&todo.id)
+            .bind(&batch.id)
+            .bind(position as i64)
+            .bind(to_json(todo, "archived todo")?)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to write archived todo {}: {}", todo.id, e))?;
+    }
+    Ok(())
+}
+
+/// 读取所有归档批次（先归档的在前）
+pub async fn read_archive() -> Result<ArchivedTodos, String> {
+    let pool = pool().await?;
+    let batch_rows = sqlx::query("SELECT id, archived_at FROM archive_batches ORDER BY position")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to read archive batches: {}", e))?;
+    let todo_rows = sqlx::query("SELECT id, batch_id, data FROM archived_todos ORDER BY batch_id, position")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to read archived todos: {}", e))?;
+
+    let mut batches: Vec<ArchiveBatch> = batch_rows
+        .iter()
+        .map(|row| ArchiveBatch {
+            id: row.get("id"),
+            archived_at: row.get("archived_at"),
+            todos: Vec::new(),
+        })
+        .collect();
+    for row in &todo_rows {
+        let batch_id: String = row.get("batch_id");
+        let batch = batches
+            .iter_mut()
+            .find(|batch| batch.id == batch_id)
+            .ok_or_else(|| format!("Archived todo {} refers to missing batch {}", row.get::<String, _>("id"), batch_id))?;
+        batch.todos.push(todo_from_data(row)?);
+    }
+
+    Ok(ArchivedTodos { batches })
+}
+
+/// 归档中所有待办的 id
+pub async fn archived_ids() -> Result<HashSet<String>, String> {
+    Ok(sqlx::query("SELECT id FROM archived_todos")
+        .fetch_all(pool().await?)
+        .await
+        .map_err(|e| format!("Failed to read archived ids: {}", e))?
+        .iter()
+        .map(|row| row.get("id"))
+        .collect())
+}
+
+/// 替换整个归档
+pub async fn write_archive(archive: &ArchivedTodos) -> Result<(), String> {
+    backup_data("stage.json", false).await?;
+
+    let mut tx = begin().await?;
+    write_archive_rows(&mut tx, archive).await?;
+    commit(tx).await
+}
+
+/// 把一批待办从列表移入归档（作为最新的批次）
+pub async fn archive_batch(batch: &ArchiveBatch, last_update: &str, source: &str) -> Result<(), String> {
+    backup_data("todos.json", false).await?;
+    backup_data("stage.json", false).await?;
+
+    let mut tx = begin().await?;
+    insert_archive_batch(&mut tx, batch).await?;
+    for todo in &batch.todos {
+        sqlx::query("DELETE FROM todos WHERE id = ?")
+            .bind(&todo.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to delete todo {}: {}", todo.id, e))?;
+    }
+    write_todos_meta(&mut tx, Some(last_update), Some(source)).await?;
+    commit(tx).await
+}
+
+/// 把归档中的待办移回列表末尾，移空的批次随之删除
+pub async fn unarchive(todos: &[Todo], ops: &[TodoOp], last_update: &str, source: &str) -> Result<(), String> {
+    backup_data("todos.json", false).await?;
+    backup_data("stage.json", false).await?;
+
+    let mut tx = begin().await?;
+    for todo in todos {
+        sqlx::query("DELETE FROM archived_todos WHERE id = ?")
+            .bind(&todo.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to delete archived todo {}: {}", todo.id, e))?;
+        sqlx::query(r#"
+            INSERT INTO todos (id, parent_id, position, data)
+            VALUES (?, ?, COALESCE((SELECT MAX(position) FROM todos), -1) + 1, ?)
+        "#)
+            .bind(&todo.id)
+            .bind(&todo.parent_id)
+            .bind(to_json(todo, "todo")?)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to write todo {}: {}", todo.id, e))?;
+    }
+    sqlx::query("DELETE FROM archive_batches WHERE id NOT IN (SELECT DISTINCT batch_id FROM archived_todos)")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to delete empty archive batches: {}", e))?;
+    insert_op_rows(&mut tx, ops).await?;
+    write_todos_meta(&mut tx, Some(last_update), Some(source)).await?;
+    commit(tx).await
+}
+
+// ---------- 回收站 ----------
+
+async fn put_trash_rows(conn: &mut SqliteConnection, todos: &[Todo]) -> Result<(), String> {
+    for todo in todos {
+        sqlx::query("INSERT INTO trash (id, data) VALUES (?, ?) ON CONFLICT (id) DO UPDATE SET data = excluded.data")
+            .bind(&todo.id)
+            .bind(to_json(todo, "trashed todo")?)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to write trashed todo {}: {}", todo.id, e))?;
+    }
+    Ok(())
+}
+
+async fn write_trash_rows(conn: &mut SqliteConnection, todos: &[Todo]) -> Result<(), String> {
+    sqlx::query("DELETE FROM trash")
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to clear trash: {}", e))?;
+    put_trash_rows(conn, todos).await
+}
+
+/// 重新出现在列表中的待办不再留在回收站
+async fn prune_trash(conn: &mut SqliteConnection) -> Result<(), String> {
+    sqlx::query("DELETE FROM trash WHERE id IN (SELECT id FROM todos)")
+        .execute(&mut *conn)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to update trash: {}", e))
+}
+
+/// 读取回收站
+pub async fn read_trash() -> Result<TrashFile, String> {
+    let rows = sqlx::query("SELECT id, data FROM trash ORDER BY rowid")
+        .fetch_all(pool().await?)
+        .await
+        .map_err(|e| format!("Failed to read trash: {}", e))?;
+
+    Ok(TrashFile {
+        todos: rows.iter().map(todo_from_data).collect::<Result<_, _>>()?,
+    })
+}
+
+// ---------- 设置 ----------
+
+/// 替换全部设置行：不再出现的字段删除，值有变化或新增的字段记录 `modified_at`
+async fn write_settings_rows(
+    conn: &mut SqliteConnection,
+    settings: &Map<String, Value>,
+    modified_at: &str,
+    field_times: &HashMap<String, String>,
+) -> Result<(), String> {
+    let existing: Vec<String> = sqlx::query("SELECT key FROM settings")
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to read settings: {}", e))?
+        .iter()
+        .map(|row| row.get("key"))
+        .collect();
+    for key in existing.iter().filter(|key| !settings.contains_key(key.as_str())) {
+        sqlx::query("DELETE FROM settings WHERE key = ?")
+            .bind(key)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to delete setting {}: {}", key, e))?;
+    }
+
+    // 格式版本只属于原来的设置文件
+    for (key, value) in settings.iter().filter(|(key, _)| key.as_str() != FORMAT_VERSION_KEY) {
+        sqlx::query(r#"
+            INSERT INTO settings (key, value, modified_at) VALUES (?, ?, ?)
+            ON CONFLICT (key) DO UPDATE SET value = excluded.value, modified_at = excluded.modified_at
+            WHERE settings.value != excluded.value
+        "#)
+            .bind(key)
+            .bind(to_json(value, key)?)
+            .bind(field_times.get(key).map_or(modified_at, String::as_str))
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to write setting {}: {}", key, e))?;
+    }
+    Ok(())
+}
+
+/// 读取设置，尚未保存过设置时返回 None
+pub async fn read_settings() -> Result<Option<Map<String, Value>>, String> {
+    let rows = sqlx::query("SELECT key, value FROM settings ORDER BY key")
+        .fetch_all(pool().await?)
+        .await
+        .map_err(|e| format!("Failed to read settings: {}", e))?;
+    if rows.is_empty() {
+        return Ok(None);
+    }
+
+    rows.iter()
+        .map(|row| {
+            let key: String = row.get("key");
+            let value = from_json(row.get("value"), &key)?;
+            Ok((key, value))
+        })
+        .collect::<Result<_, String>>()
+        .map(Some)
+}
+
+/// 各设置字段最后一次改变的时间，从设置文件导入后未改变过的字段不包括在内
+pub async fn read_settings_times() -> Result<HashMap<String, String>, String> {
+    Ok(sqlx::query("SELECT key, modified_at FROM settings WHERE modified_at IS NOT NULL")
+        .fetch_all(pool().await?)
+        .await
+        .map_err(|e| format!("Failed to read settings: {}", e))?
+        .iter()
+        .map(|row| (row.get("key"), row.get("modified_at")))
+        .collect())
+}
+
+/// 替换全部设置，值有变化的字段记为在 `field_times` 中各自的时间修改，没有记录的为 `modified_at`
+pub async fn write_settings(
+    settings: &Map<String, Value>,
+    modified_at: &str,
+    field_times: &HashMap<String, String>,
+) -> Result<(), String> {
+    backup_data("settings.json", false).await?;
+
+    let mut tx = begin().await?;
+    write_settings_rows(&mut tx, settings, modified_at, field_times).await?;
+    commit(tx).await
+}
+
+// ---------- 元数据 ----------
+
+async fn read_meta_row(conn: &mut SqliteConnection, table: &str, key: &str) -> Result<Option<String>, String> {
+    Ok(sqlx::query(&format!("SELECT value FROM {} WHERE key = ?", table))
+        .bind(key)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", key, e))?
+        .map(|row| row.get("value")))
+}
+
+async fn write_meta_row(conn: &mut SqliteConnection, table: &str, key: &str, value: &str) -> Result<(), String> {
+    sqlx::query(&format!(
+        "INSERT INTO {} (key, value) VALUES (?, ?) ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        table
+    ))
+        .bind(key)
+        .bind(value)
+        .execute(&mut *conn)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to write {}: {}", key, e))
+}
+
+async fn delete_meta_row(conn: &mut SqliteConnection, table: &str, key: &str) -> Result<(), String> {
+    sqlx::query(&format!("DELETE FROM {} WHERE key = ?", table))
+        .bind(key)
+        .execute(&mut *conn)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to delete {}: {}", key, e))
+}
+
+/// 读取同步元数据（同步基线、冲突记录等），不存在时返回 None
+pub async fn read_sync_meta<T: DeserializeOwned>(key: &str) -> Result<Option<T>, String> {
+    let mut conn = pool().await?.acquire().await
+        .map_err(|e| format!("Failed to acquire local database connection: {}", e))?;
+    read_meta_row(&mut conn, "sync_meta", key).await?
+        .map(|json| from_json(&json, key))
+        .transpose()
+}
+
+/// 写入同步元数据
+pub async fn write_sync_meta<T: Serialize>(key: &str, value: &T) -> Result<(), String> {
+    let mut conn = pool().await?.acquire().await
+        .map_err(|e| format!("Failed to acquire local database connection: {}", e))?;
+    write_meta_row(&mut conn, "sync_meta", key, &to_json(value, key)?).await
+}
+
+// ---------- 操作日志 ----------
+
+/// 追加操作，日志中已有的操作（按 id）忽略
+///
+/// 修改待办的写入函数把这次修改的操作 `ops` 与修改放在同一事务中追加，两者不会只写入一方。
+async fn insert_op_rows(conn: &mut SqliteConnection, ops: &[TodoOp]) -> Result<(), String> {
+    for op in ops {
+        sqlx::query("INSERT OR IGNORE INTO oplog (op_id, todo_id, data) VALUES (?, ?, ?)")
+            .bind(&op.id)
+            .bind(&op.todo_id)
+            .bind(to_json(op, "todo operation")?)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to write todo operation {}: {}", op.id, e))?;
+    }
+    Ok(())
+}
+
+/// 按追加顺序读取序号大于 `after` 的操作，返回 (序号, 操作)
+pub async fn read_ops(after: i64) -> Result<Vec<(i64, TodoOp)>, String> {
+    sqlx::query("SELECT seq, op_id, data FROM oplog WHERE seq > ? ORDER BY seq")
+        .bind(after)
+        .fetch_all(pool().await?)
+        .await
+        .map_err(|e| format!("Failed to read todo operations: {}", e))?
+        .iter()
+        .map(|row| {
+            let id: String = row.get("op_id");
+            Ok((row.get("seq"), from_json(row.get("data"), &format!("todo operation {}", id))?))
+        })
+        .collect()
+}
+
+/// 按追加顺序读取一个待办的操作
+pub async fn read_todo_ops(todo_id: &str) -> Result<Vec<TodoOp>, String> {
+    sqlx::query("SELECT op_id, data FROM oplog WHERE todo_id = ? ORDER BY seq")
+        .bind(todo_id)
+        .fetch_all(pool().await?)
+        .await
+        .map_err(|e| format!("Failed to read operations of todo {}: {}", todo_id, e))?
+        .iter()
+        .map(|row| {
+            let id: String = row.get("op_id");
+            from_json(row.get("data"), &format!("todo operation {}", id))
+        })
+        .collect()
+}
+
+/// 操作日志是否为空
+pub async fn oplog_is_empty() -> Result<bool, String> {
+    Ok(sqlx::query("SELECT 1 FROM oplog LIMIT 1")
+        .fetch_optional(pool().await?)
+        .await
+        .map_err(|e| format!("Failed to read todo operations: {}", e))?
+        .is_none())
+}
+
+/// 追加不伴随待办修改的操作（日志为空时记录的完整快照）
+pub async fn append_ops(ops: &[TodoOp]) -> Result<(), String> {
+    let mut tx = begin().await?;
+    insert_op_rows(&mut tx, ops).await?;
+    commit(tx).await
+}
+
+// ---------- 离线队列 ----------
+
+/// 按入队顺序读取离线队列
+pub async fn read_outbox() -> Result<Vec<PendingOperation>, String> {
+    sqlx::query("SELECT seq, queued_at, operation, attempts, last_error FROM outbox ORDER BY seq")
+        .fetch_all(pool().await?)
+        .await
+        .map_err(|e| format!("Failed to read outbox: {}", e))?
+        .iter()
+        .map(|row| {
+            let seq: i64 = row.get("seq");
+            let attempts: i64 = row.get("attempts");
+            Ok(PendingOperation {
+                seq: seq as u64,
+                queued_at: row.get("queued_at"),
+                operation: from_json(row.get("operation"), &format!("queued operation {}", seq))?,
+                attempts: attempts as u32,
+                last_error: row.get("last_error"),
+                failed: attempts >= MAX_REPLAY_ATTEMPTS as i64,
+            })
+        })
+        .collect()
+}
+
+/// 在一个事务中移除被取代的操作 `superseded`，并把 `operations` 依次加入队列末尾
+pub async fn enqueue_operations(superseded: &[u64], operations: &[Operation], queued_at: &str) -> Result<(), String> {
+    let mut tx = begin().await?;
+    for seq in superseded {
+        remove_outbox_row(&mut tx, *seq).await?;
+    }
+    for operation in operations {
+        sqlx::query("INSERT INTO outbox (queued_at, operation) VALUES (?, ?)")
+            .bind(queued_at)
+            .bind(to_json(operation, "queued operation")?)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to write outbox: {}", e))?;
+    }
+    commit(tx).await
+}
+
+async fn remove_outbox_row(conn: &mut SqliteConnection, seq: u64) -> Result<(), String> {
+    sqlx::query("DELETE FROM outbox WHERE seq = ?")
+        .bind(seq as i64)
+        .execute(&mut *conn)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to remove queued operation {}: {}", seq, e))
+}
+
+/// 从队列中移除已回放的操作
+pub async fn remove_queued_operation(seq: u64) -> Result<(), String> {
+    let mut conn = pool().await?.acquire().await
+        .map_err(|e| format!("Failed to acquire local database connection: {}", e))?;
+    remove_outbox_row(&mut conn, seq).await
+}
+
+/// 记录操作回放失败：增加失败次数并保存最近一次的错误
+pub async fn record_queued_failure(seq: u64, error: &str) -> Result<(), String> {
+    sqlx::query("UPDATE outbox SET attempts = attempts + 1, last_error = ? WHERE seq = ?")
+        .bind(error)
+        .bind(seq as i64)
+        .execute(pool().await?)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to update queued operation {}: {}", seq, e))
+}
+
+/// 清零操作的失败次数，保留最近一次的错误
+pub async fn reset_queued_failures(seq: u64) -> Result<(), String> {
+    sqlx::query("UPDATE outbox SET attempts = 0 WHERE seq = ?")
+        .bind(seq as i64)
+        .execute(pool().await?)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to update queued operation {}: {}", seq, e))
+}
+
+// ---------- 撤销历史 ----------
+
+/// 把记录压入 `stack` 栈顶，超过 `limit` 条时丢弃最早的记录
+async fn insert_history_row(
+    conn: &mut SqliteConnection,
+    stack: HistoryStack,
+    entry: &HistoryEntry,
+    limit: usize,
+) -> Result<(), String> {
+    sqlx::query("INSERT INTO history (stack, data) VALUES (?, ?)")
+        .bind(stack.as_str())
+        .bind(to_json(entry, "history entry")?)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to write history: {}", e))?;
+    sqlx::query(r#"
+        DELETE FROM history WHERE stack = ? AND id NOT IN (
+            SELECT id FROM history WHERE stack = ? ORDER BY id DESC LIMIT ?
+        )
+    "#)
+        .bind(stack.as_str())
+        .bind(stack.as_str())
+        .bind(limit as i64)
+        .execute(&mut *conn)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to trim history: {}", e))
+}
+
+/// 记录一步新的修改（为 None 或没有变化时不记录）：压入撤销栈并清空重做栈，
+/// 之前撤销的步骤不能再重做
+async fn record_history_row(conn: &mut SqliteConnection, entry: Option<&HistoryEntry>) -> Result<(), String> {
+    let Some(entry) = entry.filter(|entry| !entry.is_empty()) else {
+        return Ok(());
+    };
+    sqlx::query("DELETE FROM history WHERE stack = ?")
+        .bind(HistoryStack::Redo.as_str())
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to clear redo history: {}", e))?;
+    insert_history_row(conn, HistoryStack::Undo, entry, HISTORY_LIMIT).await
+}
+
+/// 把记录压入 `stack` 栈顶
+pub async fn push_history(stack: HistoryStack, entry: &HistoryEntry, limit: usize) -> Result<(), String> {
+    let mut tx = begin().await?;
+    insert_history_row(&mut tx, stack, entry, limit).await?;
+    commit(tx).await
+}
+
+/// 取出 `stack` 栈顶的记录，栈为空时返回 None
+pub async fn pop_history(stack: HistoryStack) -> Result<Option<HistoryEntry>, String> {
+    let mut tx = begin().await?;
+    let Some(row) = sqlx::query("SELECT id, data FROM history WHERE stack = ? ORDER BY id DESC LIMIT 1")
+        .bind(stack.as_str())
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to read history: {}", e))?
+    else {
+        return Ok(None);
+    };
+
+    let id: i64 = row.get("id");
+    sqlx::query("DELETE FROM history WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to update history: {}", e))?;
+    commit(tx).await?;
+
+    from_json(row.get("data"), "history entry").map(Some)
+}
+
+/// `stack` 栈中的记录数
+pub async fn history_len(stack: HistoryStack) -> Result<usize, String> {
+    let count: i64 = sqlx::query("SELECT COUNT(*) AS count FROM history WHERE stack = ?")
+        .bind(stack.as_str())
+        .fetch_one(pool().await?)
+        .await
+        .map_err(|e| format!("Failed to read history: {}", e))?
+        .get("count");
+    Ok(count as usize)
+}
+
+// ---------- 备份 ----------
+
+/// 把待办、归档、回收站或设置的当前内容以原 JSON 文件的格式写成备份
+///
+/// `force` 为 false 时，若最近一次备份距今不足备份间隔则跳过；内容为空时不备份。
+pub async fn backup_data(file_name: &str, force: bool) -> Result<(), String> {
+    if !force && !backup_due(file_name)? {
+        return Ok(());
+    }
+
+    let value = match file_name {
+        "todos.json" => {
+            let todos = read_todos().await?;
+            if todos.data.is_empty() {
+                return Ok(());
+            }
+            to_versioned_value(FileKind::Todos, &todos)?
+        }
+        "stage.json" => {
+            let archive = read_archive().await?;
+            if archive.batches.is_empty() {
+                return Ok(());
+            }
+            to_versioned_value(FileKind::Archive, &archive)?
+        }
+        "trash.json" => {
+            let trash = read_trash().await?;
+            if trash.todos.is_empty() {
+                return Ok(());
+            }
+            to_versioned_value(FileKind::Trash, &trash)?
+        }
+        "settings.json" => {
+            let Some(settings) = read_settings().await? else {
+                return Ok(());
+            };
+            to_versioned_value(FileKind::Settings, &settings)?
+        }
+        _ => return Err(format!("Unknown data file: {}", file_name)),
+    };
+
+    let json_str = serde_json::to_string_pretty(&value)
+        .map_err(|e| format!("Failed to serialize {}: {}", file_name, e))?;
+    write_backup(file_name, json_str.as_bytes())
+}
+
+/// 在一个事务中写入导入的待办、归档与回收站（为 None 的部分保持不变）、列表修改对应的操作
+/// 与撤销历史，写入前先强制备份将被替换的内容
+pub async fn write_imported_data(
+    todos: Option<&TodoFile>,
+    archive: Option<&ArchivedTodos>,
+    trash: Option<&TrashFile>,
+    ops: &[TodoOp],
+    history: Option<&HistoryEntry>,
+) -> Result<(), String> {
+    for (file_name, present) in [("todos.json", todos.is_some()), ("stage.json", archive.is_some()), ("trash.json", trash.is_some())] {
+        if present {
+            backup_data(file_name, true).await?;
+        }
+    }
+
+    let mut tx = begin().await?;
+    if let Some(archive) = archive {
+        write_archive_rows(&mut tx, archive).await?;
+    }
+    if let Some(trash) = trash {
+        write_trash_rows(&mut tx, &trash.todos).await?;
+    }
+    if let Some(todos) = todos {
+        write_todo_rows(&mut tx, &todos.data).await?;
+        write_todos_meta(&mut tx, todos.last_update.as_deref(), todos.source.as_deref()).await?;
+        prune_trash(&mut tx).await?;
+    }
+    insert_op_rows(&mut tx, ops).await?;
+    record_history_row(&mut tx, history).await?;
+    commit(tx).await
+}
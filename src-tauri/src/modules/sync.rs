@@ -0,0 +1,926 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::modules::crdt::TextCrdt;
+use crate::modules::data::replace_todo_with;
+use crate::modules::store;
+use crate::modules::types::{detach_orphans, parse_utc, same_instant, Todo};
+
+// 参与三方合并的待办字段（不含 id 与 lastUpdate）
+#[derive(Debug, Clone, Copy)]
+enum MergeField {
+    Text,
+    Completed,
+    CreatedAt,
+    CompletedAt,
+    Deadline,
+    ParentId,
+    IsDeleted,
+}
+
+const MERGE_FIELDS: [MergeField; 7] = [
+    MergeField::Text,
+    MergeField::Completed,
+    MergeField::CreatedAt,
+    MergeField::CompletedAt,
+    MergeField::Deadline,
+    MergeField::ParentId,
+    MergeField::IsDeleted,
+];
+
+impl MergeField {
+    fn name(self) -> &'static str {
+        match self {
+            MergeField::Text => "text",
+            MergeField::Completed => "completed",
+            MergeField::CreatedAt => "createdAt",
+            MergeField::CompletedAt => "completedAt",
+            MergeField::Deadline => "deadline",
+            MergeField::ParentId => "parentId",
+            MergeField::IsDeleted => "isDeleted",
+        }
+    }
+
+    fn same(self, a: &Todo, b: &Todo) -> bool {
+        match self {
+            MergeField::Text => a.text == b.text,
+            MergeField::Completed => a.completed == b.completed,
+            MergeField::CreatedAt => same_instant(Some(&a.created_at), Some(&b.created_at)),
+            MergeField::CompletedAt => same_instant(a.completed_at.as_deref(), b.completed_at.as_deref()),
+            MergeField::Deadline => same_instant(a.deadline.as_deref(), b.deadline.as_deref()),
+            MergeField::ParentId => a.parent_id == b.parent_id,
+            MergeField::IsDeleted => a.is_deleted == b.is_deleted,
+        }
+    }
+
+    fn copy(self, from: &Todo, to: &mut Todo) {
+        match self {
+            MergeField::Text => {
+                to.text = from.text.clone();
+                to.text_crdt = from.text_crdt.clone();
+            }
+            MergeField::Completed => to.completed = from.completed,
+            MergeField::CreatedAt => to.created_at = from.created_at.clone(),
+            MergeField::CompletedAt => to.completed_at = from.completed_at.clone(),
+            MergeField::Deadline => to.deadline = from.deadline.clone(),
+            MergeField::ParentId => to.parent_id = from.parent_id.clone(),
+            MergeField::IsDeleted => to.is_deleted = from.is_deleted,
+        }
+    }
+}
+
+// 上次同步成功时的基线快照（每台设备本地保存一份）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncBase {
+    // 基线对应的远程数据库标识，切换数据库后基线失效
+    pub database: String,
+    pub last_sync: String,
+    pub items: HashMap<String, Todo>,
+}
+
+// 未解决的同步冲突（同一待办的同一字段在两端都被修改）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncConflict {
+    pub id: String,
+    pub fields: Vec<String>,
+    pub local: Todo,
+    pub remote: Todo,
+    pub base: Option<Todo>,
+    pub detected_at: String,
+}
+
+// 冲突解决方式（Merged 为要覆盖到本地版本上的字段）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum ConflictChoice {
+    KeepLocal,
+    KeepRemote,
+    Merged(Value),
+}
+
+// 逐项合并的结果
+#[derive(Debug, Default)]
+pub struct MergeOutcome {
+    // 合并后写回本地的待办列表（不含已删除项）
+    pub local: Vec<Todo>,
+    // 需要写入远程的待办项
+    pub upload: Vec<Todo>,
+    // 合并后的新基线
+    pub base: HashMap<String, Todo>,
+    pub uploaded: usize,
+    pub downloaded: usize,
+    pub merged: usize,
+    // 父项已被删除、移到顶层的待办数量
+    pub detached: usize,
+    // 合并后仍未解决的冲突（包括之前遗留的）
+    pub conflicts: Vec<SyncConflict>,
+}
+
+impl MergeOutcome {
+    pub fn local_changed(&self) -> bool {
+        self.downloaded > 0 || self.merged > 0 || self.detached > 0
+    }
+}
+
+// 设置逐字段合并的结果（字段名按字母排序）
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsMerge {
+    // 本地较新、需要写入远程的字段
+    pub upload_fields: Vec<String>,
+    // 远程较新、需要写入本地的字段
+    pub download_fields: Vec<String>,
+}
+
+// 字段的修改时间，没有单独记录时使用该端的 lastUpdate
+fn field_time<'a>(times: &'a HashMap<String, String>, key: &str, last_update: Option<&'a str>) -> Option<&'a str> {
+    times.get(key).map(String::as_str).or(last_update)
+}
+
+// 本地的修改时间是否晚于远程；本地没有时间（从未保存过设置）时以远程为准，无法解析时以本地为准
+fn local_is_newer(local_time: Option<&str>, remote_time: Option<&str>) -> bool {
+    match (local_time, remote_time) {
+        (Some(local), Some(remote)) => match (parse_utc(local), parse_utc(remote)) {
+            (Some(local), Some(remote)) => local > remote,
+            _ => true,
+        },
+        (None, Some(_)) => false,
+        _ => true,
+    }
+}
+
+// 逐字段合并设置：两端值不同的字段各自按修改时间取较新的一端，只有一端有的字段补到另一端
+//
+// lastUpdate 不参与比较，由同步双方各自取两端较新的一个。
+pub fn merge_settings(
+    local: &Map<String, Value>,
+    local_times: &HashMap<String, String>,
+    local_last_update: Option<&str>,
+    remote: &Map<String, Value>,
+    remote_times: &HashMap<String, String>,
+    remote_last_update: Option<&str>,
+) -> SettingsMerge {
+    let keys: BTreeSet<&String> = local.keys().chain(remote.keys())
+        .filter(|key| key.as_str() != "lastUpdate")
+        .collect();
+    
+    let mut merge = SettingsMerge::default();
+    for key in keys {
+        let upload = match (local.get(key), remote.get(key)) {
+            (Some(local_value), Some(remote_value)) if local_value == remote_value => continue,
+            (Some(_), Some(_)) => local_is_newer(
+                field_time(local_times, key, local_last_update),
+                field_time(remote_times, key, remote_last_update),
+            ),
+            (Some(_), None) => true,
+            _ => false,
+        };
+        if upload {
+            merge.upload_fields.push(key.clone());
+        } else {
+            merge.download_fields.push(key.clone());
+        }
+    }
+    merge
+}
+
+// 两个 lastUpdate 中较新的一个
+pub fn latest_time(a: Option<&str>, b: Option<&str>) -> Option<String> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if local_is_newer(Some(b), Some(a)) { b } else { a }.to_string()),
+        (a, b) => a.or(b).map(str::to_string),
+    }
+}
+
+// 取出一端设置中的指定字段及各自的修改时间
+pub fn pick_settings(
+    settings: &Map<String, Value>,
+    times: &HashMap<String, String>,
+    last_update: &str,
+    fields: &[String],
+) -> (Map<String, Value>, HashMap<String, String>) {
+    let mut values = Map::new();
+    let mut field_times = HashMap::new();
+    for key in fields {
+        if let Some(value) = settings.get(key) {
+            values.insert(key.clone(), value.clone());
+            field_times.insert(key.clone(), times.get(key).map_or(last_update, String::as_str).to_string());
+        }
+    }
+    (values, field_times)
+}
+
+// 去掉已在远程彻底删除的待办及其基线与冲突记录，返回从本地去掉的数量
+pub fn drop_purged(
+    local: &mut Vec<Todo>,
+    base: &mut HashMap<String, Todo>,
+    conflicts: &mut Vec<SyncConflict>,
+    purged: &HashSet<String>,
+) -> usize {
+    let before = local.len();
+    local.retain(|item| !purged.contains(&item.id));
+    base.retain(|id, _| !purged.contains(id));
+    conflicts.retain(|conflict| !purged.contains(&conflict.id));
+    before - local.len()
+}
+
+// 一次同步对待办的处理计划（待办 id 列表）
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TodoSyncPlan {
+    // 本地新增或修改，写入远程
+    pub upload: Vec<String>,
+    // 远程新增或修改，写入本地
+    pub download: Vec<String>,
+    // 两端都有修改，合并后写回两端
+    pub merge: Vec<String>,
+    // 本地已删除，在远程逻辑删除
+    pub delete_remote: Vec<String>,
+    // 远程已删除，从本地移除
+    pub delete_local: Vec<String>,
+    // 本地恢复了远程已删除的待办，在远程恢复
+    pub restore_remote: Vec<String>,
+    // 远程恢复了本地已删除的待办，重新加入本地
+    pub restore_local: Vec<String>,
+    // 存在冲突，本次不处理
+    pub conflicts: Vec<String>,
+    // 超过回收站保留期限，从远程彻底删除
+    pub purge_remote: Vec<String>,
+}
+
+impl TodoSyncPlan {
+    // 会在本地删除数据的计划，界面需要先确认
+    pub fn is_destructive(&self) -> bool {
+        !self.delete_local.is_empty()
+    }
+}
+
+// 根据合并结果列出每一项待办的处理方式
+pub fn plan_todo_sync(
+    local: &[Todo],
+    remote: &[Todo],
+    base: &HashMap<String, Todo>,
+    outcome: &MergeOutcome,
+) -> TodoSyncPlan {
+    let local_map: HashMap<&str, &Todo> = local.iter().map(|item| (item.id.as_str(), item)).collect();
+    let remote_map: HashMap<&str, &Todo> = remote.iter().map(|item| (item.id.as_str(), item)).collect();
+    let merged_map: HashMap<&str, &Todo> = outcome.local.iter().map(|item| (item.id.as_str(), item)).collect();
+    let uploaded: BTreeSet<&str> = outcome.upload.iter().map(|item| item.id.as_str()).collect();
+
+    let mut plan = TodoSyncPlan {
+        conflicts: outcome.conflicts.iter().map(|conflict| conflict.id.clone()).collect(),
+        ..TodoSyncPlan::default()
+    };
+
+    // 本地一侧的变化
+    let mut changed_local: BTreeSet<&str> = BTreeSet::new();
+    for item in &outcome.local {
+        let id = item.id.as_str();
+        match local_map.get(id) {
+            Some(before) if same_content(before, item) => {}
+            Some(_) => {
+                changed_local.insert(id);
+            }
+            // 上次同步时已存在：本地删除后远程又有修改，或远程恢复了已删除的待办
+            None if base.contains_key(id) => plan.restore_local.push(id.to_string()),
+            None => plan.download.push(id.to_string()),
+        }
+    }
+    for item in local.iter().filter(|item| !item.is_deleted && !merged_map.contains_key(item.id.as_str())) {
+        plan.delete_local.push(item.id.clone());
+    }
+
+    // 远程一侧的变化
+    for item in &outcome.upload {
+        let id = item.id.as_str();
+        let remote_deleted = remote_map.get(id).map(|r| r.is_deleted);
+        match (remote_deleted, item.is_deleted) {
+            (Some(false), true) => plan.delete_remote.push(id.to_string()),
+            (Some(true), false) => plan.restore_remote.push(id.to_string()),
+            _ if changed_local.contains(id) => plan.merge.push(id.to_string()),
+            _ => plan.upload.push(id.to_string()),
+        }
+    }
+    plan.download.extend(
+        changed_local
+            .into_iter()
+            .filter(|id| !uploaded.contains(id))
+            .map(str::to_string),
+    );
+
+    plan
+}
+
+// 同步基线与冲突记录在本地数据库 sync_meta 中的键
+const SYNC_BASE_KEY: &str = "syncBase";
+const SYNC_CONFLICTS_KEY: &str = "syncConflicts";
+
+// 读取同步基线，不存在时返回空基线
+async fn read_sync_base() -> Result<SyncBase, String> {
+    Ok(store::read_sync_meta(SYNC_BASE_KEY).await
+        .map_err(|e| format!("读取同步基线失败: {}", e))?
+        .unwrap_or_default())
+}
+
+// 加载指定数据库的同步基线，不存在或数据库不匹配时返回空基线
+pub async fn load_sync_base(database: &str) -> Result<SyncBase, String> {
+    let base = read_sync_base().await?;
+
+    if base.database != database {
+        return Ok(SyncBase::default());
+    }
+
+    Ok(base)
+}
+
+// 保存同步基线
+pub async fn save_sync_base(base: &SyncBase) -> Result<(), String> {
+    store::write_sync_meta(SYNC_BASE_KEY, base).await
+        .map_err(|e| format!("保存同步基线失败: {}", e))
+}
+
+// 加载未解决的冲突记录
+pub async fn load_sync_conflicts() -> Result<Vec<SyncConflict>, String> {
+    Ok(store::read_sync_meta(SYNC_CONFLICTS_KEY).await
+        .map_err(|e| format!("读取冲突记录失败: {}", e))?
+        .unwrap_or_default())
+}
+
+// 保存未解决的冲突记录
+pub async fn save_sync_conflicts(conflicts: &[SyncConflict]) -> Result<(), String> {
+    store::write_sync_meta(SYNC_CONFLICTS_KEY, &conflicts).await
+        .map_err(|e| format!("保存冲突记录失败: {}", e))
+}
+
+// 比较两个待办在合并字段上是否一致
+fn same_content(a: &Todo, b: &Todo) -> bool {
+    MERGE_FIELDS.iter().all(|field| field.same(a, b))
+}
+
+// 自上次同步以来本地新增或修改过的待办
+pub fn changed_since_base(local: &[Todo], base: &SyncBase) -> Vec<Todo> {
+    local
+        .iter()
+        .filter(|item| base.items.get(&item.id).is_none_or(|base_item| !same_content(base_item, item)))
+        .cloned()
+        .collect()
+}
+
+// 解析待办的 lastUpdate，缺失时使用所在列表的更新时间
+fn item_time(item: &Todo, fallback: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    parse_utc(item.last_update.as_deref().unwrap_or(fallback))
+}
+
+// 合并两端同源的文本副本，任一端没有副本或来源不同时返回 None
+fn merge_text_crdts(local: &Todo, remote: &Todo) -> Option<TextCrdt> {
+    let (local_crdt, remote_crdt) = (local.text_crdt.as_ref()?, remote.text_crdt.as_ref()?);
+    if !local_crdt.compatible(remote_crdt) {
+        return None;
+    }
+
+    let mut merged = local_crdt.clone();
+    merged.merge(remote_crdt);
+    Some(merged)
+}
+
+// 以本地条目为底，逐字段进行三方合并，返回合并结果与冲突字段
+fn merge_fields(
+    base: Option<&Todo>,
+    local: &Todo,
+    remote: &Todo,
+    local_wins_ties: bool,
+) -> (Todo, Vec<String>) {
+    let mut conflicts = Vec::new();
+    let mut merged = local.clone();
+
+    for field in MERGE_FIELDS {
+        // 两端都有同源的文本副本时按字符合并，并发编辑不会产生冲突
+        if let (MergeField::Text, Some(text_crdt)) = (field, merge_text_crdts(local, remote)) {
+            merged.text = text_crdt.text();
+            merged.text_crdt = Some(text_crdt);
+            continue;
+        }
+
+        if field.same(local, remote) {
+            continue;
+        }
+
+        let take_remote = match base {
+            Some(base_item) => {
+                if field.same(local, base_item) {
+                    true
+                } else if field.same(remote, base_item) {
+                    false
+                } else {
+                    // 两端都修改了同一字段：真正的冲突，留待用户解决
+                    conflicts.push(field.name().to_string());
+                    continue;
+                }
+            }
+            // 没有基线时无法判断修改方，按更新时间取较新的一方
+            None => !local_wins_ties,
+        };
+
+        if take_remote {
+            field.copy(remote, &mut merged);
+        }
+    }
+
+    (merged, conflicts)
+}
+
+// 按待办项对本地、远程与基线进行三方合并，存在未解决冲突的项保持不动
+//
+// `known_ids` 为列表之外（归档中）存在的待办 id：这些待办在本地仍然存在，不当作本地删除写到远程；
+// 父项不在合并结果与 `known_ids` 中的待办移到顶层。
+pub fn merge_todos(
+    local: &[Todo],
+    local_last_update: &str,
+    remote: &[Todo],
+    base: &HashMap<String, Todo>,
+    pending: &[SyncConflict],
+    known_ids: &HashSet<String>,
+    now: &str,
+) -> MergeOutcome {
+    let pending: HashMap<&str, &SyncConflict> = pending
+        .iter()
+        .map(|conflict| (conflict.id.as_str(), conflict))
+        .collect();
+    let local_map: HashMap<&str, &Todo> = local.iter().map(|item| (item.id.as_str(), item)).collect();
+    let remote_map: HashMap<&str, &Todo> = remote.iter().map(|item| (item.id.as_str(), item)).collect();
+
+    // 保持本地顺序，远程新增项追加在后
+    let mut ordered_ids: Vec<&str> = Vec::new();
+    let mut seen: BTreeSet<&str> = BTreeSet::new();
+    for id in local
+        .iter()
+        .chain(remote.iter())
+        .map(|item| item.id.as_str())
+        .chain(base.keys().map(|id| id.as_str()))
+    {
+        if seen.insert(id) {
+            ordered_ids.push(id);
+        }
+    }
+
+    let mut outcome = MergeOutcome::default();
+
+    for id in ordered_ids {
+        let base_item = base.get(id);
+        let local_item = local_map.get(id).copied();
+        let remote_item = remote_map.get(id).copied();
+
+        // 冲突未解决前不覆盖该项，只刷新记录中的两端数据
+        if let Some(conflict) = pending.get(id) {
+            let mut conflict = (*conflict).clone();
+            if let Some(local_item) = local_item {
+                conflict.local = local_item.clone();
+                if !local_item.is_deleted {
+                    outcome.local.push(local_item.clone());
+                }
+            }
+            if let Some(remote_item) = remote_item {
+                conflict.remote = remote_item.clone();
+            }
+            if let Some(base_item) = base_item {
+                outcome.base.insert(id.to_string(), base_item.clone());
+            }
+            outcome.conflicts.push(conflict);
+            continue;
+        }
+
+        match (local_item, remote_item) {
+            (Some(local_item), None) => {
+                if base_item.is_some_and(|b| same_content(b, local_item)) {
+                    // 远程已彻底移除且本地未修改：本地随之移除
+                    outcome.downloaded += 1;
+                    continue;
+                }
+                // 上传待办自身的修改时间，缺失时才使用列表的更新时间
+                let mut item = local_item.clone();
+                item.last_update.get_or_insert_with(|| local_last_update.to_string());
+                outcome.upload.push(item.clone());
+                outcome.uploaded += 1;
+                if !item.is_deleted {
+                    outcome.local.push(local_item.clone());
+                }
+                outcome.base.insert(id.to_string(), item);
+            }
+            (None, Some(remote_item)) if known_ids.contains(id) => {
+                // 已在本地归档：远程保持不变，基线保持不动，取消归档后照常合并
+                outcome.base.insert(id.to_string(), base_item.unwrap_or(remote_item).clone());
+            }
+            (None, Some(remote_item)) => {
+                match base_item {
+                    Some(base_item) if !remote_item.is_deleted && same_content(base_item, remote_item) => {
+                        // 本地已删除且远程未修改：将删除同步到远程
+                        let mut item = remote_item.clone();
+                        item.is_deleted = true;
+                        item.last_update = Some(now.to_string());
+                        outcome.upload.push(item.clone());
+                        outcome.uploaded += 1;
+                        outcome.base.insert(id.to_string(), item);
+                    }
+                    _ => {
+                        if !remote_item.is_deleted {
+                            outcome.local.push(remote_item.clone());
+                            outcome.downloaded += 1;
+                        }
+                        outcome.base.insert(id.to_string(), remote_item.clone());
+                    }
+                }
+            }
+            (Some(local_item), Some(remote_item)) => {
+                if same_content(local_item, remote_item) {
+                    if !local_item.is_deleted {
+                        outcome.local.push(local_item.clone());
+                    }
+                    outcome.base.insert(id.to_string(), remote_item.clone());
+                    continue;
+                }
+
+                let local_wins_ties = match (
+                    item_time(local_item, local_last_update),
+                    item_time(remote_item, local_last_update),
+                ) {
+                    (Some(local_time), Some(remote_time)) => local_time > remote_time,
+                    _ => true,
+                };
+
+                let (mut merged, conflict_fields) =
+                    merge_fields(base_item, local_item, remote_item, local_wins_ties);
+
+                if !conflict_fields.is_empty() {
+                    // 保留本地与旧基线，等待 resolve_sync_conflict
+                    outcome.conflicts.push(SyncConflict {
+                        id: id.to_string(),
+                        fields: conflict_fields,
+                        local: local_item.clone(),
+                        remote: remote_item.clone(),
+                        base: base_item.cloned(),
+                        detected_at: now.to_string(),
+                    });
+                    if !local_item.is_deleted {
+                        outcome.local.push(local_item.clone());
+                    }
+                    if let Some(base_item) = base_item {
+                        outcome.base.insert(id.to_string(), base_item.clone());
+                    }
+                    continue;
+                }
+
+                let changed_remote = !same_content(&merged, remote_item);
+                let changed_local = !same_content(&merged, local_item);
+
+                match (changed_local, changed_remote) {
+                    (true, true) => {
+                        outcome.merged += 1;
+                        merged.last_update = Some(now.to_string());
+                    }
+                    (false, true) => {
+                        outcome.uploaded += 1;
+                        merged.last_update.get_or_insert_with(|| local_last_update.to_string());
+                    }
+                    (true, false) => {
+                        outcome.downloaded += 1;
+                        merged = remote_item.clone();
+                    }
+                    (false, false) => {}
+                }
+
+                if changed_remote {
+                    outcome.upload.push(merged.clone());
+                }
+                if !merged.is_deleted {
+                    outcome.local.push(merged.clone());
+                }
+                outcome.base.insert(id.to_string(), merged);
+            }
+            (None, None) => {
+                // 只存在于基线中：两端都已移除
+            }
+        }
+    }
+
+    // 一端删除了父项、另一端在其下新增或恢复了子项：子项移到顶层并写回两端，
+    // 存在冲突的项只在本地移动，远程留待冲突解决后的同步
+    let detached = detach_orphans(&mut outcome.local, known_ids);
+    let conflicted: HashSet<&str> = outcome.conflicts.iter().map(|conflict| conflict.id.as_str()).collect();
+    for item in outcome.local.iter_mut().filter(|item| detached.contains(&item.id)) {
+        item.last_update = Some(now.to_string());
+        outcome.detached += 1;
+        if conflicted.contains(item.id.as_str()) {
+            continue;
+        }
+        outcome.upload.retain(|uploaded| uploaded.id != item.id);
+        outcome.upload.push(item.clone());
+        outcome.base.insert(item.id.clone(), item.clone());
+    }
+
+    outcome
+}
+
+// 获取未解决的同步冲突列表
+#[tauri::command]
+pub async fn list_sync_conflicts() -> Result<Vec<SyncConflict>, String> {
+    load_sync_conflicts().await
+}
+
+// 解决同步冲突：保留本地、保留远程或使用合并后的值
+#[tauri::command]
+pub async fn resolve_sync_conflict(id: String, choice: ConflictChoice) -> Result<(), String> {
+    let mut conflicts = load_sync_conflicts().await?;
+    let index = conflicts
+        .iter()
+        .position(|conflict| conflict.id == id)
+        .ok_or_else(|| format!("未找到待办 {} 的冲突记录", id))?;
+    let conflict = conflicts.remove(index);
+
+    replace_todo_with(&id, |current| match choice {
+        ConflictChoice::KeepLocal => Ok(current.cloned()),
+        ConflictChoice::KeepRemote => Ok((!conflict.remote.is_deleted).then(|| conflict.remote.clone())),
+        ConflictChoice::Merged(value) => {
+            let fields = value.as_object().ok_or("合并值必须是对象")?;
+            let mut merged = serde_json::to_value(current.unwrap_or(&conflict.local))
+                .map_err(|e| format!("序列化待办失败: {}", e))?;
+            if let Some(obj) = merged.as_object_mut() {
+                for (key, field_value) in fields {
+                    if key != "id" {
+                        obj.insert(key.clone(), field_value.clone());
+                    }
+                }
+            }
+            let merged: Todo = serde_json::from_value(merged)
+                .map_err(|e| format!("合并值无效: {}", e))?;
+            merged.validate().map_err(|e| format!("合并值无效: {}", e))?;
+            Ok(Some(merged))
+        }
+    })
+    .await?;
+
+    // 以远程版本作为新基线，下次同步时本地的选择会作为本地修改上传
+    let mut base = read_sync_base().await?;
+    base.items.insert(id, conflict.remote);
+    save_sync_base(&base).await?;
+
+    save_sync_conflicts(&conflicts).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::types::validate_todos;
+    use serde_json::json;
+
+    const BASE_TIME: &str = "2024-01-01T00:00:00.000Z";
+    const LOCAL_TIME: &str = "2024-01-02T00:00:00.000Z";
+    const NOW: &str = "2024-01-03T00:00:00.000Z";
+
+    fn todo(id: &str, text: &str) -> Todo {
+        serde_json::from_value(json!({
+            "id": id,
+            "text": text,
+            "completed": false,
+            "createdAt": BASE_TIME,
+            "lastUpdate": BASE_TIME,
+        }))
+        .unwrap()
+    }
+
+    fn base_of(items: &[Todo]) -> HashMap<String, Todo> {
+        items.iter().map(|item| (item.id.clone(), item.clone())).collect()
+    }
+
+    fn merge(local: &[Todo], remote: &[Todo], base: &HashMap<String, Todo>) -> MergeOutcome {
+        merge_todos(local, LOCAL_TIME, remote, base, &[], &HashSet::new(), NOW)
+    }
+
+    fn find<'a>(items: &'a [Todo], id: &str) -> Option<&'a Todo> {
+        items.iter().find(|item| item.id == id)
+    }
+
+    #[test]
+    fn only_local_changed_is_uploaded() {
+        let base = base_of(&[todo("a", "old")]);
+        let local = [todo("a", "new")];
+        let remote = [todo("a", "old")];
+
+        let outcome = merge(&local, &remote, &base);
+        assert_eq!(outcome.uploaded, 1);
+        assert!(!outcome.local_changed());
+        assert_eq!(find(&outcome.upload, "a").unwrap().text, "new");
+        assert_eq!(find(&outcome.local, "a").unwrap().text, "new");
+        assert_eq!(outcome.base["a"].text, "new");
+
+        let plan = plan_todo_sync(&local, &remote, &base, &outcome);
+        assert_eq!(plan.upload, ["a"]);
+        assert!(plan.download.is_empty() && plan.merge.is_empty());
+    }
+
+    #[test]
+    fn uploaded_items_keep_their_own_times() {
+        let base = base_of(&[todo("a", "old")]);
+        let mut edited = todo("a", "new");
+        edited.last_update = Some("2024-01-01T12:00:00.000Z".to_string());
+        let mut added = todo("b", "added");
+        added.last_update = None;
+
+        let outcome = merge(&[edited, added], &[todo("a", "old")], &base);
+        assert_eq!(find(&outcome.upload, "a").unwrap().last_update.as_deref(), Some("2024-01-01T12:00:00.000Z"));
+        // 没有自身时间的待办使用列表的更新时间
+        assert_eq!(find(&outcome.upload, "b").unwrap().last_update.as_deref(), Some(LOCAL_TIME));
+    }
+
+    #[test]
+    fn only_remote_changed_is_downloaded() {
+        let base = base_of(&[todo("a", "old")]);
+        let local = [todo("a", "old")];
+        let mut remote_item = todo("a", "new");
+        remote_item.completed = true;
+        let remote = [remote_item];
+
+        let outcome = merge(&local, &remote, &base);
+        assert_eq!(outcome.downloaded, 1);
+        assert!(outcome.upload.is_empty());
+        let merged = find(&outcome.local, "a").unwrap();
+        assert_eq!(merged.text, "new");
+        assert!(merged.completed);
+
+        let plan = plan_todo_sync(&local, &remote, &base, &outcome);
+        assert_eq!(plan.download, ["a"]);
+        assert!(plan.upload.is_empty());
+    }
+
+    #[test]
+    fn both_changed_different_fields_are_merged() {
+        let base = base_of(&[todo("a", "old")]);
+        let local = [todo("a", "new")];
+        let mut remote_item = todo("a", "old");
+        remote_item.completed = true;
+        let remote = [remote_item];
+
+        let outcome = merge(&local, &remote, &base);
+        assert_eq!(outcome.merged, 1);
+        assert!(outcome.conflicts.is_empty());
+        let merged = find(&outcome.local, "a").unwrap();
+        assert_eq!(merged.text, "new");
+        assert!(merged.completed);
+        assert_eq!(merged.last_update.as_deref(), Some(NOW));
+        assert_eq!(find(&outcome.upload, "a"), Some(merged));
+
+        let plan = plan_todo_sync(&local, &remote, &base, &outcome);
+        assert_eq!(plan.merge, ["a"]);
+    }
+
+    #[test]
+    fn both_changed_same_field_is_a_conflict() {
+        let base = base_of(&[todo("a", "old")]);
+        let local = [todo("a", "local")];
+        let remote = [todo("a", "remote")];
+
+        let outcome = merge(&local, &remote, &base);
+        assert_eq!(outcome.conflicts.len(), 1);
+        assert_eq!(outcome.conflicts[0].fields, ["text"]);
+        assert_eq!(outcome.conflicts[0].remote.text, "remote");
+        // 冲突解决前两端都保持不动，基线保持旧值
+        assert!(outcome.upload.is_empty());
+        assert_eq!(find(&outcome.local, "a").unwrap().text, "local");
+        assert_eq!(outcome.base["a"].text, "old");
+
+        // 下一次同步时冲突仍然保留
+        let again = merge_todos(&local, LOCAL_TIME, &remote, &outcome.base, &outcome.conflicts, &HashSet::new(), NOW);
+        assert_eq!(again.conflicts.len(), 1);
+        assert!(again.upload.is_empty());
+    }
+
+    #[test]
+    fn deleted_remotely_and_edited_locally_stays_deleted() {
+        let base = base_of(&[todo("a", "old")]);
+        let local = [todo("a", "new")];
+        let mut remote_item = todo("a", "old");
+        remote_item.is_deleted = true;
+        let remote = [remote_item];
+
+        let outcome = merge(&local, &remote, &base);
+        assert!(find(&outcome.local, "a").is_none());
+        // 本地的修改随删除一起写回远程
+        let uploaded = find(&outcome.upload, "a").unwrap();
+        assert!(uploaded.is_deleted);
+        assert_eq!(uploaded.text, "new");
+    }
+
+    #[test]
+    fn deleted_locally_and_edited_remotely_is_restored() {
+        let base = base_of(&[todo("a", "old")]);
+        let remote = [todo("a", "new")];
+
+        let outcome = merge(&[], &remote, &base);
+        assert_eq!(find(&outcome.local, "a").unwrap().text, "new");
+        assert!(outcome.upload.is_empty());
+
+        let plan = plan_todo_sync(&[], &remote, &base, &outcome);
+        assert_eq!(plan.restore_local, ["a"]);
+    }
+
+    #[test]
+    fn deleted_locally_and_unchanged_remotely_is_deleted_remotely() {
+        let base = base_of(&[todo("a", "old")]);
+        let remote = [todo("a", "old")];
+
+        let outcome = merge(&[], &remote, &base);
+        assert!(outcome.local.is_empty());
+        assert!(find(&outcome.upload, "a").unwrap().is_deleted);
+
+        let plan = plan_todo_sync(&[], &remote, &base, &outcome);
+        assert_eq!(plan.delete_remote, ["a"]);
+    }
+
+    #[test]
+    fn added_on_both_sides_keeps_both() {
+        let local = [todo("a", "local")];
+        let remote = [todo("b", "remote")];
+
+        let outcome = merge(&local, &remote, &HashMap::new());
+        let ids: Vec<&str> = outcome.local.iter().map(|item| item.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert_eq!(outcome.upload.len(), 1);
+        assert_eq!(outcome.upload[0].id, "a");
+        assert_eq!((outcome.uploaded, outcome.downloaded), (1, 1));
+        assert_eq!(outcome.base.len(), 2);
+    }
+
+    #[test]
+    fn child_added_under_remotely_deleted_parent_moves_to_top_level() {
+        let parent_id = "11111111-1111-4111-8111-111111111111";
+        let child_id = "22222222-2222-4222-8222-222222222222";
+        let parent = todo(parent_id, "parent");
+        let mut child = todo(child_id, "child");
+        child.parent_id = Some(parent_id.to_string());
+
+        // 本设备在 P 下新增子项，另一台设备删除了 P
+        let base = base_of(std::slice::from_ref(&parent));
+        let local = [parent.clone(), child];
+        let mut deleted_parent = parent;
+        deleted_parent.is_deleted = true;
+        let remote = [deleted_parent];
+
+        let outcome = merge(&local, &remote, &base);
+        assert!(find(&outcome.local, parent_id).is_none());
+        let merged_child = find(&outcome.local, child_id).unwrap();
+        assert_eq!(merged_child.parent_id, None);
+        assert_eq!(outcome.detached, 1);
+        assert!(outcome.local_changed());
+        assert_eq!(find(&outcome.upload, child_id).unwrap().parent_id, None);
+        assert_eq!(outcome.base[child_id].parent_id, None);
+        validate_todos(&outcome.local, &HashSet::new(), "todos.json").unwrap();
+
+        // 再次同步时不再有变化
+        let remote: Vec<Todo> = remote.iter().chain(&outcome.upload).cloned().collect();
+        let again = merge_todos(&outcome.local, LOCAL_TIME, &remote, &outcome.base, &[], &HashSet::new(), NOW);
+        assert_eq!(again.detached, 0);
+        assert!(!again.local_changed());
+    }
+
+    #[test]
+    fn child_with_archived_parent_is_kept() {
+        let parent_id = "11111111-1111-4111-8111-111111111111";
+        let mut child = todo("22222222-2222-4222-8222-222222222222", "child");
+        child.parent_id = Some(parent_id.to_string());
+
+        let archived = HashSet::from([parent_id.to_string()]);
+        let outcome = merge_todos(&[child], LOCAL_TIME, &[], &HashMap::new(), &[], &archived, NOW);
+        assert_eq!(outcome.detached, 0);
+        assert_eq!(outcome.local[0].parent_id.as_deref(), Some(parent_id));
+    }
+
+    #[test]
+    fn archived_locally_is_not_deleted_remotely() {
+        let base = base_of(&[todo("a", "old")]);
+        let remote = [todo("a", "old")];
+
+        // 归档后 a 不在列表中，只在归档中
+        let archived = HashSet::from(["a".to_string()]);
+        let outcome = merge_todos(&[], LOCAL_TIME, &remote, &base, &[], &archived, NOW);
+        assert!(outcome.upload.is_empty());
+        assert!(outcome.local.is_empty());
+        assert_eq!(outcome.base["a"].text, "old");
+
+        let plan = plan_todo_sync(&[], &remote, &base, &outcome);
+        assert!(plan.delete_remote.is_empty() && plan.download.is_empty());
+    }
+
+    #[test]
+    fn added_on_both_sides_with_same_id_takes_newer() {
+        let mut local_item = todo("a", "local");
+        local_item.last_update = Some(LOCAL_TIME.to_string());
+        let remote = [todo("a", "remote")];
+
+        let outcome = merge(&[local_item], &remote, &HashMap::new());
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(find(&outcome.local, "a").unwrap().text, "local");
+        assert_eq!(find(&outcome.upload, "a").unwrap().text, "local");
+    }
+}
@@ -1,34 +1,78 @@
 use tauri::{
-    menu::{Menu, MenuItem, PredefinedMenuItem},
+    menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu},
     tray::{TrayIconBuilder, TrayIconEvent},
-    Emitter, Manager,
+    AppHandle, Emitter, Manager, Wry,
 };
 
-/// 设置系统托盘
-pub fn setup_tray(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
+use crate::modules::app::{profile_list, switch_profile};
+
+/// 托盘图标的 id
+const TRAY_ID: &str = "main-tray";
+
+/// 配置方案菜单项 id 的前缀，后接方案名称
+const PROFILE_ITEM_PREFIX: &str = "profile:";
+
+/// 创建托盘菜单，配置方案子菜单中勾选当前使用的方案
+///
+/// 读取配置方案失败时子菜单为空，并发出 `profile-failed` 事件（内容为错误信息）。
+fn build_menu(app: &AppHandle) -> tauri::Result<Menu<Wry>> {
     // 创建菜单项
     let quit = MenuItem::with_id(app, "quit", "退出", true, None::<&str>)?;
     let settings = MenuItem::with_id(app, "settings", "设置", true, None::<&str>)?;
     let show = MenuItem::with_id(app, "show", "显示", true, None::<&str>)?;
     let hide = MenuItem::with_id(app, "hide", "隐藏", true, None::<&str>)?;
 
-    let menu = Menu::with_items(
+    let profiles = Submenu::with_items(app, "配置方案", true, &[])?;
+    match profile_list() {
+        Ok(list) => {
+            for name in &list.profiles {
+                let item = CheckMenuItem::with_id(
+                    app,
+                    format!("{}{}", PROFILE_ITEM_PREFIX, name),
+                    name,
+                    true,
+                    *name == list.active,
+                    None::<&str>,
+                )?;
+                profiles.append(&item)?;
+            }
+        }
+        Err(e) => {
+            let _ = app.emit("profile-failed", format!("读取配置方案失败: {}", e));
+        }
+    }
+
+    Menu::with_items(
         app,
         &[
             &show,
             &hide,
             &PredefinedMenuItem::separator(app)?,
             &settings,
+            &profiles,
             &PredefinedMenuItem::separator(app)?,
             &quit,
         ],
-    )?;
+    )
+}
+
+/// 配置方案变化后重建托盘菜单
+pub fn refresh_tray_menu(app: &AppHandle) -> tauri::Result<()> {
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        tray.set_menu(Some(build_menu(app)?))?;
+    }
+    Ok(())
+}
+
+/// 设置系统托盘
+pub fn setup_tray(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
+    let menu = build_menu(app.handle())?;
 
     // 直接使用原始字节数据创建图标
     let icon_bytes = include_bytes!("../../icons/icon.ico");
     let icon = tauri::image::Image::from_bytes(icon_bytes)?;
 
-    let _tray = TrayIconBuilder::with_id("main-tray")
+    let _tray = TrayIconBuilder::with_id(TRAY_ID)
         .icon(icon)
         .menu(&menu)
         .on_menu_event(move |app, event| match event.id.as_ref() {
@@ -39,7 +83,7 @@ pub fn setup_tray(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
                 let window = app.get_webview_window("main").unwrap();
                 window.show().unwrap();
                 window.set_focus().unwrap();
-                window.emit("open-settings", {}).unwrap();
+                window.emit("open-settings", ()).unwrap();
             }
             "show" => {
                 let window = app.get_webview_window("main").unwrap();
@@ -50,7 +94,21 @@ pub fn setup_tray(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
                 let window = app.get_webview_window("main").unwrap();
                 window.hide().unwrap();
             }
-            _ => {}
+            id => {
+                if let Some(name) = id.strip_prefix(PROFILE_ITEM_PREFIX) {
+                    let app = app.clone();
+                    let name = name.to_string();
+                    tauri::async_runtime::spawn(async move {
+                        if let Err(e) = switch_profile(app.clone(), name).await {
+                            let _ = app.emit("profile-failed", format!("切换配置方案失败: {}", e));
+                        }
+                        // 切换失败时恢复菜单的勾选状态
+                        if let Err(e) = refresh_tray_menu(&app) {
+                            let _ = app.emit("profile-failed", format!("更新托盘菜单失败: {}", e));
+                        }
+                    });
+                }
+            }
         })
         .on_tray_icon_event(|tray, event| {
             if let TrayIconEvent::Click {
@@ -1,4 +1,11 @@
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use chrono::{DateTime, SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+
+use crate::modules::crdt::TextCrdt;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WindowConfig {
@@ -7,3 +14,261 @@ pub struct WindowConfig {
     pub width: f64,
     pub height: f64,
 }
+
+/// 待办事项
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Todo {
+    pub id: String,
+    pub text: String,
+    #[serde(default)]
+    pub completed: bool,
+    pub created_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_deleted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_update: Option<String>,
+    /// `text` 的字符级合并副本，可见文本与 `text` 一致
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_crdt: Option<TextCrdt>,
+    /// 前端附加的其他字段，原样保留
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// todos.json 的内容
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TodoFile {
+    pub data: Vec<Todo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_update: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// 待办列表的增量变化，随 `todos-changed` 事件发出
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TodoDelta {
+    /// 新增或修改后的待办
+    pub upserted: Vec<Todo>,
+    /// 移出列表的待办 id（删除时包括级联的子项）
+    pub removed: Vec<String>,
+}
+
+/// 撤销历史中的一步修改：受影响的待办在修改前后的状态
+///
+/// 只在 `before` 中的待办为这一步移出列表的，只在 `after` 中的为这一步新增或移回列表的。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    /// 修改的类型（add、update、toggle、move、delete、restore、save）
+    pub action: String,
+    pub at: String,
+    pub before: Vec<Todo>,
+    pub after: Vec<Todo>,
+    /// 修改改变了列表顺序时，修改前后列表中所有待办的 id 顺序
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_before: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_after: Option<Vec<String>>,
+}
+
+/// 一次归档移入的待办
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveBatch {
+    pub id: String,
+    #[serde(default)]
+    pub archived_at: String,
+    pub todos: Vec<Todo>,
+}
+
+/// stage.json 的内容：按归档批次保存，先归档的在前
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedTodos {
+    #[serde(default)]
+    pub batches: Vec<ArchiveBatch>,
+}
+
+/// trash.json 的内容：本地逻辑删除的待办（isDeleted 为 true，lastUpdate 为删除时间）
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashFile {
+    pub todos: Vec<Todo>,
+}
+
+fn check_uuid(value: &str, field: &str) -> Result<(), String> {
+    uuid::Uuid::parse_str(value)
+        .map(|_| ())
+        .map_err(|e| format!("{} is not a valid UUID ({:?}): {}", field, value, e))
+}
+
+fn check_time(value: &str, field: &str) -> Result<(), String> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|_| ())
+        .map_err(|e| format!("{} is not a valid RFC 3339 timestamp ({:?}): {}", field, value, e))
+}
+
+/// 解析 RFC 3339 时间并统一为 UTC
+pub fn parse_utc(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok().map(|time| time.to_utc())
+}
+
+/// 格式化为毫秒精度的 UTC 时间，与远程 DATETIME(3) 列的精度一致
+pub fn format_utc(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// 生成新的随机 UUID（v4）
+pub fn new_id() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    uuid::Builder::from_random_bytes(bytes).into_uuid().to_string()
+}
+
+/// 两个时间字段是否表示同一时刻（按毫秒比较，忽略时区写法）；无法解析时按原字符串比较
+pub fn same_instant(a: Option<&str>, b: Option<&str>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => match (parse_utc(a), parse_utc(b)) {
+            (Some(a), Some(b)) => a.timestamp_millis() == b.timestamp_millis(),
+            _ => a == b,
+        },
+        _ => false,
+    }
+}
+
+impl Todo {
+    /// 校验 id、父项 id 和各时间字段的格式
+    pub fn validate(&self) -> Result<(), String> {
+        check_uuid(&self.id, "id")?;
+        check_time(&self.created_at, "createdAt")?;
+        if let Some(completed_at) = &self.completed_at {
+            check_time(completed_at, "completedAt")?;
+        }
+        if let Some(deadline) = &self.deadline {
+            check_time(deadline, "deadline")?;
+        }
+        if let Some(parent_id) = &self.parent_id {
+            check_uuid(parent_id, "parentId")?;
+            if parent_id == &self.id {
+                return Err("parentId refers to the todo itself".to_string());
+            }
+        }
+        if let Some(last_update) = &self.last_update {
+            check_time(last_update, "lastUpdate")?;
+        }
+        Ok(())
+    }
+}
+
+impl HistoryEntry {
+    pub fn new(action: &str, before: Vec<Todo>, after: Vec<Todo>) -> Self {
+        HistoryEntry {
+            action: action.to_string(),
+            at: format_utc(Utc::now()),
+            before,
+            after,
+            order_before: None,
+            order_after: None,
+        }
+    }
+
+    /// 同时记录修改前后的列表顺序
+    pub fn with_order(mut self, before: Vec<String>, after: Vec<String>) -> Self {
+        self.order_before = Some(before);
+        self.order_after = Some(after);
+        self
+    }
+
+    /// 这一步没有修改任何待办，也没有改变顺序
+    pub fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty() && self.order_before == self.order_after
+    }
+}
+
+/// 校验一组待办：逐项格式、id 唯一，以及父项引用必须存在于本组或 `known_ids` 中
+pub fn validate_todos(
+    todos: &[Todo],
+    known_ids: &HashSet<String>,
+    file_name: &str,
+) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for (index, todo) in todos.iter().enumerate() {
+        todo.validate()
+            .map_err(|e| format!("{} item {} (id {:?}): {}", file_name, index, todo.id, e))?;
+        if !ids.insert(todo.id.as_str()) {
+            return Err(format!("{} item {}: duplicate id {:?}", file_name, index, todo.id));
+        }
+    }
+
+    for (index, todo) in todos.iter().enumerate() {
+        if let Some(parent_id) = &todo.parent_id {
+            if !ids.contains(parent_id.as_str()) && !known_ids.contains(parent_id) {
+                return Err(format!(
+                    "{} item {} (id {:?}): parentId {:?} does not refer to an existing todo",
+                    file_name, index, todo.id, parent_id
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 把父项既不在本组也不在 `known_ids` 中的待办移到顶层，返回被移动的待办 id
+///
+/// 一端删除父项、另一端在其下新增或恢复子项时，合并结果中会出现这样的待办。
+pub fn detach_orphans(todos: &mut [Todo], known_ids: &HashSet<String>) -> HashSet<String> {
+    let ids: HashSet<String> = todos.iter().map(|todo| todo.id.clone()).collect();
+    let mut detached = HashSet::new();
+    for todo in todos {
+        if todo.parent_id.as_ref().is_some_and(|parent| !ids.contains(parent) && !known_ids.contains(parent)) {
+            todo.parent_id = None;
+            detached.insert(todo.id.clone());
+        }
+    }
+    detached
+}
+
+impl TodoFile {
+    /// 校验整个文件，`known_ids` 为其他位置（如归档）中存在的待办 id
+    pub fn validate(&self, known_ids: &HashSet<String>) -> Result<(), String> {
+        if let Some(last_update) = &self.last_update {
+            check_time(last_update, "lastUpdate").map_err(|e| format!("todos.json: {}", e))?;
+        }
+        validate_todos(&self.data, known_ids, "todos.json")
+    }
+}
+
+impl ArchivedTodos {
+    /// 所有批次中的待办
+    pub fn todos(&self) -> impl Iterator<Item = &Todo> {
+        self.batches.iter().flat_map(|batch| batch.todos.iter())
+    }
+
+    /// 校验归档文件，`known_ids` 为活动列表中存在的待办 id
+    pub fn validate(&self, known_ids: &HashSet<String>) -> Result<(), String> {
+        let mut batch_ids = HashSet::new();
+        for batch in &self.batches {
+            if !batch_ids.insert(batch.id.as_str()) {
+                return Err(format!("stage.json: duplicate batch id {:?}", batch.id));
+            }
+            if !batch.archived_at.is_empty() {
+                check_time(&batch.archived_at, "archivedAt")
+                    .map_err(|e| format!("stage.json batch {:?}: {}", batch.id, e))?;
+            }
+        }
+        let todos: Vec<Todo> = self.todos().cloned().collect();
+        validate_todos(&todos, known_ids, "stage.json")
+    }
+}
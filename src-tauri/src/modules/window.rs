@@ -1,3 +1,4 @@
+use crate::modules::migration::{load_versioned, to_versioned_value, FileKind};
 use crate::modules::types::WindowConfig;
 use std::fs;
 use tauri::Manager;
@@ -16,7 +17,7 @@ pub fn save_window_config(config: WindowConfig) -> Result<(), String> {
     }
 
     let config_path = config_dir.join("window.json");
-    let json = serde_json::to_string_pretty(&config)
+    let json = serde_json::to_string_pretty(&to_versioned_value(FileKind::Window, &config)?)
         .map_err(|e| format!("Failed to serialize config: {}", e))?;
 
     fs::write(&config_path, json).map_err(|e| format!("Failed to write config file: {}", e))?;
@@ -34,7 +35,7 @@ pub fn load_window_config() -> Result<WindowConfig, String> {
 
     let config_path = config_dir.join("window.json");
 
-    if !config_path.exists() {
+    let Some(content) = load_versioned(FileKind::Window, &config_path)? else {
         // Return default config if file doesn't exist - 70% screen height, 30% screen width
         return Ok(WindowConfig {
             x: 100.0,
@@ -42,13 +43,10 @@ pub fn load_window_config() -> Result<WindowConfig, String> {
             width: (1920.0 * 0.3),  // 30% of typical screen width
             height: (1080.0 * 0.7), // 70% of typical screen height
         });
-    }
-
-    let content = fs::read_to_string(&config_path)
-        .map_err(|e| format!("Failed to read config file: {}", e))?;
+    };
 
     let config: WindowConfig =
-        serde_json::from_str(&content).map_err(|e| format!("Failed to parse config: {}", e))?;
+        serde_json::from_value(content).map_err(|e| format!("Failed to parse config: {}", e))?;
 
     Ok(config)
 }
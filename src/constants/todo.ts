@@ -19,4 +19,5 @@ export const defaultAppSettings: AppSettings = {
   },
   archiveDays: 30, // 默认30天后归档
   autoSync: '0', // 默认不自动同步
+  trashRetentionDays: 0, // 默认不自动清除回收站
 }
@@ -1,12 +1,16 @@
 import type { LocaleKey } from '../constants/locale'
-import type { AppSettings, WindowConfig } from '../types/app'
+import type { UnlistenFn } from '@tauri-apps/api/event'
+import type { AppSettings, BundleManifest, ImportMode, ImportSummary, ProfileList, SettingsChanged, SettingsError, SettingsPatch, WindowConfig } from '../types/app'
 import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
+import { open, save } from '@tauri-apps/plugin-dialog'
 import { defineStore } from 'pinia'
 import { computed, ref } from 'vue'
 import { debounce } from 'xe-utils'
 import { defaultLocale } from '../constants/locale'
 import { defaultAppSettings } from '../constants/todo'
 import { useSyncStore } from './sync'
+import { useTodoStore } from './todo'
 
 // 全局通知类型
 export interface GlobalNotification {
@@ -23,6 +27,8 @@ export const useAppStore = defineStore('app', () => {
   const syncStore = useSyncStore()
   // 待办事项设置
   const appSettings = ref<AppSettings>({ ...defaultAppSettings })
+  // 配置方案
+  const profiles = ref<ProfileList>({ active: '', profiles: [] })
 
   // 全局通知状态
   const globalNotifications = ref<GlobalNotification[]>([])
@@ -34,30 +40,164 @@ export const useAppStore = defineStore('app', () => {
     border: appSettings.value.windowConfig.borderWidth > 0 ? `${appSettings.value.windowConfig.borderWidth}px solid ${appSettings.value.windowConfig.borderColor}` : 'none',
   }))
 
+  // 等待发送的设置修改，按 JSON merge patch 合并
+  let pendingPatch: SettingsPatch = {}
+  let settingsUnlisten: UnlistenFn | null = null
+  let profilesUnlisten: UnlistenFn | null = null
+  let profileFailedUnlisten: UnlistenFn | null = null
+
+  const mergePatch = (target: Record<string, unknown>, patch: Record<string, unknown>) => {
+    for (const [key, value] of Object.entries(patch)) {
+      const current = target[key]
+      if (value && typeof value === 'object' && !Array.isArray(value) && current && typeof current === 'object' && !Array.isArray(current))
+        mergePatch(current as Record<string, unknown>, value as Record<string, unknown>)
+      else
+        target[key] = value
+    }
+  }
+
+  const autoSyncAfterSave = async () => {
+    // 如果启用了自动同步，立即同步设置
+    if (syncStore.isAutoSyncEnabled && syncStore.isSyncAvailable) {
+      try {
+        await syncStore.startSync()
+      }
+      catch (error) {
+        console.error('设置保存后自动同步失败:', error)
+        // 不抛出错误，避免影响设置保存
+      }
+    }
+  }
+
+  const notifySettingsError = (err: unknown) => {
+    console.error('Failed to save todo settings:', err)
+    const settingsError = err as SettingsError
+    if (settingsError?.kind === 'invalid') {
+      const fields = settingsError.errors.map(e => `${e.field} ${e.message}`).join('; ')
+      showNotification('error', `设置无效，未保存: ${fields}`, 0)
+    }
+    else if (settingsError?.kind === 'storage') {
+      showNotification('error', `保存设置失败: ${settingsError.message}`)
+    }
+  }
+
   const saveAppSettings = async () => {
     try {
-      await invoke('save_app_settings', { settings: { ...appSettings.value, lastUpdate: new Date().toISOString() } })
-      // 如果启用了自动同步，立即同步设置
-      if (syncStore.isAutoSyncEnabled && syncStore.isSyncAvailable) {
-        try {
-          await syncStore.startSync()
-        }
-        catch (error) {
-          console.error('设置保存后自动同步失败:', error)
-          // 不抛出错误，避免影响设置保存
-        }
-      }
+      await invoke('save_app_settings', { settings: appSettings.value })
+      await autoSyncAfterSave()
     }
     catch (err) {
-      console.error('Failed to save todo settings:', err)
+      notifySettingsError(err)
       throw err
     }
   }
-  const debouncedSaveAppSettings = debounce(saveAppSettings, 1000)
+
+  // 发送等待中的修改，只有值改变的字段记录修改时间
+  const flushSettingsPatch = async () => {
+    const patch = pendingPatch
+    pendingPatch = {}
+    if (Object.keys(patch).length === 0)
+      return
+    try {
+      appSettings.value = await invoke('update_app_settings', { patch }) as AppSettings
+      await autoSyncAfterSave()
+    }
+    catch (err) {
+      notifySettingsError(err)
+      // 修改被拒绝，恢复为已保存的设置
+      await loadAppSettings(true)
+    }
+  }
+  const debouncedFlushSettingsPatch = debounce(flushSettingsPatch, 1000)
+
+  // 先在本地应用修改，稍后合并发送到后端
+  const patchAppSettings = (patch: SettingsPatch) => {
+    mergePatch(appSettings.value as unknown as Record<string, unknown>, structuredClone(patch))
+    mergePatch(pendingPatch, structuredClone(patch))
+    debouncedFlushSettingsPatch()
+  }
+
+  // 监听其他窗口或托盘修改设置
+  const listenSettingsEvents = async () => {
+    if (settingsUnlisten)
+      return
+
+    settingsUnlisten = await listen<SettingsChanged>('settings-changed', (event) => {
+      const settings = { ...event.payload.settings }
+      // 保留本窗口尚未发送的修改
+      mergePatch(settings as unknown as Record<string, unknown>, structuredClone(pendingPatch))
+      appSettings.value = settings
+    })
+    profilesUnlisten = await listen<ProfileList>('profiles-changed', (event) => {
+      profiles.value = event.payload
+    })
+    // 托盘切换方案失败或托盘菜单更新失败
+    profileFailedUnlisten = await listen<string>('profile-failed', (event) => {
+      showNotification('error', event.payload)
+    })
+  }
+
+  const loadProfiles = async () => {
+    try {
+      profiles.value = await invoke('list_profiles') as ProfileList
+    }
+    catch (err) {
+      console.error('加载配置方案失败:', err)
+    }
+  }
+
+  // 以当前设置新建配置方案
+  const createProfile = async (name: string) => {
+    await flushSettingsPatch()
+    profiles.value = await invoke('create_profile', { name }) as ProfileList
+  }
+
+  const switchProfile = async (name: string) => {
+    // 先保存当前方案尚未发送的修改
+    await flushSettingsPatch()
+    appSettings.value = await invoke('switch_profile', { name }) as AppSettings
+    await loadProfiles()
+  }
+
+  const deleteProfile = async (name: string) => {
+    profiles.value = await invoke('delete_profile', { name }) as ProfileList
+  }
+
+  // 导出待办、设置与配置方案到 .tonbak 文件，取消时返回 null
+  const exportBundle = async (includeCredentials: boolean, passphrase?: string) => {
+    await flushSettingsPatch()
+    const dateStr = new Date().toISOString().split('T')[0]
+    const path = await save({
+      defaultPath: `ton-${dateStr}.tonbak`,
+      filters: [{ name: 'Ton Backup', extensions: ['tonbak'] }],
+    })
+    if (!path)
+      return null
+    return await invoke('export_bundle', { path, includeCredentials, passphrase }) as BundleManifest
+  }
+
+  // 选择要导入的 .tonbak 文件并读取其清单，取消时返回 null
+  const pickBundle = async () => {
+    const path = await open({
+      multiple: false,
+      filters: [{ name: 'Ton Backup', extensions: ['tonbak'] }],
+    })
+    if (!path)
+      return null
+    const manifest = await invoke('inspect_bundle', { path }) as BundleManifest
+    return { path, manifest }
+  }
+
+  const importBundle = async (path: string, mode: ImportMode, passphrase?: string) => {
+    await flushSettingsPatch()
+    const summary = await invoke('import_bundle', { path, mode, passphrase }) as ImportSummary
+    await Promise.all([useTodoStore().loadTodos(), loadAppSettings(true), loadProfiles()])
+    return summary
+  }
+
   // 动作
   const toggleTransparency = () => {
-    appSettings.value.isTransparent = !appSettings.value.isTransparent
-    debouncedSaveAppSettings()
+    patchAppSettings({ isTransparent: !appSettings.value.isTransparent })
   }
 
   const toggleBorder = (show: boolean) => {
@@ -72,30 +212,28 @@ export const useAppStore = defineStore('app', () => {
     isSettingsOpen.value = false
   }
   const updateWindowConfig = (config: Partial<WindowConfig>) => {
-    appSettings.value.windowConfig = { ...appSettings.value.windowConfig, ...config }
-    debouncedSaveAppSettings()
+    patchAppSettings({ windowConfig: config })
   }
 
   const updateLocale = (newLocale?: LocaleKey) => {
-    appSettings.value.locale = newLocale || defaultLocale
-    debouncedSaveAppSettings()
+    patchAppSettings({ locale: newLocale || defaultLocale })
   }
 
-  const updateAppSettings = async (newSettings: Partial<AppSettings>) => {
-    appSettings.value = { ...appSettings.value, ...newSettings }
-    debouncedSaveAppSettings()
+  const updateAppSettings = async (newSettings: SettingsPatch) => {
+    patchAppSettings(newSettings)
   }
 
   const resetColorsToDefault = async () => {
-    appSettings.value.colors = { ...defaultAppSettings.colors }
-    appSettings.value.windowConfig.borderColor = defaultAppSettings.windowConfig.borderColor
-    debouncedSaveAppSettings()
+    patchAppSettings({
+      colors: { ...defaultAppSettings.colors },
+      windowConfig: { borderColor: defaultAppSettings.windowConfig.borderColor },
+    })
   }
 
-  const loadAppSettings = async () => {
+  async function loadAppSettings(force = false) {
     try {
       const settingsData = await invoke('load_app_settings') as AppSettings
-      if (settingsData && settingsData.lastUpdate && (!appSettings.value.lastUpdate || new Date(settingsData.lastUpdate) > new Date(appSettings.value.lastUpdate))) {
+      if (settingsData && (force || (settingsData.lastUpdate && (!appSettings.value.lastUpdate || new Date(settingsData.lastUpdate) > new Date(appSettings.value.lastUpdate))))) {
         appSettings.value = settingsData
       }
       isSettingsOpen.value = false
@@ -158,6 +296,7 @@ export const useAppStore = defineStore('app', () => {
     // 状态
     isSettingsOpen,
     appSettings,
+    profiles,
     windowStyle,
     globalNotifications,
     currentNotification,
@@ -172,6 +311,14 @@ export const useAppStore = defineStore('app', () => {
     resetColorsToDefault,
     saveAppSettings,
     loadAppSettings,
+    listenSettingsEvents,
+    loadProfiles,
+    createProfile,
+    switchProfile,
+    deleteProfile,
+    exportBundle,
+    pickBundle,
+    importBundle,
     // 通知方法
     showNotification,
     removeNotification,
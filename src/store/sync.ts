@@ -1,5 +1,7 @@
-import type { ConnectionStatus, DatabaseConfig, SyncResult, SyncStatus } from '../types/database'
+import type { UnlistenFn } from '@tauri-apps/api/event'
+import type { AutoSyncEvent, ConnectionStatus, DatabaseConfig, PendingOperation, SyncPlan, SyncResult, SyncStatus } from '../types/database'
 import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 import { defineStore } from 'pinia'
 import { computed, ref } from 'vue'
 import { useAppStore } from './app'
@@ -17,9 +19,11 @@ export const useSyncStore = defineStore('sync', () => {
   const databaseConfig = ref<DatabaseConfig | null>(null)
   const connectionStatus = ref<ConnectionStatus>('checking')
   const syncDebounceTime = ref(2000) // 2秒防抖
+  const pendingOperationCount = ref(0) // 离线队列中尚未同步的修改数
+  const failedOperations = ref<PendingOperation[]>([]) // 多次回放失败、等待重试或放弃的操作
 
-  // 自动同步相关状态
-  let autoSyncTimer: ReturnType<typeof window.setInterval> | null = null
+  // 自动同步相关状态（计时由 Rust 端调度，这里只记录状态）
+  let autoSyncUnlisteners: UnlistenFn[] = []
   const autoSyncInterval = ref<number>(0) // 自动同步间隔（毫秒）
   const isAutoSyncEnabled = ref(false)
   const nextAutoSyncTime = ref<string>('')
@@ -41,6 +45,43 @@ export const useSyncStore = defineStore('sync', () => {
   })
 
   // 方法
+  const refreshPendingOperations = async () => {
+    try {
+      const operations = await invoke('get_pending_operations') as PendingOperation[]
+      pendingOperationCount.value = operations.filter(operation => !operation.failed).length
+      failedOperations.value = operations.filter(operation => operation.failed)
+    }
+    catch (error) {
+      console.error('获取离线队列失败:', error)
+    }
+  }
+
+  // 放弃全部失败的操作
+  const discardFailedOperations = async () => {
+    try {
+      for (const operation of failedOperations.value) {
+        await invoke('discard_failed_operation', { seq: operation.seq })
+      }
+    }
+    catch (error) {
+      appStore.showNotification('error', `放弃失败的修改出错: ${error}`)
+    }
+    await refreshPendingOperations()
+  }
+
+  // 重试全部失败的操作，下次同步时按原顺序回放
+  const retryFailedOperations = async () => {
+    try {
+      for (const operation of failedOperations.value) {
+        await invoke('retry_failed_operation', { seq: operation.seq })
+      }
+    }
+    catch (error) {
+      appStore.showNotification('error', `重试失败的修改出错: ${error}`)
+    }
+    await refreshPendingOperations()
+  }
+
   const loadDatabaseConfig = async () => {
     try {
       const config = await invoke('load_database_config') as DatabaseConfig | null
@@ -73,6 +114,7 @@ export const useSyncStore = defineStore('sync', () => {
       syncStatus.value.isConnected = true
       syncStatus.value.error = undefined
       databaseConfig.value = config
+      await refreshPendingOperations()
       return true
     }
     catch (error) {
@@ -92,6 +134,20 @@ export const useSyncStore = defineStore('sync', () => {
     }
   }
 
+  // 预览同步计划，不修改本地与远程数据
+  const previewSync = async (): Promise<SyncPlan> => {
+    if (!syncStatus.value.isConnected) {
+      throw new Error('数据库未连接')
+    }
+
+    try {
+      return await invoke('preview_database_sync') as SyncPlan
+    }
+    catch (error) {
+      throw new Error(error instanceof Error ? error.message : String(error))
+    }
+  }
+
   const startSync = async (): Promise<SyncResult> => {
     if (!syncStatus.value.isConnected) {
       throw new Error('数据库未连接')
@@ -116,6 +172,7 @@ export const useSyncStore = defineStore('sync', () => {
     }
     finally {
       syncStatus.value.isSyncing = false
+      await refreshPendingOperations()
     }
   }
 
@@ -182,69 +239,65 @@ export const useSyncStore = defineStore('sync', () => {
     }
   }
 
-  // 启动自动同步
+  // 启动自动同步（实际同步由 Rust 端按 autoSync 设置执行，窗口隐藏时也会运行）
   function startAutoSync(interval: number) {
     if (interval <= 0) {
       return
     }
 
-    // 清除现有计时器
-    stopAutoSync()
-
     // 设置下次同步时间
     const nextTime = new Date(Date.now() + interval)
     nextAutoSyncTime.value = nextTime.toLocaleString('zh-CN')
 
-    // 启动计时器
-    autoSyncTimer = window.setInterval(async () => {
-      if (connectionStatus.value === 'connected' && !syncStatus.value.isSyncing) {
-        await performAutoSync()
-
-        // 更新下次同步时间
-        const nextTime = new Date(Date.now() + interval)
-        nextAutoSyncTime.value = nextTime.toLocaleString('zh-CN')
-      }
-    }, interval)
-
     appStore.showInfo(`自动同步已启动，间隔: ${formatAutoSyncInterval(interval)}`)
   }
 
   // 停止自动同步
   function stopAutoSync() {
-    if (autoSyncTimer !== null) {
-      clearInterval(autoSyncTimer)
-      autoSyncTimer = null
-    }
-
     nextAutoSyncTime.value = ''
   }
 
-  // 执行自动同步
-  async function performAutoSync() {
-    try {
-      // 重新建立连接以确保连接有效
-      const config = await loadDatabaseConfig()
-      if (!config) {
-        return
-      }
-
-      await connectDatabase(config)
-      await checkAndInitializeTables()
+  // 监听后台自动同步事件
+  async function listenAutoSyncEvents() {
+    if (autoSyncUnlisteners.length > 0) {
+      return
+    }
 
-      const result = await startSync()
+    autoSyncUnlisteners = await Promise.all([
+      listen<AutoSyncEvent>('sync-started', () => {
+        syncStatus.value.isSyncing = true
+        syncStatus.value.error = undefined
+      }),
+      listen<AutoSyncEvent>('sync-finished', async ({ payload }) => {
+        syncStatus.value.isSyncing = false
+        syncStatus.value.isConnected = true
+        syncStatus.value.lastSyncTime = new Date().toISOString()
+        connectionStatus.value = 'connected'
+        if (payload.nextSyncAt) {
+          nextAutoSyncTime.value = new Date(payload.nextSyncAt).toLocaleString('zh-CN')
+        }
 
-      if (result.success) {
         appStore.showSuccess('自动同步成功')
         // 重新加载本地数据
         await todoStore.loadTodos()
-      }
-      else {
-        appStore.showError(`自动同步失败: ${result.message}`)
-      }
-    }
-    catch (error) {
-      console.error('自动同步出错:', error)
-    }
+        await refreshPendingOperations()
+      }),
+      listen<AutoSyncEvent>('sync-failed', ({ payload }) => {
+        syncStatus.value.isSyncing = false
+        syncStatus.value.error = payload.error ?? '同步失败'
+        if (payload.nextSyncAt) {
+          nextAutoSyncTime.value = new Date(payload.nextSyncAt).toLocaleString('zh-CN')
+        }
+
+        appStore.showError(`自动同步失败（第 ${payload.failures} 次）: ${payload.error}`)
+        refreshPendingOperations()
+      }),
+      listen<AutoSyncEvent>('sync-skipped', ({ payload }) => {
+        if (payload.error) {
+          appStore.showInfo(payload.error)
+        }
+      }),
+    ])
   }
 
   // 初始化自动同步
@@ -274,6 +327,8 @@ export const useSyncStore = defineStore('sync', () => {
   async function initializeDatabaseConnection() {
     try {
       connectionStatus.value = 'checking'
+      await listenAutoSyncEvents()
+      await refreshPendingOperations()
 
       // 1. 检查是否有数据库配置
       const config = await loadDatabaseConfig()
@@ -333,11 +388,6 @@ export const useSyncStore = defineStore('sync', () => {
       }
       const interval = parseAutoSyncInterval(newAutoSync)
       if (interval > 0) {
-        if (autoSyncTimer !== null) {
-          clearInterval(autoSyncTimer)
-          autoSyncTimer = null
-        }
-
         nextAutoSyncTime.value = ''
         // 设置新的自动同步配置
         autoSyncInterval.value = interval
@@ -367,6 +417,8 @@ export const useSyncStore = defineStore('sync', () => {
     databaseConfig,
     connectionStatus,
     syncDebounceTime,
+    pendingOperationCount,
+    failedOperations,
     autoSyncInterval,
     isAutoSyncEnabled,
     nextAutoSyncTime,
@@ -376,11 +428,15 @@ export const useSyncStore = defineStore('sync', () => {
     lastSyncDisplay,
 
     // 方法
+    refreshPendingOperations,
+    discardFailedOperations,
+    retryFailedOperations,
     loadDatabaseConfig,
     testConnection,
     connectDatabase,
     checkAndInitializeTables,
     startSync,
+    previewSync,
     setSyncDebounceTime,
     clearSyncError,
     resetSyncStatus,
@@ -388,7 +444,7 @@ export const useSyncStore = defineStore('sync', () => {
     formatAutoSyncInterval,
     startAutoSync,
     stopAutoSync,
-    performAutoSync,
+    listenAutoSyncEvents,
     initializeAutoSync,
     initializeDatabaseConnection,
     handleConnectionStatusChange,
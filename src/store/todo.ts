@@ -1,14 +1,13 @@
-import type { ArchivedTodoData, TodoData, TodoItem } from '../types/todo'
+import type { UnlistenFn } from '@tauri-apps/api/event'
+import type { ArchiveBatch, ArchiveMatch, TodoData, TodoDelta, TodoItem } from '../types/todo'
 import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 import { save } from '@tauri-apps/plugin-dialog'
 import { writeFile } from '@tauri-apps/plugin-fs'
 import { ElMessage } from 'element-plus'
 import { defineStore } from 'pinia'
-import { v4 as uuidv4 } from 'uuid'
 import { computed, ref, watch } from 'vue'
 import { $confirm } from '../utils/message'
-import { timeUtils } from '../utils/time'
-import { useAppStore } from './app'
 import { useSyncStore } from './sync'
 
 export const useTodoStore = defineStore('todo', () => {
@@ -18,7 +17,6 @@ export const useTodoStore = defineStore('todo', () => {
     lastUpdate: new Date().toISOString(),
     source: 'manual',
   })
-  const appStore = useAppStore()
   const loading = ref(false)
   const error = ref<string | null>(null)
 
@@ -96,33 +94,41 @@ export const useTodoStore = defineStore('todo', () => {
     }
   }
 
-  // 添加待办事项
-  const addTodo = async (text: string, parentId?: string, deadline?: string) => {
-    const newTodo: TodoItem = {
-      id: uuidv4(),
-      text: text.trim(),
-      completed: false,
-      createdAt: new Date().toISOString(),
-      deadline,
-      parentId,
+  // 把后端返回的变化合并到列表：已有的待办原位替换，新的追加到末尾
+  const applyDelta = (delta: TodoDelta) => {
+    for (const todo of delta.upserted) {
+      const index = todos.value.data.findIndex(t => t.id === todo.id)
+      if (index !== -1)
+        todos.value.data[index] = todo
+      else
+        todos.value.data.push(todo)
+    }
+    if (delta.removed.length > 0) {
+      const removed = new Set(delta.removed)
+      todos.value.data = todos.value.data.filter(t => !removed.has(t.id))
     }
-
-    todos.value.data.push(newTodo)
     todos.value.lastUpdate = new Date().toISOString()
     todos.value.source = 'manual'
-    await saveTodos()
+  }
+
+  // 添加待办事项
+  const addTodo = async (text: string, parentId?: string, deadline?: string) => {
+    const newTodo = await invoke('add_todo', { text, parentId, deadline }) as TodoItem
+    applyDelta({ upserted: [newTodo], removed: [] })
     return newTodo
   }
 
-  // 更新待办事项
+  // 更新待办事项，值为 undefined 的字段会被删除
   const updateTodo = async (id: string, updates: Partial<TodoItem>) => {
-    const index = todos.value.data.findIndex(todo => todo.id === id)
-    if (index !== -1) {
-      todos.value.data[index] = { ...todos.value.data[index], ...updates }
-      todos.value.lastUpdate = new Date().toISOString()
-      todos.value.source = 'manual'
-      await saveTodos()
-    }
+    const patch = Object.fromEntries(Object.entries(updates).map(([key, value]) => [key, value ?? null]))
+    const todo = await invoke('update_todo', { id, patch }) as TodoItem
+    applyDelta({ upserted: [todo], removed: [] })
+  }
+
+  // 切换完成状态，withChildren 时所有子项设为同一状态
+  const setCompleted = async (id: string, withChildren: boolean) => {
+    const changed = await invoke('toggle_todo', { id, withChildren }) as TodoItem[]
+    applyDelta({ upserted: changed, removed: [] })
   }
 
   // 切换完成状态
@@ -131,7 +137,6 @@ export const useTodoStore = defineStore('todo', () => {
     if (!todo)
       return
 
-    const now = new Date().toISOString()
     const completed = !todo.completed
 
     // 如果是父项，需要根据子项状态决定操作逻辑
@@ -139,83 +144,26 @@ export const useTodoStore = defineStore('todo', () => {
     if (children.length > 0) {
       const allChildrenCompleted = children.every(child => child.completed)
 
-      if (completed) {
-        // 勾选父项（标记为完成）
-        if (!allChildrenCompleted) {
-          // 存在未完成的子项，提示是否完成所有子项
-          const confirmMessage = `确认将"${todo.text}"及其${children.length}个子项标记为完成？`
-          try {
-            await $confirm(confirmMessage)
-            // 用户点击"确定"，同时完成父项和所有子项
-            await updateTodo(id, {
-              completed,
-              completedAt: now,
-            })
-            for (const child of children) {
-              await updateTodo(child.id, {
-                completed: true,
-                completedAt: now,
-              })
-            }
-          }
-          catch {
-            // 用户点击"取消"，只更新父项为完成
-            await updateTodo(id, {
-              completed,
-              completedAt: now,
-            })
-          }
+      // 勾选时存在未完成的子项，或取消勾选时子项都已完成，提示是否同时修改所有子项
+      if (completed !== allChildrenCompleted) {
+        const confirmMessage = `确认将"${todo.text}"及其${children.length}个子项标记为${completed ? '完成' : '未完成'}？`
+        let withChildren = true
+        try {
+          await $confirm(confirmMessage)
         }
-        else {
-          // 所有子项都已完成，直接完成父项
-          await updateTodo(id, {
-            completed,
-            completedAt: now,
-          })
+        catch {
+          // 用户点击"取消"，只更新父项
+          withChildren = false
         }
+        await setCompleted(id, withChildren)
       }
       else {
-        // 取消勾选父项（标记为未完成）
-        if (allChildrenCompleted) {
-          // 所有子项都已完成，提示是否取消完成所有子项
-          const confirmMessage = `确认将"${todo.text}"及其${children.length}个子项标记为未完成？`
-          try {
-            await $confirm(confirmMessage)
-            // 用户点击"确定"，同时取消完成父项和所有子项
-            await updateTodo(id, {
-              completed,
-              completedAt: undefined,
-            })
-            for (const child of children) {
-              await updateTodo(child.id, {
-                completed: false,
-                completedAt: undefined,
-              })
-            }
-          }
-          catch {
-            // 用户点击"取消"，只取消完成父项
-            await updateTodo(id, {
-              completed,
-              completedAt: undefined,
-            })
-          }
-        }
-        else {
-          // 子项没有都选中，直接取消完成父项
-          await updateTodo(id, {
-            completed,
-            completedAt: undefined,
-          })
-        }
+        await setCompleted(id, false)
       }
     }
     else {
       // 普通项目直接切换
-      await updateTodo(id, {
-        completed,
-        completedAt: completed ? now : undefined,
-      })
+      await setCompleted(id, false)
 
       // 如果是子项且标记为完成，检查是否应该自动完成父项
       if (todo.parentId && completed) {
@@ -229,10 +177,7 @@ export const useTodoStore = defineStore('todo', () => {
             const shouldCompleteParent = await $confirm(confirmMessage)
 
             if (shouldCompleteParent) {
-              await updateTodo(parent.id, {
-                completed: true,
-                completedAt: now,
-              })
+              await setCompleted(parent.id, false)
             }
           }
         }
@@ -240,74 +185,113 @@ export const useTodoStore = defineStore('todo', () => {
     }
   }
 
-  // 删除待办事项
+  // 把待办移到 parentId 下（不传为顶层）的第 index 个位置
+  const moveTodo = async (id: string, parentId: string | undefined, index: number) => {
+    await invoke('move_todo', { id, parentId, index })
+    // 移动会改变列表顺序，重新加载
+    await loadTodos()
+  }
+
+  // 删除待办事项（连同所有后代项移入回收站）
   const deleteTodo = async (id: string) => {
     const todo = todos.value.data.find(t => t.id === id)
     if (!todo)
       return
 
-    // 如果是父项，需要确认是否同时删除子项
-    const children = todos.value.data.filter(t => t.parentId === id)
+    // 如果是父项，需要确认是否同时删除所有后代项
+    const collectDescendants = (parentId: string): TodoItem[] => {
+      return todos.value.data
+        .filter(t => t.parentId === parentId)
+        .flatMap(child => [child, ...collectDescendants(child.id)])
+    }
+    const children = collectDescendants(id)
     if (children.length > 0) {
       const confirmMessage = `确认删除"${todo.text}"及其${children.length}个子项？`
       await $confirm(confirmMessage)
-      // 删除所有子项
-      for (const child of children) {
-        const childIndex = todos.value.data.findIndex(t => t.id === child.id)
-        if (childIndex !== -1) {
-          todos.value.data.splice(childIndex, 1)
-        }
-      }
     }
 
-    // 删除主项
-    const index = todos.value.data.findIndex(t => t.id === id)
-    if (index !== -1) {
-      todos.value.data.splice(index, 1)
-      await saveTodos()
+    const deleted = await invoke('delete_todo', { id }) as boolean
+    if (deleted)
+      applyDelta({ upserted: [], removed: [id, ...children.map(child => child.id)] })
+  }
+
+  // 撤销最近一步修改，没有可撤销的修改时返回 false
+  const undo = async () => {
+    try {
+      const undone = await invoke('undo') as boolean
+      if (undone)
+        await loadTodos()
+      return undone
+    }
+    catch (err) {
+      ElMessage.warning(`无法撤销: ${err}`)
+      return false
     }
   }
 
-  // 设置数据来源
-  const setDataSource = (source: 'manual' | 'import' | 'sync') => {
-    todos.value.source = source
-    todos.value.lastUpdate = new Date().toISOString()
+  // 重做最近撤销的一步，没有可重做的修改时返回 false
+  const redo = async () => {
+    try {
+      const redone = await invoke('redo') as boolean
+      if (redone)
+        await loadTodos()
+      return redone
+    }
+    catch (err) {
+      ElMessage.warning(`无法重做: ${err}`)
+      return false
+    }
   }
 
-  // 归档已完成的待办事项
-  const archiveCompletedTodos = async (archiveDays: number = 7) => {
-    const completedTodos = todos.value.data.filter((todo) => {
-      return todo.completed
-        && todo.completedAt
-        && timeUtils.shouldArchive(todo.completedAt, archiveDays)
-    })
+  // 获取已删除的待办（未连接数据库时为本地回收站）
+  const getDeletedTodos = async () => {
+    return await invoke('get_deleted_todos') as TodoItem[]
+  }
 
-    if (completedTodos.length === 0)
-      return
+  // 恢复已删除的待办，withAncestors 为 true 时同时恢复被删除的父项
+  const restoreDeletedTodo = async (id: string, withAncestors = true) => {
+    const restored = await invoke(withAncestors ? 'restore_todo' : 'restore_todo_safe', { todoId: id }) as boolean
+    await loadTodos()
+    return restored
+  }
 
-    try {
-      // 加载现有归档数据
-      const existingArchived = await invoke('load_archived_todos') as ArchivedTodoData
-      const archivedData: ArchivedTodoData = {
-        todos: [...(existingArchived?.todos || []), ...completedTodos],
-        archivedAt: new Date().toISOString(),
-      }
+  // 列出归档批次，最近归档的在前
+  const listArchives = async () => {
+    return await invoke('list_archives') as ArchiveBatch[]
+  }
 
-      // 保存归档数据
-      await invoke('save_archived_todos', { archivedTodos: archivedData })
+  // 按内容搜索归档，查询为空时返回全部
+  const searchArchive = async (query: string) => {
+    return await invoke('search_archive', { query }) as ArchiveMatch[]
+  }
 
-      // 从当前待办事项中移除已归档的项目
-      const archivedIds = new Set(completedTodos.map(t => t.id))
-      todos.value.data = todos.value.data.filter(todo => !archivedIds.has(todo.id))
-      todos.value.lastUpdate = new Date().toISOString()
-      todos.value.source = 'manual'
+  // 把归档的待办连同其子项移回待办列表
+  const unarchiveTodo = async (id: string) => {
+    const restored = await invoke('unarchive_todo', { todoId: id }) as boolean
+    await loadTodos()
+    return restored
+  }
 
-      await saveTodos()
-    }
-    catch (err) {
-      console.error('归档失败:', err)
-      error.value = '归档失败'
-    }
+  // 监听后台归档事件，归档后重新加载待办
+  let archiveUnlisten: UnlistenFn | null = null
+  const listenArchiveEvents = async () => {
+    if (archiveUnlisten)
+      return
+
+    archiveUnlisten = await listen<number>('todos-archived', async () => {
+      await loadTodos()
+    })
+  }
+
+  // 监听其他窗口或后端对待办的增量修改
+  let changesUnlisten: UnlistenFn | null = null
+  const listenTodoEvents = async () => {
+    if (changesUnlisten)
+      return
+
+    changesUnlisten = await listen<TodoDelta>('todos-changed', (event) => {
+      applyDelta(event.payload)
+    })
   }
 
   // 清除归档历史
@@ -321,17 +305,6 @@ export const useTodoStore = defineStore('todo', () => {
     }
   }
 
-  // 监听数据变化并自动归档
-  let archiveTimeout: ReturnType<typeof setTimeout> | null = null
-  const scheduleArchiveCheck = (archiveDays: number = 7) => {
-    if (archiveTimeout)
-      clearTimeout(archiveTimeout)
-    archiveTimeout = setTimeout(() => {
-      archiveCompletedTodos(archiveDays)
-      archiveTimeout = null
-    }, 5000) // 5秒后检查归档
-  }
-
   // 导出待办数据
   const exportTodos = async () => {
     try {
@@ -459,7 +432,6 @@ export const useTodoStore = defineStore('todo', () => {
 
   // 监听待办事项变化
   watch(() => todos.value, () => {
-    scheduleArchiveCheck(appStore.appSettings.archiveDays)
     scheduleAutoSync()
   }, { deep: true })
 
@@ -475,13 +447,21 @@ export const useTodoStore = defineStore('todo', () => {
     addTodo,
     updateTodo,
     toggleTodo,
+    moveTodo,
     deleteTodo,
+    undo,
+    redo,
+    getDeletedTodos,
+    restoreDeletedTodo,
     loadTodos,
     saveTodos,
-    archiveCompletedTodos,
+    listArchives,
+    searchArchive,
+    unarchiveTodo,
+    listenArchiveEvents,
+    listenTodoEvents,
     clearArchivedTodos,
     exportTodos,
     importTodos,
-    setDataSource,
   }
 })
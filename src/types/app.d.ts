@@ -22,9 +22,55 @@ export interface AppSettings {
   windowConfig: WindowConfig
   // 自动同步周期设置
   autoSync?: string // 自动同步周期，格式如 "0", "1h", "15m" 等
+  // 回收站保留天数，同步时彻底删除更早删除的待办，0 表示不自动清除
+  trashRetentionDays?: number
   lastUpdate?: string
 }
+// 保存设置失败时后端返回的错误
+export interface SettingsFieldError {
+  field: string // 字段路径，如 colors.normal
+  message: string
+}
+export type SettingsError = { kind: 'invalid', errors: SettingsFieldError[] } | { kind: 'storage', message: string }
+// 修改设置时发送的 JSON merge patch，嵌套对象只需包含要修改的字段
+export type SettingsPatch = Partial<Omit<AppSettings, 'windowConfig' | 'colors' | 'lastUpdate'>> & {
+  windowConfig?: Partial<WindowConfig>
+  colors?: Partial<AppSettings['colors']>
+}
+// settings-changed 事件的内容
+export interface SettingsChanged {
+  keys: string[] // 值有变化的顶层字段
+  settings: AppSettings
+}
+// 配置方案列表（list_profiles 与 profiles-changed 事件）
+export interface ProfileList {
+  active: string // 当前使用的方案
+  profiles: string[]
+}
 export interface WindowPosition {
   x: number
   y: number
 }
+// 导出与导入（.tonbak）
+export type ImportMode = 'replace' | 'merge'
+export interface BundleEntry {
+  name: string
+  size: number
+  sha256: string
+}
+export interface BundleManifest {
+  format: string
+  formatVersion: number
+  createdAt: string
+  appVersion: string
+  entries: BundleEntry[]
+  credentials?: BundleEntry // 加密的数据库配置，只在导出时选择包含凭据才有
+}
+export interface ImportSummary {
+  todos: number // 新增或更新的待办数量
+  archived: number
+  trashed: number
+  settings: string[] // 值有变化的设置字段
+  profiles: number
+  credentials: boolean
+}
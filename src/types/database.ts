@@ -5,7 +5,11 @@ import type { AppSettings } from './app'
 // 重新导出AppSettings类型
 export type { AppSettings }
 
+// 远程数据库种类，sqlite 时 database 为数据库文件路径
+export type DatabaseKind = 'mysql' | 'postgres' | 'sqlite'
+
 export interface DatabaseConfig {
+  kind: DatabaseKind
   host: string
   port: number
   username: string
@@ -30,6 +34,61 @@ export interface SyncResult {
   }
 }
 
+// 后台自动同步事件（sync-started / sync-finished / sync-failed / sync-skipped）
+export interface AutoSyncEvent {
+  result: SyncResult | null
+  error: string | null
+  failures: number // 连续失败次数
+  nextSyncAt: string | null // 下次自动同步时间
+}
+
+// 离线队列中等待写入远程的操作
+export interface PendingOperation {
+  seq: number
+  queuedAt: string
+  type: 'upsertTodo' | 'deleteTodo' | 'restoreTodo' | 'uploadSettings'
+  attempts: number
+  lastError?: string
+  failed: boolean // 失败次数达到上限，不再自动回放
+}
+
+// 远程结构版本状态
+export interface RemoteSchemaStatus {
+  currentVersion: number
+  latestVersion: number
+  pendingVersions: number[]
+  compatible: boolean
+  error: string | null
+}
+
+// 一次同步对待办的处理计划（待办 id 列表）
+export interface TodoSyncPlan {
+  upload: string[]
+  download: string[]
+  merge: string[]
+  deleteRemote: string[]
+  deleteLocal: string[] // 远程已删除，将从本地移除
+  restoreRemote: string[]
+  restoreLocal: string[]
+  conflicts: string[]
+  purgeRemote: string[] // 超过回收站保留期限，将被彻底删除
+}
+
+// 同步预览（preview_database_sync），不修改任何数据
+export interface SyncPlan {
+  schema: RemoteSchemaStatus
+  pendingOperations: number
+  opsToUpload: number
+  opsToDownload: number
+  todos: TodoSyncPlan
+  // 设置逐字段合并，每个字段按各自的修改时间取较新的一端
+  settings: {
+    uploadFields: string[] // 本地较新、将写入远程的字段
+    downloadFields: string[] // 远程较新、将覆盖本地的字段
+  }
+  destructive: boolean // 会删除或覆盖本地数据，执行前需要确认
+}
+
 // 数据比较差异类型
 export interface DataDifference {
   type: 'missing_local' | 'missing_remote' | 'content_diff' | 'no_diff'
@@ -9,6 +9,7 @@ export interface TodoItem {
   deadline?: string // 截止时间 ISO 字符串
   parentId?: string // 父项ID，用于树形结构
   children?: TodoItem[] // 子项列表
+  textCrdt?: unknown // 文本的字符级合并副本，由 Rust 端维护，前端原样保留
 }
 
 export interface TodoData {
@@ -17,9 +18,26 @@ export interface TodoData {
   source: 'manual' | 'import' | 'sync'
 }
 
-export interface ArchivedTodoData {
+// todos-changed 事件和增量命令携带的变化
+export interface TodoDelta {
+  upserted: TodoItem[]
+  removed: string[]
+}
+
+export interface ArchiveBatch {
+  id: string
+  archivedAt: string
   todos: TodoItem[]
+}
+
+export interface ArchivedTodoData {
+  batches: ArchiveBatch[]
+}
+
+export interface ArchiveMatch {
+  batchId: string
   archivedAt: string
+  todo: TodoItem
 }
 
 export type TodoTimeStatus = 'normal' | 'warning' | 'urgent'
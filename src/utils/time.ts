@@ -13,14 +13,6 @@ export const timeUtils = {
     return 'normal'
   },
 
-  // 检查是否需要归档
-  shouldArchive: (completedAt: string, archiveDays: number): boolean => {
-    const completed = new Date(completedAt)
-    const now = new Date()
-    const diffDays = (now.getTime() - completed.getTime()) / (1000 * 60 * 60 * 24)
-    return diffDays >= archiveDays
-  },
-
   // 格式化时间显示
   formatTime: (isoString: string): string => {
     const date = new Date(isoString)